@@ -1,23 +1,53 @@
 mod color;
 mod parser;
+mod tokenizer;
+
+use html::{
+    dom::{Document, NodeId},
+    source::SourceSpan,
+};
 
 pub use color::Color;
 pub use parser::Parser;
+pub use tokenizer::{Token, Tokenizer};
 
 #[derive(PartialEq, Debug)]
 pub struct Stylesheet {
     pub rules: Vec<Rule>,
+    /// The problems found while parsing the stylesheet.
+    pub warnings: Vec<Warning>,
+}
+
+/// A problem found while parsing a stylesheet. Instead of failing, the parser recovers by dropping
+/// the invalid part, as the spec describes.
+///
+/// https://www.w3.org/TR/css-syntax-3/#error-handling
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Warning {
+    pub message: String,
+    /// Where the part of the stylesheet that was dropped because of the problem was parsed from.
+    pub span: SourceSpan,
 }
 
 #[derive(PartialEq, Debug)]
 pub struct Rule {
     pub selectors: Vec<Selector>,
     pub declarations: Vec<Declaration>,
+    /// Where the rule was parsed from, from its first selector to its closing `}`.
+    pub span: SourceSpan,
 }
 
 #[derive(PartialEq, Eq, Debug)]
 pub enum Selector {
     Simple(SimpleSelector),
+    /// A selector made of simple selectors joined by combinators, e.g. `div.note > p`. `subject`
+    /// is the rightmost simple selector, the one that the matched element must match, and
+    /// `combinators` holds the ones to its left from right to left, each with the combinator that
+    /// joins it to the selector on its right.
+    Complex {
+        subject: SimpleSelector,
+        combinators: Vec<(Combinator, SimpleSelector)>,
+    },
 }
 
 #[derive(PartialEq, Eq, Debug)]
@@ -25,12 +55,40 @@ pub struct SimpleSelector {
     pub tag_name: Option<String>,
     pub id: Option<String>,
     pub class: Vec<String>,
+    pub pseudo_classes: Vec<PseudoClass>,
+}
+
+/// https://www.w3.org/TR/selectors-4/#combinators
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Combinator {
+    /// `a b`
+    Descendant,
+    /// `a > b`
+    Child,
+    /// `a + b`
+    NextSibling,
+    /// `a ~ b`
+    SubsequentSibling,
+}
+
+/// The tree-structural pseudo-classes that don't take arguments.
+///
+/// https://www.w3.org/TR/selectors-4/#structural-pseudos
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum PseudoClass {
+    Root,
+    Empty,
+    FirstChild,
+    LastChild,
+    OnlyChild,
 }
 
 #[derive(PartialEq, Debug)]
 pub struct Declaration {
     pub name: String,
     pub value: Value,
+    /// Where the declaration was parsed from, from its name to its `;`.
+    pub span: SourceSpan,
 }
 
 #[derive(PartialEq, Clone, Debug)]
@@ -52,14 +110,63 @@ pub type Specificity = (usize, usize, usize);
 impl Selector {
     pub fn specificity(&self) -> Specificity {
         // http://www.w3.org/TR/selectors/#specificity
-        let Selector::Simple(ref simple) = *self;
-        let a = simple.id.iter().count();
-        let b = simple.class.len();
-        let c = simple.tag_name.iter().count();
+        match *self {
+            Selector::Simple(ref simple) => simple.specificity(),
+            Selector::Complex {
+                ref subject,
+                ref combinators,
+            } => combinators
+                .iter()
+                .map(|(_, simple)| simple.specificity())
+                .fold(subject.specificity(), |(a, b, c), (d, e, f)| {
+                    (a + d, b + e, c + f)
+                }),
+        }
+    }
+}
+
+impl SimpleSelector {
+    pub fn specificity(&self) -> Specificity {
+        let a = self.id.iter().count();
+        let b = self.class.len() + self.pseudo_classes.len();
+        let c = self.tag_name.iter().count();
         (a, b, c)
     }
 }
 
+impl Stylesheet {
+    /// Parse the contents of every `<style>` element in a document, in tree order, into one
+    /// stylesheet. The spans of its rules and declarations point into the HTML source.
+    pub fn from_style_elements(document: &Document) -> Stylesheet {
+        let mut stylesheet = Stylesheet {
+            rules: Vec::new(),
+            warnings: Vec::new(),
+        };
+        stylesheet.add_style_elements(document, document.root());
+        stylesheet
+    }
+
+    fn add_style_elements(&mut self, document: &Document, node: NodeId) {
+        match document.element(node) {
+            Some(elem) if elem.tag_name == "style" => {
+                let origin = document
+                    .first_child(node)
+                    .and_then(|text| document.span(text))
+                    .map(|span| span.start)
+                    .unwrap_or_default();
+                let stylesheet = Parser::parse_at(&document.text_content(node), origin);
+                self.rules.extend(stylesheet.rules);
+                self.warnings.extend(stylesheet.warnings);
+            }
+            _ => {
+                for child in document.children(node) {
+                    self.add_style_elements(document, child);
+                }
+            }
+        }
+    }
+}
+
 impl Value {
     /// Return the size of a length in px, or zero for non-lengths.
     pub fn to_px(&self) -> f32 {
@@ -1,217 +1,580 @@
-use super::{Color, Declaration, Rule, Selector, SimpleSelector, Stylesheet, Unit, Value};
+use std::{iter::Peekable, ops::Range};
 
+use html::source::{PositionTracker, SourcePosition};
+
+use super::{
+    tokenizer::{Token, Tokenizer},
+    Color, Combinator, Declaration, PseudoClass, Rule, Selector, SimpleSelector, Stylesheet, Unit,
+    Value, Warning,
+};
+
+/// A token, or a block or function along with the component values inside it.
+///
+/// https://www.w3.org/TR/css-syntax-3/#component-value
+#[derive(PartialEq, Clone, Debug)]
+enum ComponentValue {
+    Token(Token),
+    /// A `{}`, `[]` or `()` block. `open` is the token that started it.
+    Block {
+        open: Token,
+        values: Vec<Component>,
+    },
+    Function {
+        name: String,
+        arguments: Vec<Component>,
+    },
+}
+
+/// A component value, and the bytes of the input that it was parsed from.
+#[derive(PartialEq, Clone, Debug)]
+struct Component {
+    value: ComponentValue,
+    range: Range<usize>,
+}
+
+impl Component {
+    fn is(&self, token: &Token) -> bool {
+        matches!(&self.value, ComponentValue::Token(t) if t == token)
+    }
+}
+
+/// Parses CSS from the tokens of the tokenizer, following section 5 of CSS Syntax Module Level 3.
+///
+/// https://www.w3.org/TR/css-syntax-3/#parsing
 pub struct Parser {
-    pos: usize,
     input: String,
+    /// Every token of the input, with the bytes it was made from, ending with `Token::Eof`.
+    tokens: Vec<(Token, Range<usize>)>,
+    pos: usize,
+    positions: PositionTracker,
+    warnings: Vec<Warning>,
 }
 
 impl Parser {
+    fn new(source: &str, origin: SourcePosition) -> Parser {
+        let mut tokenizer = Tokenizer::new(source);
+        let mut tokens = Vec::new();
+        loop {
+            let token = tokenizer.next_token();
+            let eof = token == Token::Eof;
+            tokens.push((token, tokenizer.token_range()));
+            if eof {
+                break;
+            }
+        }
+        Parser {
+            input: source.to_owned(),
+            tokens,
+            pos: 0,
+            positions: PositionTracker::new(origin),
+            warnings: Vec::new(),
+        }
+    }
+
     /// Parse a whole CSS stylesheet.
     pub fn parse(source: &str) -> Stylesheet {
-        let mut parser = Parser {
-            pos: 0,
-            input: source.to_owned(),
-        };
+        Parser::parse_at(source, SourcePosition::default())
+    }
+
+    /// Parse a whole CSS stylesheet that starts at `origin` in its file, like the contents of a
+    /// `<style>` element. Invalid declarations, rules and at-rules are dropped, with a warning for
+    /// each in the stylesheet.
+    pub fn parse_at(source: &str, origin: SourcePosition) -> Stylesheet {
+        let mut parser = Parser::new(source, origin);
+        let rules = parser.consume_rules();
         Stylesheet {
-            rules: parser.parse_rules(),
+            rules,
+            warnings: parser.warnings,
         }
     }
 
-    /// Parse a list of rule sets, separated by optional whitespace.
-    fn parse_rules(&mut self) -> Vec<Rule> {
-        let mut rules = Vec::new();
-        loop {
-            self.consume_whitespace();
-            if self.eof() {
-                break;
-            }
-            rules.push(self.parse_rule());
+    /// Parse a comma-separated list of selectors on its own, e.g. the argument of
+    /// `query_selector`. If any of the selectors is invalid, the whole list is.
+    pub fn parse_selector_list(source: &str) -> Result<Vec<Selector>, Warning> {
+        let mut parser = Parser::new(source, SourcePosition::default());
+        let mut values = Vec::new();
+        while *parser.next_token() != Token::Eof {
+            values.push(parser.consume_component_value());
         }
-        rules
+        parse_selectors(&values).map_err(|message| {
+            parser.warn(message, 0..source.len());
+            parser.warnings.remove(0)
+        })
+    }
+
+    /// Report a problem that made the parser drop the bytes `range` of the input.
+    fn warn(&mut self, message: String, range: Range<usize>) {
+        let span = self.positions.span(&self.input, range.start, range.end);
+        self.warnings.push(Warning { message, span });
+    }
+
+    /// The next token, without consuming it.
+    fn next_token(&self) -> &Token {
+        &self.tokens[self.pos].0
     }
 
-    /// Parse a rule set: `<selectors> { <declarations> }`.
-    fn parse_rule(&mut self) -> Rule {
-        Rule {
-            selectors: self.parse_selectors(),
-            declarations: self.parse_declarations(),
+    /// Consume the next token. The end-of-file token is never consumed, so it can be seen any
+    /// number of times.
+    fn consume_token(&mut self) -> (Token, Range<usize>) {
+        let token = self.tokens[self.pos].clone();
+        if token.0 != Token::Eof {
+            self.pos += 1;
         }
+        token
     }
 
-    /// Parse a comma-separated list of selectors.
-    fn parse_selectors(&mut self) -> Vec<Selector> {
-        let mut selectors = Vec::new();
+    /// The byte after the last token that was consumed.
+    fn end(&self) -> usize {
+        self.pos
+            .checked_sub(1)
+            .map_or(0, |last| self.tokens[last].1.end)
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-list-of-rules
+    fn consume_rules(&mut self) -> Vec<Rule> {
+        let mut rules = Vec::new();
         loop {
-            selectors.push(Selector::Simple(self.parse_simple_selector()));
-            self.consume_whitespace();
-            match self.next_char() {
-                ',' => {
-                    self.consume_char();
-                    self.consume_whitespace();
+            match self.next_token() {
+                // <whitespace-token>: Do nothing.
+                // <CDO-token> <CDC-token>: If the top-level flag is set, do nothing.
+                Token::Whitespace | Token::Cdo | Token::Cdc => {
+                    self.consume_token();
+                }
+                Token::Eof => return rules,
+                // No at-rules are supported yet, so they're consumed and dropped.
+                Token::AtKeyword(name) => {
+                    let message = format!("unsupported at-rule @{}", name);
+                    let start = self.tokens[self.pos].1.start;
+                    self.consume_at_rule();
+                    let end = self.end();
+                    self.warn(message, start..end);
+                }
+                _ => {
+                    if let Some(rule) = self.consume_qualified_rule() {
+                        rules.push(rule);
+                    }
                 }
-                '{' => break,
-                c => panic!("Unexpected character {} in selector list", c),
             }
         }
-        // Return selectors with highest specificity first, for use in matching.
-        selectors.sort_by_key(|b| std::cmp::Reverse(b.specificity()));
-        selectors
     }
 
-    /// Parse one simple selector, e.g.: `type#id.class1.class2.class3`
-    fn parse_simple_selector(&mut self) -> SimpleSelector {
-        let mut selector = SimpleSelector {
-            tag_name: None,
-            id: None,
-            class: Vec::new(),
-        };
-        while !self.eof() {
-            match self.next_char() {
-                '#' => {
-                    self.consume_char();
-                    selector.id = Some(self.parse_identifier());
-                }
-                '.' => {
-                    self.consume_char();
-                    selector.class.push(self.parse_identifier());
+    /// https://www.w3.org/TR/css-syntax-3/#consume-at-rule
+    fn consume_at_rule(&mut self) {
+        self.consume_token();
+        loop {
+            match self.next_token() {
+                Token::Semicolon => {
+                    self.consume_token();
+                    return;
                 }
-                '*' => {
-                    // universal selector
-                    self.consume_char();
+                // This is a parse error. Return the at-rule.
+                Token::Eof => return,
+                // Consume a simple block and assign it to the at-rule's block. Return the at-rule.
+                Token::OpenCurly => {
+                    self.consume_component_value();
+                    return;
                 }
-                c if valid_identifier_char(c) => {
-                    selector.tag_name = Some(self.parse_identifier());
+                _ => {
+                    self.consume_component_value();
                 }
-                _ => break,
             }
         }
-        selector
     }
 
-    /// Parse a list of declarations enclosed in `{ ... }`.
-    fn parse_declarations(&mut self) -> Vec<Declaration> {
-        assert_eq!(self.consume_char(), '{');
-        let mut declarations = Vec::new();
+    /// Consume a rule set: `<selectors> { <declarations> }`.
+    ///
+    /// https://www.w3.org/TR/css-syntax-3/#consume-qualified-rule
+    fn consume_qualified_rule(&mut self) -> Option<Rule> {
+        let start = self.tokens[self.pos].1.start;
+        let mut prelude = Vec::new();
         loop {
-            self.consume_whitespace();
-            if self.next_char() == '}' {
-                self.consume_char();
-                break;
+            match self.next_token() {
+                // This is a parse error. Return nothing.
+                Token::Eof => {
+                    let end = self.end();
+                    self.warn(
+                        "unexpected end of input before a rule's block".into(),
+                        start..end,
+                    );
+                    return None;
+                }
+                Token::OpenCurly => break,
+                _ => prelude.push(self.consume_component_value()),
             }
-            declarations.push(self.parse_declaration());
         }
-        declarations
+        self.consume_token();
+        let block = self.consume_block_contents(&Token::CloseCurly);
+        let end = self.end();
+        // An invalid selector makes the whole rule invalid, so it's dropped along with its block.
+        let selectors = match parse_selectors(&prelude) {
+            Ok(selectors) => selectors,
+            Err(message) => {
+                self.warn(message, start..end);
+                return None;
+            }
+        };
+        Some(Rule {
+            selectors,
+            declarations: self.parse_declarations(&block),
+            span: self.positions.span(&self.input, start, end),
+        })
     }
 
-    /// Parse one `<property>: <value>;` declaration.
-    fn parse_declaration(&mut self) -> Declaration {
-        let property_name = self.parse_identifier();
-        self.consume_whitespace();
-        assert_eq!(self.consume_char(), ':');
-        self.consume_whitespace();
-        let value = self.parse_value();
-        self.consume_whitespace();
-        assert_eq!(self.consume_char(), ';');
-
-        Declaration {
-            name: property_name,
+    /// https://www.w3.org/TR/css-syntax-3/#consume-component-value
+    fn consume_component_value(&mut self) -> Component {
+        let (token, range) = self.consume_token();
+        let value = match token {
+            Token::OpenCurly => ComponentValue::Block {
+                values: self.consume_block_contents(&Token::CloseCurly),
+                open: token,
+            },
+            Token::OpenSquare => ComponentValue::Block {
+                values: self.consume_block_contents(&Token::CloseSquare),
+                open: token,
+            },
+            Token::OpenParen => ComponentValue::Block {
+                values: self.consume_block_contents(&Token::CloseParen),
+                open: token,
+            },
+            Token::Function(name) => ComponentValue::Function {
+                name,
+                arguments: self.consume_block_contents(&Token::CloseParen),
+            },
+            token => ComponentValue::Token(token),
+        };
+        Component {
             value,
+            range: range.start..self.end(),
         }
     }
 
-    // Methods for parsing values:
-
-    fn parse_value(&mut self) -> Value {
-        match self.next_char() {
-            '0'..='9' => self.parse_length(),
-            '#' => self.parse_color(),
-            _ => Value::Keyword(self.parse_identifier()),
+    /// Consume the component values of a block or function, up to and including the `close`
+    /// token that ends it.
+    ///
+    /// https://www.w3.org/TR/css-syntax-3/#consume-simple-block
+    fn consume_block_contents(&mut self, close: &Token) -> Vec<Component> {
+        let mut values = Vec::new();
+        loop {
+            match self.next_token() {
+                token if token == close => {
+                    self.consume_token();
+                    return values;
+                }
+                // This is a parse error. Return the block.
+                Token::Eof => return values,
+                _ => values.push(self.consume_component_value()),
+            }
         }
     }
 
-    fn parse_length(&mut self) -> Value {
-        Value::Length(self.parse_float(), self.parse_unit())
+    /// Parse the contents of a rule set's block. Invalid declarations are dropped up to the next
+    /// `;`.
+    ///
+    /// https://www.w3.org/TR/css-syntax-3/#consume-list-of-declarations
+    fn parse_declarations(&mut self, mut values: &[Component]) -> Vec<Declaration> {
+        let mut declarations = Vec::new();
+        while let Some(first) = values.first() {
+            // The declaration, at-rule or invalid content that `first` starts ends at the next
+            // semicolon, which is dropped with it.
+            let end = values
+                .iter()
+                .position(|value| value.is(&Token::Semicolon))
+                .unwrap_or(values.len());
+            match &first.value {
+                ComponentValue::Token(Token::Whitespace | Token::Semicolon) => {
+                    values = &values[1..];
+                    continue;
+                }
+                // At-rules aren't supported, so they're dropped along with their block.
+                ComponentValue::Token(Token::AtKeyword(name)) => {
+                    let end = values
+                        .iter()
+                        .position(|value| {
+                            matches!(
+                                value.value,
+                                ComponentValue::Block {
+                                    open: Token::OpenCurly,
+                                    ..
+                                }
+                            )
+                        })
+                        .map_or(end, |block| block.min(end));
+                    let range = first.range.start..values.get(end).unwrap_or(first).range.end;
+                    self.warn(format!("unsupported at-rule @{}", name), range);
+                    values = &values[(end + 1).min(values.len())..];
+                    continue;
+                }
+                // Anything but an identifier is a parse error, which `parse_declaration` reports so
+                // that everything up to the next semicolon is dropped.
+                _ => {}
+            }
+            let declaration = trim_whitespace(&values[..end]);
+            let range = first.range.start
+                ..values
+                    .get(end)
+                    .or(declaration.last())
+                    .unwrap_or(first)
+                    .range
+                    .end;
+            match parse_declaration(declaration) {
+                Ok((name, value)) => declarations.push(Declaration {
+                    name,
+                    value,
+                    span: self.positions.span(&self.input, range.start, range.end),
+                }),
+                Err(message) => self.warn(message, range),
+            }
+            values = &values[(end + 1).min(values.len())..];
+        }
+        declarations
     }
+}
 
-    fn parse_float(&mut self) -> f32 {
-        let s = self.consume_while(|c| matches!(c, '0'..='9' | '.'));
-        s.parse().unwrap()
+/// Parse one `<property>: <value>` declaration.
+///
+/// https://www.w3.org/TR/css-syntax-3/#consume-declaration
+fn parse_declaration(values: &[Component]) -> Result<(String, Value), String> {
+    let ComponentValue::Token(Token::Ident(name)) = &values[0].value else {
+        return Err("expected a property name".to_string());
+    };
+    // If the next input token is anything other than a <colon-token>, this is a parse error.
+    // Return nothing.
+    let value = match trim_whitespace(&values[1..]).split_first() {
+        Some((colon, value)) if colon.is(&Token::Colon) => trim_whitespace(value),
+        _ => return Err(format!("expected a colon after the property name {}", name)),
+    };
+    match parse_value(strip_important(value)) {
+        Ok(value) => Ok((name.clone(), value)),
+        Err(message) => Err(format!("invalid value for {}: {}", name, message)),
     }
+}
 
-    fn parse_unit(&mut self) -> Unit {
-        match &*self.parse_identifier().to_ascii_lowercase() {
-            "px" => Unit::Px,
-            _ => panic!("unrecognized unit"),
+/// Remove a trailing `!important` from a declaration's value. The cascade doesn't give important
+/// declarations precedence yet, so the flag isn't kept.
+///
+/// https://www.w3.org/TR/css-syntax-3/#consume-declaration
+fn strip_important(values: &[Component]) -> &[Component] {
+    // If the last two non-<whitespace-token>s in the declaration's value are a <delim-token> with the value "!" followed by an <ident-token> with a value that is an ASCII case-insensitive match for "important", remove them from the declaration's value and set the declaration's important flag to true.
+    let Some((last, rest)) = values.split_last() else {
+        return values;
+    };
+    let rest = trim_whitespace(rest);
+    match (&last.value, rest.split_last()) {
+        (ComponentValue::Token(Token::Ident(ident)), Some((bang, rest)))
+            if ident.eq_ignore_ascii_case("important") && bang.is(&Token::Delim('!')) =>
+        {
+            // While the last token in the declaration's value is a <whitespace-token>, remove that token.
+            trim_whitespace(rest)
         }
+        _ => values,
     }
+}
 
-    fn parse_color(&mut self) -> Value {
-        assert_eq!(self.consume_char(), '#');
-        Value::ColorValue(Color::new(
-            self.parse_hex_pair(),
-            self.parse_hex_pair(),
-            self.parse_hex_pair(),
-            255,
-        ))
+/// Remove the whitespace tokens from the start and end of `values`.
+fn trim_whitespace(mut values: &[Component]) -> &[Component] {
+    while values.first().is_some_and(|v| v.is(&Token::Whitespace)) {
+        values = &values[1..];
     }
-
-    /// Parse two hexadecimal digits.
-    fn parse_hex_pair(&mut self) -> u8 {
-        let s = &self.input[self.pos..self.pos + 2];
-        self.pos += 2;
-        u8::from_str_radix(s, 16).unwrap()
+    while values.last().is_some_and(|v| v.is(&Token::Whitespace)) {
+        values = &values[..values.len() - 1];
     }
+    values
+}
 
-    /// Parse a property name or keyword.
-    fn parse_identifier(&mut self) -> String {
-        self.consume_while(valid_identifier_char)
+/// Parse a comma-separated list of selectors.
+fn parse_selectors(values: &[Component]) -> Result<Vec<Selector>, String> {
+    let mut selectors = values
+        .split(|value| value.is(&Token::Comma))
+        .map(|values| {
+            let tokens = trim_whitespace(values)
+                .iter()
+                .map(|value| match &value.value {
+                    ComponentValue::Token(token) => Ok(token),
+                    value => Err(format!("unexpected {:?} in selector", value)),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            parse_selector(&mut tokens.into_iter().peekable())
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    // Return selectors with highest specificity first, for use in matching.
+    selectors.sort_by_key(|b| std::cmp::Reverse(b.specificity()));
+    Ok(selectors)
+}
+
+/// Parse one selector: simple selectors joined by combinators, e.g. `div.note > p`.
+fn parse_selector<'a>(
+    tokens: &mut Peekable<impl Iterator<Item = &'a Token>>,
+) -> Result<Selector, String> {
+    let mut simple_selectors = vec![parse_simple_selector(tokens)?];
+    let mut combinators = Vec::new();
+    loop {
+        let whitespace = tokens.next_if_eq(&&Token::Whitespace).is_some();
+        let combinator = match tokens.peek() {
+            None => break,
+            Some(Token::Delim('>')) => Combinator::Child,
+            Some(Token::Delim('+')) => Combinator::NextSibling,
+            Some(Token::Delim('~')) => Combinator::SubsequentSibling,
+            Some(_) if whitespace => Combinator::Descendant,
+            Some(token) => return Err(format!("unexpected {:?} in selector", token)),
+        };
+        if combinator != Combinator::Descendant {
+            tokens.next();
+            tokens.next_if_eq(&&Token::Whitespace);
+        }
+        combinators.push(combinator);
+        simple_selectors.push(parse_simple_selector(tokens)?);
     }
 
-    /// Consume and discard zero or more whitespace characters.
-    fn consume_whitespace(&mut self) {
-        self.consume_while(char::is_whitespace);
+    let subject = simple_selectors.pop().unwrap();
+    if combinators.is_empty() {
+        return Ok(Selector::Simple(subject));
     }
+    Ok(Selector::Complex {
+        subject,
+        combinators: combinators
+            .into_iter()
+            .zip(simple_selectors)
+            .rev()
+            .collect(),
+    })
+}
 
-    /// Consume characters until `test` returns false.
-    fn consume_while<F>(&mut self, test: F) -> String
-    where
-        F: Fn(char) -> bool,
-    {
-        let mut result = String::new();
-        while !self.eof() && test(self.next_char()) {
-            result.push(self.consume_char());
+/// Parse one simple selector, e.g.: `type#id.class1.class2.class3:first-child`
+fn parse_simple_selector<'a>(
+    tokens: &mut Peekable<impl Iterator<Item = &'a Token>>,
+) -> Result<SimpleSelector, String> {
+    let mut selector = SimpleSelector {
+        tag_name: None,
+        id: None,
+        class: Vec::new(),
+        pseudo_classes: Vec::new(),
+    };
+    // A type selector or the universal selector can only come first.
+    let universal = match tokens.peek() {
+        Some(Token::Ident(name)) => {
+            selector.tag_name = Some(name.clone());
+            tokens.next();
+            false
         }
-        result
+        Some(Token::Delim('*')) => tokens.next().is_some(),
+        _ => false,
+    };
+    loop {
+        match tokens.peek() {
+            // Only hashes that start with an identifier are ID selectors, so `#1` isn't one.
+            Some(Token::Hash { value, id: true }) => selector.id = Some(value.clone()),
+            Some(Token::Delim('.')) => {
+                tokens.next();
+                match tokens.peek() {
+                    Some(Token::Ident(class)) => selector.class.push(class.clone()),
+                    token => return Err(format!("expected a class name, found {:?}", token)),
+                }
+            }
+            Some(Token::Colon) => {
+                tokens.next();
+                match tokens.peek() {
+                    Some(Token::Ident(name)) => {
+                        selector.pseudo_classes.push(parse_pseudo_class(name)?)
+                    }
+                    token => return Err(format!("expected a pseudo-class, found {:?}", token)),
+                }
+            }
+            _ => break,
+        }
+        tokens.next();
     }
+    if !universal && selector.specificity() == (0, 0, 0) {
+        return Err(format!("expected a selector, found {:?}", tokens.peek()));
+    }
+    Ok(selector)
+}
 
-    /// Return the current character, and advance self.pos to the next character.
-    fn consume_char(&mut self) -> char {
-        let mut iter = self.input[self.pos..].char_indices();
-        let (_, cur_char) = iter.next().unwrap();
-        let (next_pos, _) = iter.next().unwrap_or((1, ' '));
-        self.pos += next_pos;
-        cur_char
+fn parse_pseudo_class(name: &str) -> Result<PseudoClass, String> {
+    match &*name.to_ascii_lowercase() {
+        "root" => Ok(PseudoClass::Root),
+        "empty" => Ok(PseudoClass::Empty),
+        "first-child" => Ok(PseudoClass::FirstChild),
+        "last-child" => Ok(PseudoClass::LastChild),
+        "only-child" => Ok(PseudoClass::OnlyChild),
+        name => Err(format!("unsupported pseudo-class :{}", name)),
     }
+}
+
+// Functions for parsing values:
 
-    /// Read the current character without consuming it.
-    fn next_char(&self) -> char {
-        self.input[self.pos..].chars().next().unwrap()
+fn parse_value(values: &[Component]) -> Result<Value, String> {
+    let [value] = values else {
+        return Err(match values {
+            [] => "missing value".to_string(),
+            _ => "expected a single value".to_string(),
+        });
+    };
+    match &value.value {
+        ComponentValue::Token(Token::Ident(keyword)) => Ok(Value::Keyword(keyword.clone())),
+        ComponentValue::Token(Token::Dimension { value, unit, .. }) => {
+            Ok(Value::Length(*value, parse_unit(unit)?))
+        }
+        // A length of zero can be written without a unit.
+        ComponentValue::Token(Token::Number { value, .. }) if *value == 0.0 => {
+            Ok(Value::Length(0.0, Unit::Px))
+        }
+        ComponentValue::Token(Token::Hash { value, .. }) => {
+            Ok(Value::ColorValue(parse_hex_color(value)?))
+        }
+        ComponentValue::Function { name, .. } => Err(format!("unsupported function {}()", name)),
+        value => Err(format!("unsupported value {:?}", value)),
     }
+}
 
-    /// Return true if all input is consumed.
-    fn eof(&self) -> bool {
-        self.pos >= self.input.len()
+fn parse_unit(unit: &str) -> Result<Unit, String> {
+    match &*unit.to_ascii_lowercase() {
+        "px" => Ok(Unit::Px),
+        _ => Err(format!("unrecognized unit {}", unit)),
     }
 }
 
-fn valid_identifier_char(c: char) -> bool {
-    matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_')
+/// Parse the digits of a hex color, e.g. `cc0000`, or `c00` with each digit doubled. The
+/// 4 and 8 digit forms have an alpha channel at the end.
+///
+/// https://www.w3.org/TR/css-color-4/#hex-notation
+fn parse_hex_color(hex: &str) -> Result<Color, String> {
+    let digits: Option<Vec<u8>> = hex
+        .chars()
+        .map(|c| c.to_digit(16).map(|d| d as u8))
+        .collect();
+    let channels: Vec<u8> = match digits {
+        Some(digits) if matches!(digits.len(), 3 | 4) => digits.iter().map(|d| d * 17).collect(),
+        Some(digits) if matches!(digits.len(), 6 | 8) => digits
+            .chunks(2)
+            .map(|pair| pair[0] * 16 + pair[1])
+            .collect(),
+        _ => return Err(format!("invalid hex color #{}", hex)),
+    };
+    Ok(Color::new(
+        channels[0],
+        channels[1],
+        channels[2],
+        channels.get(3).copied().unwrap_or(255),
+    ))
 }
 
 #[cfg(test)]
 mod tests {
+    use html::source::SourceSpan;
+
     use super::*;
 
+    fn at(line: usize, column: usize) -> SourcePosition {
+        SourcePosition { line, column }
+    }
+
+    fn span(start: (usize, usize), end: (usize, usize)) -> SourceSpan {
+        SourceSpan {
+            start: at(start.0, start.1),
+            end: at(end.0, end.1),
+        }
+    }
+
     #[test]
     fn one_line() {
         assert_eq!(
@@ -222,30 +585,37 @@ mod tests {
                         Selector::Simple(SimpleSelector {
                             tag_name: Some("h1".to_string()),
                             id: None,
-                            class: Vec::new()
+                            class: Vec::new(),
+                            pseudo_classes: Vec::new()
                         }),
                         Selector::Simple(SimpleSelector {
                             tag_name: Some("h2".to_string()),
                             id: None,
-                            class: Vec::new()
+                            class: Vec::new(),
+                            pseudo_classes: Vec::new()
                         }),
                         Selector::Simple(SimpleSelector {
                             tag_name: Some("h3".to_string()),
                             id: None,
-                            class: Vec::new()
+                            class: Vec::new(),
+                            pseudo_classes: Vec::new()
                         })
                     ],
                     declarations: vec![
                         Declaration {
                             name: "margin".to_string(),
-                            value: Value::Keyword("auto".to_string())
+                            value: Value::Keyword("auto".to_string()),
+                            span: span((1, 14), (1, 27))
                         },
                         Declaration {
                             name: "color".to_string(),
-                            value: Value::ColorValue(Color::new(204, 0, 0, 255))
+                            value: Value::ColorValue(Color::new(204, 0, 0, 255)),
+                            span: span((1, 28), (1, 43))
                         }
-                    ]
-                }]
+                    ],
+                    span: span((1, 1), (1, 45))
+                }],
+                warnings: Vec::new()
             }
         );
     }
@@ -263,32 +633,208 @@ mod tests {
                         selectors: vec![Selector::Simple(SimpleSelector {
                             tag_name: Some("div".to_string()),
                             id: None,
-                            class: vec!["note".to_string()]
+                            class: vec!["note".to_string()],
+                            pseudo_classes: Vec::new()
                         }),],
                         declarations: vec![
                             Declaration {
                                 name: "margin-bottom".to_string(),
-                                value: Value::Length(20.0, Unit::Px)
+                                value: Value::Length(20.0, Unit::Px),
+                                span: span((1, 12), (1, 32))
                             },
                             Declaration {
                                 name: "padding".to_string(),
-                                value: Value::Length(10.0, Unit::Px)
+                                value: Value::Length(10.0, Unit::Px),
+                                span: span((1, 33), (1, 47))
                             }
-                        ]
+                        ],
+                        span: span((1, 1), (1, 49))
                     },
                     Rule {
                         selectors: vec![Selector::Simple(SimpleSelector {
                             tag_name: None,
                             id: Some("answer".to_string()),
-                            class: vec![]
+                            class: vec![],
+                            pseudo_classes: Vec::new()
                         }),],
                         declarations: vec![Declaration {
                             name: "display".to_string(),
-                            value: Value::Keyword("none".to_string())
-                        },]
+                            value: Value::Keyword("none".to_string()),
+                            span: span((2, 28), (2, 42))
+                        },],
+                        span: span((2, 18), (2, 44))
                     }
-                ]
+                ],
+                warnings: Vec::new()
             }
         );
     }
+
+    #[test]
+    fn combinators_and_pseudo_classes() {
+        let simple =
+            |tag_name: &str, class: &[&str], pseudo_classes: Vec<PseudoClass>| SimpleSelector {
+                tag_name: Some(tag_name.to_string()),
+                id: None,
+                class: class.iter().map(|class| class.to_string()).collect(),
+                pseudo_classes,
+            };
+        assert_eq!(
+            Parser::parse_selector_list("div.note > p:first-child, ul li+li ~ a").unwrap(),
+            vec![
+                Selector::Complex {
+                    subject: simple("p", &[], vec![PseudoClass::FirstChild]),
+                    combinators: vec![(Combinator::Child, simple("div", &["note"], vec![]))]
+                },
+                Selector::Complex {
+                    subject: simple("a", &[], vec![]),
+                    combinators: vec![
+                        (Combinator::SubsequentSibling, simple("li", &[], vec![])),
+                        (Combinator::NextSibling, simple("li", &[], vec![])),
+                        (Combinator::Descendant, simple("ul", &[], vec![]))
+                    ]
+                }
+            ]
+        );
+        assert_eq!(
+            Parser::parse_selector_list("div.note > p:first-child").unwrap()[0].specificity(),
+            (0, 2, 2)
+        );
+        assert_eq!(
+            Parser::parse_selector_list("*").unwrap()[0],
+            Selector::Simple(SimpleSelector {
+                tag_name: None,
+                id: None,
+                class: Vec::new(),
+                pseudo_classes: Vec::new()
+            })
+        );
+    }
+
+    #[test]
+    fn tokens() {
+        let stylesheet = Parser::parse(
+            "@media print { a { display: none; } }
+             .a\\:b/* comment */{ color: #abc; margin: 0; padding: 1.5PX; border-color: #80808080 }",
+        );
+        // The at-rule is skipped along with its block.
+        assert_eq!(stylesheet.rules.len(), 1);
+        let rule = &stylesheet.rules[0];
+        // Escaped characters are part of the identifier.
+        assert_eq!(
+            rule.selectors,
+            vec![Selector::Simple(SimpleSelector {
+                tag_name: None,
+                id: None,
+                class: vec!["a:b".to_string()],
+                pseudo_classes: Vec::new()
+            })]
+        );
+        let values: Vec<_> = rule.declarations.iter().map(|d| &d.value).collect();
+        assert_eq!(
+            values,
+            vec![
+                &Value::ColorValue(Color::new(170, 187, 204, 255)),
+                &Value::Length(0.0, Unit::Px),
+                &Value::Length(1.5, Unit::Px),
+                &Value::ColorValue(Color::new(128, 128, 128, 128)),
+            ]
+        );
+        // The last declaration doesn't need a semicolon.
+        assert_eq!(rule.declarations[3].span, span((2, 74), (2, 97)));
+        assert_eq!(rule.span, span((2, 14), (2, 99)));
+    }
+
+    #[test]
+    fn error_recovery() {
+        let stylesheet = Parser::parse(
+            "a { color: rgb(1,2,3); margin: 1em; ! ; display: block }
+             p:hover, b { display: none; }
+             @media print { a { display: none; } } @import 'x.css';
+             c { @page { x: y } width: 10px; height }",
+        );
+        let declarations = |rule: &Rule| -> Vec<(String, Value)> {
+            rule.declarations
+                .iter()
+                .map(|d| (d.name.clone(), d.value.clone()))
+                .collect()
+        };
+        assert_eq!(stylesheet.rules.len(), 2);
+        assert_eq!(
+            declarations(&stylesheet.rules[0]),
+            vec![("display".to_string(), Value::Keyword("block".to_string()))]
+        );
+        assert_eq!(
+            declarations(&stylesheet.rules[1]),
+            vec![("width".to_string(), Value::Length(10.0, Unit::Px))]
+        );
+        let warnings: Vec<_> = stylesheet
+            .warnings
+            .iter()
+            .map(|w| (w.message.as_str(), w.span))
+            .collect();
+        assert_eq!(
+            warnings,
+            vec![
+                (
+                    "invalid value for color: unsupported function rgb()",
+                    span((1, 5), (1, 23))
+                ),
+                (
+                    "invalid value for margin: unrecognized unit em",
+                    span((1, 24), (1, 36))
+                ),
+                ("expected a property name", span((1, 37), (1, 40))),
+                // An invalid selector drops the whole rule.
+                ("unsupported pseudo-class :hover", span((2, 14), (2, 43))),
+                ("unsupported at-rule @media", span((3, 14), (3, 51))),
+                ("unsupported at-rule @import", span((3, 52), (3, 68))),
+                ("unsupported at-rule @page", span((4, 18), (4, 32))),
+                (
+                    "expected a colon after the property name height",
+                    span((4, 46), (4, 52))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn important() {
+        let stylesheet = Parser::parse("a { color: red !important; margin: 0 ! IMPORTANT }");
+        assert_eq!(stylesheet.warnings, Vec::new());
+        let declarations: Vec<_> = stylesheet.rules[0]
+            .declarations
+            .iter()
+            .map(|d| (d.name.as_str(), d.value.clone()))
+            .collect();
+        assert_eq!(
+            declarations,
+            vec![
+                ("color", Value::Keyword("red".to_string())),
+                ("margin", Value::Length(0.0, Unit::Px)),
+            ]
+        );
+    }
+
+    #[test]
+    fn style_elements() {
+        let document = html::parser::Parser::from_string(
+            "<style>a { color: #000000; }</style><p>x</p>\n<style>#b { margin: auto; }</style>",
+        )
+        .run();
+        let stylesheet = Stylesheet::from_style_elements(&document);
+        // Spans point into the HTML source.
+        let rules = [
+            Parser::parse_at("a { color: #000000; }", at(1, 8)),
+            Parser::parse_at("#b { margin: auto; }", at(2, 8)),
+        ];
+        assert_eq!(stylesheet.rules.len(), 2);
+        assert_eq!(stylesheet.rules[0], rules[0].rules[0]);
+        assert_eq!(stylesheet.rules[1], rules[1].rules[0]);
+        assert_eq!(stylesheet.rules[1].span, span((2, 8), (2, 28)));
+        assert_eq!(
+            stylesheet.rules[1].declarations[0].span,
+            span((2, 13), (2, 26))
+        );
+    }
 }
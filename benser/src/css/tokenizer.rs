@@ -0,0 +1,647 @@
+// This file is based on section 4 of CSS Syntax Module Level 3
+// The goal is to implement the tokenizer (4.3)
+// https://www.w3.org/TR/css-syntax-3/#tokenization
+
+use std::ops::Range;
+
+const REPLACEMENT_CHARACTER: char = '\u{FFFD}';
+
+/// The output of the tokenization step is a stream of zero or more of the following tokens.
+///
+/// https://www.w3.org/TR/css-syntax-3/#tokenization
+#[derive(PartialEq, Clone, Debug)]
+pub enum Token {
+    Ident(String),
+    /// The name of a function, which is followed by its arguments and a `)`.
+    Function(String),
+    AtKeyword(String),
+    /// A `#` followed by a name. `id` is set when the name would be a valid identifier, so the
+    /// hash can be used as an ID selector.
+    Hash {
+        value: String,
+        id: bool,
+    },
+    String(String),
+    /// A string with an unescaped line break in it.
+    BadString,
+    Url(String),
+    /// An unquoted URL with a character in it that isn't allowed there.
+    BadUrl,
+    Delim(char),
+    Number {
+        value: f32,
+        integer: bool,
+    },
+    Percentage(f32),
+    Dimension {
+        value: f32,
+        integer: bool,
+        unit: String,
+    },
+    Whitespace,
+    /// `<!--`
+    Cdo,
+    /// `-->`
+    Cdc,
+    Colon,
+    Semicolon,
+    Comma,
+    OpenSquare,
+    CloseSquare,
+    OpenParen,
+    CloseParen,
+    OpenCurly,
+    CloseCurly,
+    Eof,
+}
+
+/// Splits CSS source into tokens. Comments are skipped, and escapes are replaced by the
+/// characters they stand for.
+pub struct Tokenizer<'a> {
+    input: &'a str,
+    pos: usize,
+    /// The bytes of the input that the token last returned by `next_token` was made from.
+    token_range: Range<usize>,
+}
+
+impl<'a> Tokenizer<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Tokenizer {
+            input,
+            pos: 0,
+            token_range: 0..0,
+        }
+    }
+
+    /// The bytes of the input that the token last returned by `next_token` was made from.
+    pub fn token_range(&self) -> Range<usize> {
+        self.token_range.clone()
+    }
+
+    /// The next code point at the byte `pos` of the input, and the number of bytes it takes up.
+    ///
+    /// https://www.w3.org/TR/css-syntax-3/#input-preprocessing
+    fn code_point_at(&self, pos: usize) -> Option<(char, usize)> {
+        let c = self.input[pos..].chars().next()?;
+        Some(match c {
+            // Replace any U+000D CARRIAGE RETURN (CR) code points, U+000C FORM FEED (FF) code points, or pairs of U+000D CARRIAGE RETURN (CR) followed by U+000A LINE FEED (LF) in input by a single U+000A LINE FEED (LF) code point.
+            '\r' if self.input[pos + 1..].starts_with('\n') => ('\n', 2),
+            '\r' | '\x0C' => ('\n', 1),
+            // Replace any U+0000 NULL or surrogate code points in input with U+FFFD REPLACEMENT CHARACTER.
+            '\0' => (REPLACEMENT_CHARACTER, 1),
+            c => (c, c.len_utf8()),
+        })
+    }
+
+    /// Look at the code point `n` code points after the next one, without consuming anything.
+    fn peek(&self, n: usize) -> Option<char> {
+        let mut pos = self.pos;
+        for _ in 0..n {
+            pos += self.code_point_at(pos)?.1;
+        }
+        self.code_point_at(pos).map(|(c, _)| c)
+    }
+
+    fn consume(&mut self) -> Option<char> {
+        let (c, len) = self.code_point_at(self.pos)?;
+        self.pos += len;
+        Some(c)
+    }
+
+    /// Consume the next input token.
+    ///
+    /// https://www.w3.org/TR/css-syntax-3/#consume-token
+    pub fn next_token(&mut self) -> Token {
+        // Consume comments.
+        self.consume_comments();
+        let start = self.pos;
+        let token = self.consume_token();
+        self.token_range = start..self.pos;
+        token
+    }
+
+    fn consume_token(&mut self) -> Token {
+        let Some(c) = self.peek(0) else {
+            return Token::Eof;
+        };
+        match c {
+            c if is_whitespace(c) => {
+                while self.peek(0).is_some_and(is_whitespace) {
+                    self.consume();
+                }
+                Token::Whitespace
+            }
+            '"' | '\'' => {
+                self.consume();
+                self.consume_string(c)
+            }
+            '#' => {
+                self.consume();
+                // If the next input code point is an ident code point or the next two input code points are a valid escape, then:
+                if self.peek(0).is_some_and(is_ident_code_point) || self.starts_valid_escape(0) {
+                    // If the next 3 input code points would start an ident sequence, set the <hash-token>'s type flag to "id".
+                    let id = self.starts_ident_sequence(0);
+                    let value = self.consume_ident_sequence();
+                    Token::Hash { value, id }
+                } else {
+                    Token::Delim('#')
+                }
+            }
+            '(' => self.consume_and(Token::OpenParen),
+            ')' => self.consume_and(Token::CloseParen),
+            '+' | '.' if self.starts_number(0) => self.consume_numeric_token(),
+            ',' => self.consume_and(Token::Comma),
+            '-' => {
+                if self.starts_number(0) {
+                    self.consume_numeric_token()
+                } else if self.peek(1) == Some('-') && self.peek(2) == Some('>') {
+                    self.consume();
+                    self.consume();
+                    self.consume_and(Token::Cdc)
+                } else if self.starts_ident_sequence(0) {
+                    self.consume_ident_like_token()
+                } else {
+                    self.consume_and(Token::Delim('-'))
+                }
+            }
+            ':' => self.consume_and(Token::Colon),
+            ';' => self.consume_and(Token::Semicolon),
+            '<' if self.peek(1) == Some('!')
+                && self.peek(2) == Some('-')
+                && self.peek(3) == Some('-') =>
+            {
+                for _ in 0..3 {
+                    self.consume();
+                }
+                self.consume_and(Token::Cdo)
+            }
+            '@' => {
+                self.consume();
+                // If the next 3 input code points would start an ident sequence, consume an ident sequence, create an <at-keyword-token> with its value set to the returned value, and return it.
+                if self.starts_ident_sequence(0) {
+                    Token::AtKeyword(self.consume_ident_sequence())
+                } else {
+                    Token::Delim('@')
+                }
+            }
+            '[' => self.consume_and(Token::OpenSquare),
+            ']' => self.consume_and(Token::CloseSquare),
+            // If the input stream starts with a valid escape, reconsume the current input code point, consume an ident-like token, and return it. Otherwise, this is a parse error. Return a <delim-token> with its value set to the current input code point.
+            '\\' if self.starts_valid_escape(0) => self.consume_ident_like_token(),
+            '{' => self.consume_and(Token::OpenCurly),
+            '}' => self.consume_and(Token::CloseCurly),
+            c if c.is_ascii_digit() => self.consume_numeric_token(),
+            c if is_ident_start_code_point(c) => self.consume_ident_like_token(),
+            c => self.consume_and(Token::Delim(c)),
+        }
+    }
+
+    /// Consume the next code point, and return `token`.
+    fn consume_and(&mut self, token: Token) -> Token {
+        self.consume();
+        token
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-comment
+    fn consume_comments(&mut self) {
+        // If the next two input code point are U+002F SOLIDUS (/) followed by a U+002A ASTERISK (*), consume them and all following code points up to and including the first U+002A ASTERISK (*) followed by a U+002F SOLIDUS (/), or up to an EOF code point. Return to the start of this step.
+        while self.input[self.pos..].starts_with("/*") {
+            match self.input[self.pos + 2..].find("*/") {
+                Some(end) => self.pos += 2 + end + 2,
+                // If the preceding paragraph ended by consuming an EOF code point, this is a parse error.
+                None => self.pos = self.input.len(),
+            }
+        }
+    }
+
+    /// Do the two code points starting `n` code points ahead make a valid escape?
+    ///
+    /// https://www.w3.org/TR/css-syntax-3/#starts-with-a-valid-escape
+    fn starts_valid_escape(&self, n: usize) -> bool {
+        // If the first code point is not U+005C REVERSE SOLIDUS (\), return false. Otherwise, if the second code point is a newline, return false. Otherwise, return true.
+        self.peek(n) == Some('\\') && self.peek(n + 1).is_some_and(|c| c != '\n')
+    }
+
+    /// Would the three code points starting `n` code points ahead start an ident sequence?
+    ///
+    /// https://www.w3.org/TR/css-syntax-3/#would-start-an-identifier
+    fn starts_ident_sequence(&self, n: usize) -> bool {
+        match self.peek(n) {
+            Some('-') => {
+                self.peek(n + 1)
+                    .is_some_and(|c| is_ident_start_code_point(c) || c == '-')
+                    || self.starts_valid_escape(n + 1)
+            }
+            Some('\\') => self.starts_valid_escape(n),
+            Some(c) => is_ident_start_code_point(c),
+            None => false,
+        }
+    }
+
+    /// Would the three code points starting `n` code points ahead start a number?
+    ///
+    /// https://www.w3.org/TR/css-syntax-3/#starts-with-a-number
+    fn starts_number(&self, n: usize) -> bool {
+        let is_digit = |n| self.peek(n).is_some_and(|c: char| c.is_ascii_digit());
+        match self.peek(n) {
+            Some('+' | '-') => {
+                is_digit(n + 1) || (self.peek(n + 1) == Some('.') && is_digit(n + 2))
+            }
+            Some('.') => is_digit(n + 1),
+            _ => is_digit(n),
+        }
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-escaped-code-point
+    fn consume_escaped_code_point(&mut self) -> char {
+        match self.consume() {
+            Some(c) if c.is_ascii_hexdigit() => {
+                // Consume as many hex digits as possible, but no more than 5. Note that this means 1-6 hex digits have been consumed in total.
+                let mut code = c.to_digit(16).unwrap();
+                for _ in 0..5 {
+                    match self.peek(0).and_then(|c| c.to_digit(16)) {
+                        Some(digit) => {
+                            self.consume();
+                            code = code * 16 + digit;
+                        }
+                        None => break,
+                    }
+                }
+                // If the next input code point is whitespace, consume it as well.
+                if self.peek(0).is_some_and(is_whitespace) {
+                    self.consume();
+                }
+                // If this number is zero, or is for a surrogate, or is greater than the maximum allowed code point, return U+FFFD REPLACEMENT CHARACTER (�).
+                match code {
+                    0 => REPLACEMENT_CHARACTER,
+                    code => char::from_u32(code).unwrap_or(REPLACEMENT_CHARACTER),
+                }
+            }
+            Some(c) => c,
+            // This is a parse error. Return U+FFFD REPLACEMENT CHARACTER (�).
+            None => REPLACEMENT_CHARACTER,
+        }
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-name
+    fn consume_ident_sequence(&mut self) -> String {
+        let mut result = String::new();
+        loop {
+            match self.peek(0) {
+                Some(c) if is_ident_code_point(c) => {
+                    self.consume();
+                    result.push(c);
+                }
+                Some('\\') if self.starts_valid_escape(0) => {
+                    self.consume();
+                    result.push(self.consume_escaped_code_point());
+                }
+                _ => return result,
+            }
+        }
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-number
+    fn consume_number(&mut self) -> (f32, bool) {
+        let mut repr = String::new();
+        let mut integer = true;
+        let digits = |tokenizer: &mut Self, repr: &mut String| {
+            while let Some(c) = tokenizer.peek(0).filter(char::is_ascii_digit) {
+                tokenizer.consume();
+                repr.push(c);
+            }
+        };
+        // If the next input code point is U+002B PLUS SIGN (+) or U+002D HYPHEN-MINUS (-), consume it and append it to repr.
+        if let Some(c @ ('+' | '-')) = self.peek(0) {
+            self.consume();
+            repr.push(c);
+        }
+        digits(self, &mut repr);
+        // If the next 2 input code points are U+002E FULL STOP (.) followed by a digit, then consume them, append them to repr, and set type to "number".
+        if self.peek(0) == Some('.') && self.peek(1).is_some_and(|c| c.is_ascii_digit()) {
+            self.consume();
+            repr.push('.');
+            integer = false;
+            digits(self, &mut repr);
+        }
+        // If the next 2 or 3 input code points are U+0045 LATIN CAPITAL LETTER E (E) or U+0065 LATIN SMALL LETTER E (e), optionally followed by U+002D HYPHEN-MINUS (-) or U+002B PLUS SIGN (+), followed by a digit, then consume them, append them to repr, and set type to "number".
+        if let Some('e' | 'E') = self.peek(0) {
+            let sign = matches!(self.peek(1), Some('+' | '-'));
+            let digit = if sign { 2 } else { 1 };
+            if self.peek(digit).is_some_and(|c| c.is_ascii_digit()) {
+                repr.push('e');
+                self.consume();
+                if sign {
+                    repr.push(self.consume().unwrap());
+                }
+                integer = false;
+                digits(self, &mut repr);
+            }
+        }
+        (repr.parse().unwrap_or(0.0), integer)
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-numeric-token
+    fn consume_numeric_token(&mut self) -> Token {
+        let (value, integer) = self.consume_number();
+        // If the next 3 input code points would start an ident sequence, then create a <dimension-token> with the same value and type flag as number, and a unit set initially to the empty string. Consume an ident sequence. Set the <dimension-token>'s unit to the returned value.
+        if self.starts_ident_sequence(0) {
+            let unit = self.consume_ident_sequence();
+            Token::Dimension {
+                value,
+                integer,
+                unit,
+            }
+        } else if self.peek(0) == Some('%') {
+            self.consume_and(Token::Percentage(value))
+        } else {
+            Token::Number { value, integer }
+        }
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-ident-like-token
+    fn consume_ident_like_token(&mut self) -> Token {
+        let string = self.consume_ident_sequence();
+        if self.peek(0) != Some('(') {
+            return Token::Ident(string);
+        }
+        self.consume();
+        // If string's value is an ASCII case-insensitive match for "url", and the next input code point is U+0028 LEFT PARENTHESIS ((), consume it. While the next two input code points are whitespace, consume the next input code point.
+        if string.eq_ignore_ascii_case("url") {
+            while self.peek(0).is_some_and(is_whitespace) && self.peek(1).is_some_and(is_whitespace)
+            {
+                self.consume();
+            }
+            // If the next one or two input code points are U+0022 QUOTATION MARK ("), U+0027 APOSTROPHE ('), or whitespace followed by U+0022 QUOTATION MARK (") or U+0027 APOSTROPHE ('), then create a <function-token> with its value set to string and return it. Otherwise, consume a url token, and return it.
+            let quoted = match self.peek(0) {
+                Some('"' | '\'') => true,
+                Some(c) if is_whitespace(c) => matches!(self.peek(1), Some('"' | '\'')),
+                _ => false,
+            };
+            if !quoted {
+                return self.consume_url_token();
+            }
+        }
+        Token::Function(string)
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-string-token
+    fn consume_string(&mut self, ending: char) -> Token {
+        let mut string = String::new();
+        loop {
+            match self.peek(0) {
+                Some(c) if c == ending => return self.consume_and(Token::String(string)),
+                // This is a parse error. Return the <string-token>.
+                None => return Token::String(string),
+                // This is a parse error. Reconsume the current input code point, create a <bad-string-token>, and return it.
+                Some('\n') => return Token::BadString,
+                Some('\\') => {
+                    self.consume();
+                    match self.peek(0) {
+                        // If the next input code point is EOF, do nothing.
+                        None => {}
+                        // Otherwise, if the next input code point is a newline, consume it.
+                        Some('\n') => {
+                            self.consume();
+                        }
+                        // Otherwise, (the stream starts with a valid escape) consume an escaped code point and append the returned code point to the <string-token>'s value.
+                        Some(_) => string.push(self.consume_escaped_code_point()),
+                    }
+                }
+                Some(c) => {
+                    self.consume();
+                    string.push(c);
+                }
+            }
+        }
+    }
+
+    /// https://www.w3.org/TR/css-syntax-3/#consume-url-token
+    fn consume_url_token(&mut self) -> Token {
+        let mut url = String::new();
+        // Consume as much whitespace as possible.
+        while self.peek(0).is_some_and(is_whitespace) {
+            self.consume();
+        }
+        loop {
+            match self.consume() {
+                Some(')') => return Token::Url(url),
+                // This is a parse error. Return the <url-token>.
+                None => return Token::Url(url),
+                Some(c) if is_whitespace(c) => {
+                    while self.peek(0).is_some_and(is_whitespace) {
+                        self.consume();
+                    }
+                    // If the next input code point is U+0029 RIGHT PARENTHESIS ()) or EOF, consume it and return the <url-token> (if EOF was encountered, this is a parse error); otherwise, consume the remnants of a bad url, create a <bad-url-token>, and return it.
+                    return match self.peek(0) {
+                        Some(')') => self.consume_and(Token::Url(url)),
+                        None => Token::Url(url),
+                        Some(_) => self.consume_remnants_of_bad_url(),
+                    };
+                }
+                // This is a parse error. Consume the remnants of a bad url, create a <bad-url-token>, and return it.
+                Some('"' | '\'' | '(') => return self.consume_remnants_of_bad_url(),
+                Some(c) if is_non_printable(c) => return self.consume_remnants_of_bad_url(),
+                Some('\\') => {
+                    // If the stream starts with a valid escape, consume an escaped code point and append the returned code point to the <url-token>'s value. Otherwise, this is a parse error. Consume the remnants of a bad url, create a <bad-url-token>, and return it.
+                    if self.peek(0).is_some_and(|c| c != '\n') {
+                        url.push(self.consume_escaped_code_point());
+                    } else {
+                        return self.consume_remnants_of_bad_url();
+                    }
+                }
+                Some(c) => url.push(c),
+            }
+        }
+    }
+
+    /// Consume enough of the input to reach a recovery point where normal tokenizing can resume.
+    ///
+    /// https://www.w3.org/TR/css-syntax-3/#consume-remnants-of-bad-url
+    fn consume_remnants_of_bad_url(&mut self) -> Token {
+        loop {
+            match self.peek(0) {
+                Some(')') => return self.consume_and(Token::BadUrl),
+                None => return Token::BadUrl,
+                // This allows an escaped right parenthesis ("\)") to be encountered without ending the <bad-url-token>.
+                Some('\\') if self.starts_valid_escape(0) => {
+                    self.consume();
+                    self.consume_escaped_code_point();
+                }
+                Some(_) => {
+                    self.consume();
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for Tokenizer<'_> {
+    type Item = Token;
+
+    /// Return the next token, ending before the end-of-file token.
+    fn next(&mut self) -> Option<Token> {
+        match self.next_token() {
+            Token::Eof => None,
+            token => Some(token),
+        }
+    }
+}
+
+/// A newline, U+0009 CHARACTER TABULATION, or U+0020 SPACE.
+///
+/// https://www.w3.org/TR/css-syntax-3/#whitespace
+fn is_whitespace(c: char) -> bool {
+    matches!(c, '\n' | '\t' | ' ')
+}
+
+/// A letter, a non-ASCII code point, or U+005F LOW LINE (_).
+///
+/// https://www.w3.org/TR/css-syntax-3/#ident-start-code-point
+fn is_ident_start_code_point(c: char) -> bool {
+    c.is_ascii_alphabetic() || !c.is_ascii() || c == '_'
+}
+
+/// An ident-start code point, a digit, or U+002D HYPHEN-MINUS (-).
+///
+/// https://www.w3.org/TR/css-syntax-3/#ident-code-point
+fn is_ident_code_point(c: char) -> bool {
+    is_ident_start_code_point(c) || c.is_ascii_digit() || c == '-'
+}
+
+/// https://www.w3.org/TR/css-syntax-3/#non-printable-code-point
+fn is_non_printable(c: char) -> bool {
+    matches!(c, '\0'..='\x08' | '\x0B' | '\x0E'..='\x1F' | '\x7F')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokenize(input: &str) -> Vec<Token> {
+        Tokenizer::new(input).collect()
+    }
+
+    fn ident(s: &str) -> Token {
+        Token::Ident(s.to_string())
+    }
+
+    #[test]
+    fn rules() {
+        assert_eq!(
+            tokenize("a > .b, #c:hover{color:red;/* comment */}"),
+            vec![
+                ident("a"),
+                Token::Whitespace,
+                Token::Delim('>'),
+                Token::Whitespace,
+                Token::Delim('.'),
+                ident("b"),
+                Token::Comma,
+                Token::Whitespace,
+                Token::Hash {
+                    value: "c".to_string(),
+                    id: true
+                },
+                Token::Colon,
+                ident("hover"),
+                Token::OpenCurly,
+                ident("color"),
+                Token::Colon,
+                ident("red"),
+                Token::Semicolon,
+                Token::CloseCurly,
+            ]
+        );
+        assert_eq!(
+            tokenize("@media <!-- --> [x]"),
+            vec![
+                Token::AtKeyword("media".to_string()),
+                Token::Whitespace,
+                Token::Cdo,
+                Token::Whitespace,
+                Token::Cdc,
+                Token::Whitespace,
+                Token::OpenSquare,
+                ident("x"),
+                Token::CloseSquare,
+            ]
+        );
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(
+            tokenize("12 -3.5px +.5% 1e3 #12 -webkit-x"),
+            vec![
+                Token::Number {
+                    value: 12.0,
+                    integer: true
+                },
+                Token::Whitespace,
+                Token::Dimension {
+                    value: -3.5,
+                    integer: false,
+                    unit: "px".to_string()
+                },
+                Token::Whitespace,
+                Token::Percentage(0.5),
+                Token::Whitespace,
+                Token::Number {
+                    value: 1000.0,
+                    integer: false
+                },
+                Token::Whitespace,
+                // Hashes that don't start with an identifier can't be ID selectors.
+                Token::Hash {
+                    value: "12".to_string(),
+                    id: false
+                },
+                Token::Whitespace,
+                ident("-webkit-x"),
+            ]
+        );
+    }
+
+    #[test]
+    fn strings_urls_and_escapes() {
+        assert_eq!(
+            tokenize(
+                r#""a\"b" 'c\
+d' "e
+url( x.png ) url("y.png") url(a b) \31 0 \-x"#
+            ),
+            vec![
+                Token::String("a\"b".to_string()),
+                Token::Whitespace,
+                Token::String("cd".to_string()),
+                Token::Whitespace,
+                // An unescaped newline ends a string badly.
+                Token::BadString,
+                Token::Whitespace,
+                Token::Url("x.png".to_string()),
+                Token::Whitespace,
+                Token::Function("url".to_string()),
+                Token::String("y.png".to_string()),
+                Token::CloseParen,
+                Token::Whitespace,
+                Token::BadUrl,
+                Token::Whitespace,
+                ident("10"),
+                Token::Whitespace,
+                ident("-x"),
+            ]
+        );
+    }
+
+    #[test]
+    fn token_ranges() {
+        let mut tokenizer = Tokenizer::new("a /* b */ c\r\nd");
+        let mut ranges = Vec::new();
+        while tokenizer.next_token() != Token::Eof {
+            ranges.push(tokenizer.token_range());
+        }
+        // Comments belong to no token, and a CRLF pair is a single newline.
+        assert_eq!(ranges, vec![0..1, 1..2, 9..10, 10..11, 11..13, 13..14]);
+    }
+}
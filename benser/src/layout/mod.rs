@@ -22,6 +22,19 @@ pub enum Display {
     None,
 }
 
+/// How white space inside an element is handled.
+///
+/// https://www.w3.org/TR/css-text-3/#white-space-property
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum WhiteSpace {
+    Normal,
+    Pre,
+    Nowrap,
+    PreWrap,
+    BreakSpaces,
+    PreLine,
+}
+
 /// Transform a style tree into a layout tree.
 pub fn layout_tree<'a>(node: &'a StyledNode, mut containing_block: Dimensions) -> LayoutBox<'a> {
     // The layout algorithm expects the container height to start at 0.
@@ -44,6 +57,11 @@ fn build_layout_tree<'a>(style_node: &'a StyledNode) -> LayoutBox<'a> {
 
     // Create the descendant boxes.
     for child in &style_node.children {
+        // Whitespace between elements doesn't generate any boxes, unless `white-space` preserves
+        // it.
+        if child.is_collapsible_whitespace() {
+            continue;
+        }
         match child.display() {
             Display::Block => root.children.push(build_layout_tree(child)),
             Display::Inline => root
@@ -1,3 +1,4 @@
 pub mod css;
 pub mod layout;
+pub mod query;
 pub mod style;
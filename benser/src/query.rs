@@ -0,0 +1,131 @@
+use html::dom::{Document, NodeId};
+
+use crate::css::{Parser, Warning};
+use crate::style::matches;
+
+/// Find elements in a document with CSS selectors, using the same selector parsing and matching as
+/// the style engine.
+///
+/// This lives here rather than in the `html` crate because `html` doesn't know about CSS.
+///
+/// https://dom.spec.whatwg.org/#scope-match-a-selectors-string
+///
+/// Both methods return an error if `selectors` isn't a valid selector list, where the DOM would
+/// throw a `SyntaxError`.
+pub trait QuerySelector {
+    /// Return the first element among the descendants of `node`, in tree order, that matches
+    /// any of the comma-separated `selectors`.
+    fn query_selector(&self, node: NodeId, selectors: &str) -> Result<Option<NodeId>, Warning>;
+
+    /// Return every element among the descendants of `node`, in tree order, that matches any of
+    /// the comma-separated `selectors`.
+    fn query_selector_all(&self, node: NodeId, selectors: &str) -> Result<Vec<NodeId>, Warning>;
+}
+
+impl QuerySelector for Document {
+    fn query_selector(&self, node: NodeId, selectors: &str) -> Result<Option<NodeId>, Warning> {
+        let selectors = Parser::parse_selector_list(selectors)?;
+        Ok(self
+            .descendants(node)
+            .find(|&descendant| selectors.iter().any(|s| matches(self, descendant, s))))
+    }
+
+    fn query_selector_all(&self, node: NodeId, selectors: &str) -> Result<Vec<NodeId>, Warning> {
+        let selectors = Parser::parse_selector_list(selectors)?;
+        Ok(self
+            .descendants(node)
+            .filter(|&descendant| selectors.iter().any(|s| matches(self, descendant, s)))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Document {
+        html::parser::Parser::from_string(input).run()
+    }
+
+    fn texts(document: &Document, nodes: Vec<NodeId>) -> Vec<String> {
+        nodes
+            .into_iter()
+            .map(|node| document.text_content(node))
+            .collect()
+    }
+
+    #[test]
+    fn query_selector() {
+        let document = parse(
+            "<div class=note><p>a</p><p>b</p></div>\
+             <div><p>c</p></div>\
+             <section class=note><p>d</p></section>",
+        );
+        let root = document.root();
+        let found = document
+            .query_selector(root, "div.note > p:first-child")
+            .unwrap();
+        assert_eq!(
+            found.map(|node| document.text_content(node)).as_deref(),
+            Some("a")
+        );
+        assert_eq!(document.query_selector(root, "span").unwrap(), None);
+
+        assert_eq!(
+            texts(&document, document.query_selector_all(root, "p").unwrap()),
+            vec!["a", "b", "c", "d"]
+        );
+        assert_eq!(
+            texts(
+                &document,
+                document
+                    .query_selector_all(root, ".note p:last-child")
+                    .unwrap()
+            ),
+            vec!["b", "d"]
+        );
+        assert_eq!(
+            texts(
+                &document,
+                document
+                    .query_selector_all(root, "p + p, section p")
+                    .unwrap()
+            ),
+            vec!["b", "d"]
+        );
+        assert_eq!(
+            texts(
+                &document,
+                document.query_selector_all(root, "div ~ * > p").unwrap()
+            ),
+            vec!["c", "d"]
+        );
+    }
+
+    #[test]
+    fn query_selector_is_scoped_to_descendants() {
+        let document = parse("<div><p>a</p></div><p>b</p>");
+        let div = document
+            .query_selector(document.root(), "div")
+            .unwrap()
+            .unwrap();
+        // Only descendants of the node are returned, but the selector is matched against the
+        // whole document.
+        assert_eq!(
+            texts(
+                &document,
+                document.query_selector_all(div, "body p").unwrap()
+            ),
+            vec!["a"]
+        );
+        assert_eq!(document.query_selector(div, "div").unwrap(), None);
+    }
+
+    #[test]
+    fn invalid_selectors() {
+        let document = parse("<p>a</p>");
+        let root = document.root();
+        assert!(document.query_selector(root, "p >").is_err());
+        assert!(document.query_selector_all(root, "p, ").is_err());
+    }
+}
@@ -1,16 +1,24 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 
-use crate::css::{Rule, Selector, SimpleSelector, Specificity, Stylesheet, Value};
-use crate::layout::Display;
-use html::dom::{ElementData, Node, NodeType};
+use crate::css::{
+    Combinator, PseudoClass, Rule, Selector, SimpleSelector, Specificity, Stylesheet, Value,
+};
+use crate::layout::{Display, WhiteSpace};
+use html::dom::{Document, ElementData, Namespace, NodeId, NodeType, QuirksMode};
 
 /// Map from CSS property names to values.
 type PropertyMap = HashMap<String, Value>;
 
+/// Properties that an element gets from its parent when no rule sets them.
+///
+/// https://www.w3.org/TR/css-cascade-4/#inheriting
+const INHERITED_PROPERTIES: &[&str] = &["white-space"];
+
 /// A node with associated style data.
 pub struct StyledNode {
-    node: Arc<Node>, // pointer to a DOM node
+    document: Arc<Document>,
+    node: NodeId, // pointer to a DOM node in `document`
     specified_values: PropertyMap,
     pub children: Vec<StyledNode>,
 }
@@ -33,6 +41,38 @@ impl StyledNode {
         }
     }
 
+    // The value of the `white-space` property (defaults to normal).
+    pub fn white_space(&self) -> WhiteSpace {
+        match self.value("white-space") {
+            Some(Value::Keyword(s)) => match &*s {
+                "pre" => WhiteSpace::Pre,
+                "nowrap" => WhiteSpace::Nowrap,
+                "pre-wrap" => WhiteSpace::PreWrap,
+                "break-spaces" => WhiteSpace::BreakSpaces,
+                "pre-line" => WhiteSpace::PreLine,
+                _ => WhiteSpace::Normal,
+            },
+            _ => WhiteSpace::Normal,
+        }
+    }
+
+    /// Is this a text node made up entirely of white space that collapses away? Spaces and tabs
+    /// collapse unless `white-space` preserves them, and so do line breaks, except with
+    /// `pre-line`.
+    ///
+    /// https://www.w3.org/TR/css-text-3/#white-space-phase-1
+    pub fn is_collapsible_whitespace(&self) -> bool {
+        let NodeType::Text(text) = self.document.node_type(self.node) else {
+            return false;
+        };
+        let collapsible: &[char] = match self.white_space() {
+            WhiteSpace::Normal | WhiteSpace::Nowrap => &[' ', '\t', '\n'],
+            WhiteSpace::PreLine => &[' ', '\t'],
+            WhiteSpace::Pre | WhiteSpace::PreWrap | WhiteSpace::BreakSpaces => &[],
+        };
+        text.chars().all(|c| collapsible.contains(&c))
+    }
+
     /// Return the specified value of property `name`, or property `fallback_name` if that doesn't
     /// exist. or value `default` if neither does.
     pub fn lookup(&self, name: &str, fallback_name: &str, default: &Value) -> Value {
@@ -43,47 +83,146 @@ impl StyledNode {
 
 type MatchedRule<'a> = (Specificity, &'a Rule);
 
-// If `rule` matches `elem`, return a `MatchedRule`. Otherwise return `None`.
-fn match_rule<'a>(elem: &ElementData, rule: &'a Rule) -> Option<MatchedRule<'a>> {
+// If `rule` matches `node`, return a `MatchedRule`. Otherwise return `None`.
+fn match_rule<'a>(document: &Document, node: NodeId, rule: &'a Rule) -> Option<MatchedRule<'a>> {
     // Find the first (highest-specificity) matching selector.
     rule.selectors
         .iter()
-        .find(|selector| matches(elem, *selector))
+        .find(|selector| matches(document, node, selector))
         .map(|selector| (selector.specificity(), rule))
 }
 
 // Find all CSS rules that match the given element.
-fn matching_rules<'a>(elem: &ElementData, stylesheet: &'a Stylesheet) -> Vec<MatchedRule<'a>> {
+fn matching_rules<'a>(
+    document: &Document,
+    node: NodeId,
+    stylesheet: &'a Stylesheet,
+) -> Vec<MatchedRule<'a>> {
     stylesheet
         .rules
         .iter()
-        .filter_map(|rule| match_rule(elem, rule))
+        .filter_map(|rule| match_rule(document, node, rule))
         .collect()
 }
 
-fn matches(elem: &ElementData, selector: &Selector) -> bool {
+/// Does the element `node` match `selector`? Combinators are matched by walking up and sideways
+/// from `node`, so the selector can depend on anything in the document.
+pub fn matches(document: &Document, node: NodeId, selector: &Selector) -> bool {
     match *selector {
-        Selector::Simple(ref simple_selector) => matches_simple_selector(elem, simple_selector),
+        Selector::Simple(ref simple_selector) => {
+            matches_simple_selector(document, node, simple_selector)
+        }
+        Selector::Complex {
+            ref subject,
+            ref combinators,
+        } => {
+            matches_simple_selector(document, node, subject)
+                && matches_combinators(document, node, combinators)
+        }
+    }
+}
+
+// `node` matched the selector to the right of `combinators`. Check that the rest of the selector
+// matches, trying every candidate element so that e.g. `a b > c` finds the right `b`.
+fn matches_combinators(
+    document: &Document,
+    node: NodeId,
+    combinators: &[(Combinator, SimpleSelector)],
+) -> bool {
+    let Some(((combinator, selector), rest)) = combinators.split_first() else {
+        return true;
+    };
+    let matches_here = |candidate: NodeId| {
+        matches_simple_selector(document, candidate, selector)
+            && matches_combinators(document, candidate, rest)
+    };
+    match combinator {
+        Combinator::Child => parent_element(document, node).is_some_and(matches_here),
+        Combinator::Descendant => {
+            std::iter::successors(parent_element(document, node), |&ancestor| {
+                parent_element(document, ancestor)
+            })
+            .any(matches_here)
+        }
+        Combinator::NextSibling => preceding_elements(document, node)
+            .next()
+            .is_some_and(matches_here),
+        Combinator::SubsequentSibling => preceding_elements(document, node).any(matches_here),
     }
 }
 
-fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> bool {
+fn parent_element(document: &Document, node: NodeId) -> Option<NodeId> {
+    document
+        .parent(node)
+        .filter(|&parent| document.element(parent).is_some())
+}
+
+// The element siblings before `node`, nearest first.
+fn preceding_elements(document: &Document, node: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+    std::iter::successors(document.previous_sibling(node), |&sibling| {
+        document.previous_sibling(sibling)
+    })
+    .filter(|&sibling| document.element(sibling).is_some())
+}
+
+// The element siblings after `node`, nearest first.
+fn following_elements(document: &Document, node: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+    std::iter::successors(document.next_sibling(node), |&sibling| {
+        document.next_sibling(sibling)
+    })
+    .filter(|&sibling| document.element(sibling).is_some())
+}
+
+fn matches_simple_selector(document: &Document, node: NodeId, selector: &SimpleSelector) -> bool {
+    let Some(elem) = document.element(node) else {
+        return false;
+    };
+
     // Check type selector
-    if selector.tag_name.iter().any(|name| elem.tag_name != *name) {
+    if selector
+        .tag_name
+        .iter()
+        .any(|name| !matches_type_selector(elem, name))
+    {
         return false;
     }
 
+    // IDs and classes are case-sensitive, except in quirks mode where they match ASCII
+    // case-insensitively.
+    // https://www.w3.org/TR/selectors-4/#case-sensitive
+    let quirks = document.mode() == QuirksMode::Quirks;
+    let same = |a: &str, b: &str| {
+        if quirks {
+            a.eq_ignore_ascii_case(b)
+        } else {
+            a == b
+        }
+    };
+
     // Check ID selector
-    if selector.id.iter().any(|id| elem.id() != Some(id)) {
+    if selector
+        .id
+        .iter()
+        .any(|id| !elem.id().is_some_and(|elem_id| same(elem_id, id)))
+    {
         return false;
     }
 
     // Check class selectors
     let elem_classes = elem.classes();
-    if selector
-        .class
+    if selector.class.iter().any(|class| {
+        !elem_classes
+            .iter()
+            .any(|elem_class| same(elem_class, class))
+    }) {
+        return false;
+    }
+
+    // Check pseudo-classes
+    if !selector
+        .pseudo_classes
         .iter()
-        .any(|class| !elem_classes.contains(&**class))
+        .all(|&pseudo_class| matches_pseudo_class(document, node, pseudo_class))
     {
         return false;
     }
@@ -92,13 +231,48 @@ fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> boo
     true
 }
 
+// Type selectors match the names of HTML elements ASCII case-insensitively, and the names of SVG
+// and MathML elements, like `foreignObject`, exactly.
+// https://html.spec.whatwg.org/multipage/semantics-other.html#case-sensitivity-of-selectors
+fn matches_type_selector(elem: &ElementData, name: &str) -> bool {
+    match elem.namespace {
+        Namespace::Html => elem.tag_name.eq_ignore_ascii_case(name),
+        _ => elem.tag_name == name,
+    }
+}
+
+// https://www.w3.org/TR/selectors-4/#structural-pseudos
+fn matches_pseudo_class(document: &Document, node: NodeId, pseudo_class: PseudoClass) -> bool {
+    let first = || preceding_elements(document, node).next().is_none();
+    let last = || following_elements(document, node).next().is_none();
+    match pseudo_class {
+        PseudoClass::Root => document.parent(node) == Some(document.root()),
+        // The :empty pseudo-class represents an element that has no children except, optionally,
+        // document white space characters.
+        PseudoClass::Empty => {
+            document
+                .children(node)
+                .all(|child| match document.node_type(child) {
+                    NodeType::Text(data) => data
+                        .chars()
+                        .all(|c| matches!(c, ' ' | '\t' | '\n' | '\r' | '\x0C')),
+                    NodeType::Element(_) => false,
+                    _ => true,
+                })
+        }
+        PseudoClass::FirstChild => first(),
+        PseudoClass::LastChild => last(),
+        PseudoClass::OnlyChild => first() && last(),
+    }
+}
+
 // Apply styles to a single element, returning the specified values.
-fn specified_values(elem: &ElementData, stylesheet: &Stylesheet) -> PropertyMap {
+fn specified_values(document: &Document, node: NodeId, stylesheet: &Stylesheet) -> PropertyMap {
     let mut values = HashMap::new();
-    let mut rules = matching_rules(elem, stylesheet);
+    let mut rules = matching_rules(document, node, stylesheet);
 
     // Go through the rules from lowest to highest specificity.
-    rules.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
+    rules.sort_by_key(|&(specificity, _)| specificity);
     for (_, rule) in rules {
         for declaration in &rule.declarations {
             values.insert(declaration.name.clone(), declaration.value.clone());
@@ -108,19 +282,106 @@ fn specified_values(elem: &ElementData, stylesheet: &Stylesheet) -> PropertyMap
     values
 }
 
+// Only elements and text are styled and laid out. Comments, doctypes and processing instructions
+// are skipped.
+fn is_rendered(node_type: &NodeType) -> bool {
+    matches!(node_type, NodeType::Element(_) | NodeType::Text(_))
+}
+
 // Apply a stylesheet to an entire DOM tree, returning a StyledNode tree.
-pub fn style_tree<'a>(root: &'a Node, stylesheet: &'a Stylesheet) -> StyledNode {
+pub fn style_tree(document: &Arc<Document>, stylesheet: &Stylesheet) -> StyledNode {
+    // The document itself isn't rendered, only its document element.
+    let root = document.document_element().unwrap_or(document.root());
+    style_node(document, root, stylesheet, &HashMap::new())
+}
+
+fn style_node(
+    document: &Arc<Document>,
+    node: NodeId,
+    stylesheet: &Stylesheet,
+    parent_values: &PropertyMap,
+) -> StyledNode {
+    let mut values = match document.node_type(node) {
+        NodeType::Element(_) => specified_values(document, node, stylesheet),
+        _ => HashMap::new(),
+    };
+    for &name in INHERITED_PROPERTIES {
+        if let Some(value) = parent_values.get(name) {
+            values
+                .entry(name.to_string())
+                .or_insert_with(|| value.clone());
+        }
+    }
     StyledNode {
-        node: Arc::new(root.clone()),
-        specified_values: match root.clone().node_type {
-            NodeType::Element(ref elem) => specified_values(elem, stylesheet),
-            _ => HashMap::new(),
-        },
-        children: root
-            .clone()
-            .children
-            .iter()
-            .map(|child| style_tree(child, stylesheet))
+        document: Arc::clone(document),
+        node,
+        children: document
+            .children(node)
+            .filter(|&child| is_rendered(document.node_type(child)))
+            .map(|child| style_node(document, child, stylesheet, &values))
             .collect(),
+        specified_values: values,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{css::Parser, query::QuerySelector};
+
+    #[test]
+    fn collapsible_whitespace() {
+        let document = Arc::new(
+            html::parser::Parser::from_string(
+                "<div> <p>a</p>\n<p>b</p></div><pre> <b>\n</b></pre><section>\n<i> </i></section>",
+            )
+            .run(),
+        );
+        let stylesheet =
+            Parser::parse("pre { white-space: pre; } section { white-space: pre-line; }");
+        let html = style_tree(&document, &stylesheet);
+        let body = &html.children[1];
+        let [div, pre, section] = &body.children[..] else {
+            panic!("the body has three children");
+        };
+        let collapsible = |node: &StyledNode| -> Vec<bool> {
+            node.children
+                .iter()
+                .map(StyledNode::is_collapsible_whitespace)
+                .collect()
+        };
+        // The text inside the paragraphs isn't white space.
+        assert_eq!(collapsible(div), [true, false, true, false]);
+        // `white-space` is inherited, so the line break inside `b` is preserved too.
+        assert_eq!(collapsible(pre), [false, false]);
+        assert_eq!(collapsible(&pre.children[1]), [false]);
+        // `pre-line` only preserves line breaks.
+        assert_eq!(collapsible(section), [false, false]);
+        assert_eq!(collapsible(&section.children[1]), [true]);
+    }
+
+    #[test]
+    fn case_sensitivity() {
+        let matches = |input: &str, selector: &str| {
+            let document = html::parser::Parser::from_string(input).run();
+            document
+                .query_selector(document.root(), selector)
+                .unwrap()
+                .is_some()
+        };
+        let input = "<!DOCTYPE html><DIV ID=Main CLASS=Note><svg><foreignObject/></svg></DIV>";
+        // HTML element names match in any case, but SVG element names, ids and classes don't.
+        assert!(matches(input, "div"));
+        assert!(matches(input, "Div#Main.Note"));
+        assert!(matches(input, "foreignObject"));
+        assert!(!matches(input, "foreignobject"));
+        assert!(!matches(input, "#main"));
+        assert!(!matches(input, ".note"));
+
+        // Without a DOCTYPE the document is in quirks mode, where ids and classes match in any
+        // case.
+        let input = "<div id=Main class=Note></div>";
+        assert!(matches(input, "#main"));
+        assert!(matches(input, ".NOTE"));
     }
 }
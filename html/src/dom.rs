@@ -1,4 +1,9 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::source::SourceSpan;
 
 #[derive(PartialEq, Debug, Clone)]
 pub struct Node {
@@ -22,27 +27,142 @@ impl Node {
             children,
             node_type: NodeType::Element(ElementData {
                 tag_name: name,
+                namespace: Namespace::Html,
+                attributes: attrs,
+            }),
+        }
+    }
+
+    /// An element in another namespace than HTML's, like an inline `<svg>` or `<math>` element.
+    pub fn elem_ns(
+        namespace: Namespace,
+        name: String,
+        attrs: AttrMap,
+        children: Vec<Node>,
+    ) -> Self {
+        Node {
+            children,
+            node_type: NodeType::Element(ElementData {
+                tag_name: name,
+                namespace,
                 attributes: attrs,
             }),
         }
     }
+
+    pub fn comment(data: String) -> Self {
+        Node {
+            children: Vec::new(),
+            node_type: NodeType::Comment(data),
+        }
+    }
+
+    pub fn doctype(name: String, public_id: String, system_id: String) -> Self {
+        Node {
+            children: Vec::new(),
+            node_type: NodeType::Doctype(DoctypeData {
+                name,
+                public_id,
+                system_id,
+            }),
+        }
+    }
+
+    pub fn document(mode: QuirksMode, children: Vec<Node>) -> Self {
+        Node {
+            children,
+            node_type: NodeType::Document(DocumentData { mode }),
+        }
+    }
+
+    /// The concatenation of the data of all the Text node descendants of this node, in tree order.
+    ///
+    /// https://dom.spec.whatwg.org/#concept-descendant-text-content
+    pub fn text_content(&self) -> String {
+        match self.node_type {
+            NodeType::Text(ref data) => data.clone(),
+            _ => self.children.iter().map(Node::text_content).collect(),
+        }
+    }
+
+    /// The document element of a document is the element whose parent is that document, if it
+    /// exists.
+    ///
+    /// https://dom.spec.whatwg.org/#document-element
+    pub fn document_element(&self) -> Option<&Node> {
+        match self.node_type {
+            NodeType::Document(_) => self
+                .children
+                .iter()
+                .find(|child| matches!(child.node_type, NodeType::Element(_))),
+            _ => None,
+        }
+    }
 }
 
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub enum NodeType {
     Text(String),
     Element(ElementData),
+    Comment(String),
+    Doctype(DoctypeData),
+    Document(DocumentData),
+    /// A node that holds children without being part of a document's tree, like the contents of a
+    /// `<template>` element. Inserting a fragment inserts its children instead.
+    ///
+    /// https://dom.spec.whatwg.org/#interface-documentfragment
+    DocumentFragment,
+    /// The HTML parser never creates processing instructions, `<?...>` is parsed as a comment
+    /// instead, but they can appear in documents that are built by other means.
+    ProcessingInstruction(ProcessingInstructionData),
 }
 
+/// Attributes are keyed by their qualified name, so namespaced attributes on foreign elements
+/// keep their prefix, as in `xlink:href`.
 pub type AttrMap = HashMap<String, String>;
 
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub struct ElementData {
     pub tag_name: String,
+    pub namespace: Namespace,
     pub attributes: AttrMap,
 }
 
+/// The namespace of an element. HTML documents contain elements in the HTML namespace, and
+/// inline SVG and MathML elements in their own namespaces.
+///
+/// https://infra.spec.whatwg.org/#namespaces
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default, Hash)]
+pub enum Namespace {
+    #[default]
+    Html,
+    MathMl,
+    Svg,
+}
+
+impl Namespace {
+    /// https://infra.spec.whatwg.org/#namespaces
+    pub fn url(self) -> &'static str {
+        match self {
+            Namespace::Html => "http://www.w3.org/1999/xhtml",
+            Namespace::MathMl => "http://www.w3.org/1998/Math/MathML",
+            Namespace::Svg => "http://www.w3.org/2000/svg",
+        }
+    }
+}
+
 impl ElementData {
+    /// The name under which the attribute `name` is stored: if this element is in the HTML
+    /// namespace, then set qualifiedName to qualifiedName in ASCII lowercase.
+    ///
+    /// https://dom.spec.whatwg.org/#concept-element-attributes-get-by-name
+    fn attribute_name(&self, name: &str) -> String {
+        match self.namespace {
+            Namespace::Html => name.to_ascii_lowercase(),
+            _ => name.to_string(),
+        }
+    }
+
     pub fn id(&self) -> Option<&String> {
         self.attributes.get("id")
     }
@@ -54,3 +174,1065 @@ impl ElementData {
         }
     }
 }
+
+/// https://dom.spec.whatwg.org/#interface-documenttype
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct DoctypeData {
+    pub name: String,
+    pub public_id: String,
+    pub system_id: String,
+}
+
+/// https://dom.spec.whatwg.org/#interface-document
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct DocumentData {
+    pub mode: QuirksMode,
+}
+
+/// Each document has an associated mode, which is used by the rendering stages to emulate the
+/// behaviour of legacy browsers.
+///
+/// https://dom.spec.whatwg.org/#concept-document-mode
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum QuirksMode {
+    #[default]
+    NoQuirks,
+    LimitedQuirks,
+    Quirks,
+}
+
+/// https://dom.spec.whatwg.org/#interface-processinginstruction
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ProcessingInstructionData {
+    pub target: String,
+    pub data: String,
+}
+
+/// An index into the arena of nodes owned by a `Document`, tagged with the document it belongs
+/// to. It stays valid for as long as the document exists.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub struct NodeId {
+    document: usize,
+    index: usize,
+}
+
+/// The ways a DOM mutation can fail, named after the `DOMException`s that the DOM specification
+/// throws in the same situations.
+///
+/// https://webidl.spec.whatwg.org/#idl-DOMException-error-names
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum DomError {
+    /// The operation would yield an incorrect node tree, such as a cycle or an element inside a
+    /// text node.
+    HierarchyRequest,
+    /// The object can not be found here.
+    NotFound,
+    /// The supplied node is incorrect or has an incorrect ancestor for this operation.
+    InvalidNodeType,
+    /// The string contains invalid characters.
+    InvalidCharacter,
+    /// The operation is not supported.
+    NotSupported,
+}
+
+/// A node stored in a `Document`, with links to its parent, its first and last children and its
+/// siblings.
+struct DocumentNode {
+    parent: Option<NodeId>,
+    first_child: Option<NodeId>,
+    last_child: Option<NodeId>,
+    previous_sibling: Option<NodeId>,
+    next_sibling: Option<NodeId>,
+    node_type: NodeType,
+    /// The document fragment holding the contents of a template element.
+    template_contents: Option<NodeId>,
+    /// The template element whose contents are this document fragment.
+    host: Option<NodeId>,
+    /// Where the node was parsed from, if it came from a parser.
+    span: Option<SourceSpan>,
+}
+
+/// A document whose nodes are stored in an arena and referred to by `NodeId`s. Nodes link to
+/// their parent, children and siblings, so the tree can be navigated in any direction in constant
+/// time. The first node is the document itself.
+pub struct Document {
+    /// A number that no other document has, which the ids of this document's nodes carry so that
+    /// ids from other documents can be told apart.
+    id: usize,
+    nodes: Vec<DocumentNode>,
+    /// Whether the document was parsed with scripting enabled.
+    scripting: bool,
+}
+
+/// The id of the next document to be created.
+static NEXT_DOCUMENT_ID: AtomicUsize = AtomicUsize::new(0);
+
+impl Default for Document {
+    fn default() -> Self {
+        let mut document = Document {
+            id: NEXT_DOCUMENT_ID.fetch_add(1, Ordering::Relaxed),
+            nodes: Vec::new(),
+            scripting: false,
+        };
+        document.create_node(NodeType::Document(DocumentData {
+            mode: QuirksMode::default(),
+        }));
+        document
+    }
+}
+
+impl Document {
+    /// The node of the document itself.
+    pub fn root(&self) -> NodeId {
+        NodeId {
+            document: self.id,
+            index: 0,
+        }
+    }
+
+    pub fn mode(&self) -> QuirksMode {
+        match self.node_type(self.root()) {
+            NodeType::Document(data) => data.mode,
+            _ => QuirksMode::default(),
+        }
+    }
+
+    /// Whether scripting is enabled for the document's nodes, which changes how `noscript`
+    /// elements are parsed and serialized.
+    ///
+    /// https://html.spec.whatwg.org/multipage/webappapis.html#concept-n-script
+    pub fn scripting_enabled(&self) -> bool {
+        self.scripting
+    }
+
+    pub(crate) fn set_scripting_enabled(&mut self, scripting: bool) {
+        self.scripting = scripting;
+    }
+
+    pub(crate) fn set_mode(&mut self, mode: QuirksMode) {
+        let root = self.root();
+        if let NodeType::Document(data) = self.node_type_mut(root) {
+            data.mode = mode;
+        }
+    }
+
+    /// The document element of a document is the element whose parent is that document, if it
+    /// exists.
+    ///
+    /// https://dom.spec.whatwg.org/#document-element
+    pub fn document_element(&self) -> Option<NodeId> {
+        self.children(self.root())
+            .find(|&child| self.element(child).is_some())
+    }
+
+    /// Create a node that is not yet attached to the tree. Template elements get a document
+    /// fragment for their contents.
+    pub(crate) fn create_node(&mut self, node_type: NodeType) -> NodeId {
+        let is_template = matches!(
+            &node_type,
+            NodeType::Element(elem) if elem.namespace == Namespace::Html && elem.tag_name == "template"
+        );
+        self.nodes.push(DocumentNode {
+            parent: None,
+            first_child: None,
+            last_child: None,
+            previous_sibling: None,
+            next_sibling: None,
+            node_type,
+            template_contents: None,
+            host: None,
+            span: None,
+        });
+        let id = NodeId {
+            document: self.id,
+            index: self.nodes.len() - 1,
+        };
+        if is_template {
+            let contents = self.create_document_fragment();
+            self.node_mut(id).template_contents = Some(contents);
+            self.node_mut(contents).host = Some(id);
+        }
+        id
+    }
+
+    /// The node `id`. Panics if it belongs to another document: methods that return a
+    /// `Result` check their ids with `check_owned` first.
+    fn node(&self, id: NodeId) -> &DocumentNode {
+        assert_eq!(id.document, self.id, "{:?} belongs to another document", id);
+        &self.nodes[id.index]
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> &mut DocumentNode {
+        assert_eq!(id.document, self.id, "{:?} belongs to another document", id);
+        &mut self.nodes[id.index]
+    }
+
+    /// Reject ids of nodes that belong to another document with a "NotFoundError".
+    fn check_owned(&self, ids: &[NodeId]) -> Result<(), DomError> {
+        if ids.iter().all(|id| id.document == self.id) {
+            Ok(())
+        } else {
+            Err(DomError::NotFound)
+        }
+    }
+
+    pub fn node_type(&self, id: NodeId) -> &NodeType {
+        &self.node(id).node_type
+    }
+
+    pub(crate) fn node_type_mut(&mut self, id: NodeId) -> &mut NodeType {
+        &mut self.node_mut(id).node_type
+    }
+
+    /// Return the element data of `id`, or `None` if it is not an element.
+    pub fn element(&self, id: NodeId) -> Option<&ElementData> {
+        match self.node_type(id) {
+            NodeType::Element(data) => Some(data),
+            _ => None,
+        }
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id).parent
+    }
+
+    pub fn first_child(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id).first_child
+    }
+
+    pub fn last_child(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id).last_child
+    }
+
+    pub fn previous_sibling(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id).previous_sibling
+    }
+
+    pub fn next_sibling(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id).next_sibling
+    }
+
+    /// Where the node `id` was parsed from. Elements span their start tag, and elements that the
+    /// parser inserts without a tag of their own get the span of the token that implied them.
+    /// Nodes created through the DOM API have no span.
+    pub fn span(&self, id: NodeId) -> Option<SourceSpan> {
+        self.node(id).span
+    }
+
+    pub(crate) fn set_span(&mut self, id: NodeId, span: Option<SourceSpan>) {
+        self.node_mut(id).span = span;
+    }
+
+    /// The document fragment holding the contents of the template element `id`, or `None` if it
+    /// isn't a template. The contents are not children of the template, so they are skipped when
+    /// walking the tree and are never rendered.
+    ///
+    /// https://html.spec.whatwg.org/multipage/scripting.html#template-contents
+    pub fn template_contents(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id).template_contents
+    }
+
+    /// Iterate over the children of `id`, in tree order.
+    pub fn children(&self, id: NodeId) -> Children<'_> {
+        Children {
+            document: self,
+            next: self.first_child(id),
+        }
+    }
+
+    /// Iterate over the descendants of `id`, in tree order, not including `id` itself.
+    pub fn descendants(&self, id: NodeId) -> Descendants<'_> {
+        Descendants {
+            document: self,
+            root: id,
+            next: self.first_child(id),
+        }
+    }
+
+    /// Append `child` as the last child of `parent`, removing it from its old parent first.
+    pub(crate) fn append(&mut self, parent: NodeId, child: NodeId) {
+        self.insert(parent, child, None);
+    }
+
+    /// Insert `child` into `parent` before `before`, or as the last child if `before` is `None`,
+    /// removing it from its old parent first. This only maintains the links: callers are
+    /// responsible for keeping the tree valid.
+    pub(crate) fn insert(&mut self, parent: NodeId, child: NodeId, before: Option<NodeId>) {
+        self.detach(child);
+        let previous = match before {
+            Some(before) => self.node_mut(before).previous_sibling.replace(child),
+            None => self.node_mut(parent).last_child.replace(child),
+        };
+        match previous {
+            Some(previous) => self.node_mut(previous).next_sibling = Some(child),
+            None => self.node_mut(parent).first_child = Some(child),
+        }
+        let node = self.node_mut(child);
+        node.parent = Some(parent);
+        node.previous_sibling = previous;
+        node.next_sibling = before;
+    }
+
+    /// Remove `id` from its parent, if it has one.
+    pub(crate) fn detach(&mut self, id: NodeId) {
+        let node = self.node_mut(id);
+        let Some(parent) = node.parent.take() else {
+            return;
+        };
+        let previous = node.previous_sibling.take();
+        let next = node.next_sibling.take();
+        match previous {
+            Some(previous) => self.node_mut(previous).next_sibling = next,
+            None => self.node_mut(parent).first_child = next,
+        }
+        match next {
+            Some(next) => self.node_mut(next).previous_sibling = previous,
+            None => self.node_mut(parent).last_child = previous,
+        }
+    }
+
+    /// Create an HTML element with the name `tag_name`, which is lowercased.
+    ///
+    /// https://dom.spec.whatwg.org/#dom-document-createelement
+    pub fn create_element(&mut self, tag_name: &str) -> Result<NodeId, DomError> {
+        // If localName is not a valid element local name, then throw an "InvalidCharacterError"
+        // DOMException.
+        if !is_valid_element_local_name(tag_name) {
+            return Err(DomError::InvalidCharacter);
+        }
+        Ok(self.create_node(NodeType::Element(ElementData {
+            tag_name: tag_name.to_ascii_lowercase(),
+            namespace: Namespace::Html,
+            attributes: AttrMap::new(),
+        })))
+    }
+
+    pub fn create_text_node(&mut self, data: &str) -> NodeId {
+        self.create_node(NodeType::Text(data.to_string()))
+    }
+
+    pub fn create_comment(&mut self, data: &str) -> NodeId {
+        self.create_node(NodeType::Comment(data.to_string()))
+    }
+
+    pub fn create_document_fragment(&mut self) -> NodeId {
+        self.create_node(NodeType::DocumentFragment)
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-node-appendchild
+    pub fn append_child(&mut self, parent: NodeId, node: NodeId) -> Result<(), DomError> {
+        self.insert_before(parent, node, None)
+    }
+
+    /// Insert `node` into `parent` before `child`, or as the last child if `child` is `None`. If
+    /// `node` is already in the tree it is moved.
+    ///
+    /// https://dom.spec.whatwg.org/#concept-node-pre-insert
+    pub fn insert_before(
+        &mut self,
+        parent: NodeId,
+        node: NodeId,
+        child: Option<NodeId>,
+    ) -> Result<(), DomError> {
+        self.check_owned(&[parent, node])?;
+        self.check_owned(child.as_slice())?;
+        self.ensure_pre_insertion_validity(parent, node, child)?;
+        // If referenceChild is node, then set referenceChild to node's next sibling.
+        let child = match child {
+            Some(child) if child == node => self.next_sibling(node),
+            child => child,
+        };
+        self.insert_node_or_fragment(parent, node, child);
+        Ok(())
+    }
+
+    /// Insert `node` into `parent` before `before`, or its children if it is a document fragment.
+    ///
+    /// https://dom.spec.whatwg.org/#concept-node-insert
+    fn insert_node_or_fragment(&mut self, parent: NodeId, node: NodeId, before: Option<NodeId>) {
+        if matches!(self.node_type(node), NodeType::DocumentFragment) {
+            let children: Vec<NodeId> = self.children(node).collect();
+            for child in children {
+                self.insert(parent, child, before);
+            }
+        } else {
+            self.insert(parent, node, before);
+        }
+    }
+
+    /// Replace `child`, a child of `parent`, with `node`.
+    ///
+    /// https://dom.spec.whatwg.org/#concept-node-replace
+    pub fn replace_child(
+        &mut self,
+        parent: NodeId,
+        node: NodeId,
+        child: NodeId,
+    ) -> Result<(), DomError> {
+        self.check_owned(&[parent, node, child])?;
+        if !self.can_have_children(parent) {
+            return Err(DomError::HierarchyRequest);
+        }
+        if self.is_inclusive_ancestor(node, parent) {
+            return Err(DomError::HierarchyRequest);
+        }
+        if self.parent(child) != Some(parent) {
+            return Err(DomError::NotFound);
+        }
+        self.check_child_type(parent, node)?;
+        if parent == self.root() {
+            let others = || self.children(parent).filter(|&other| other != child);
+            match self.node_type(node) {
+                // If node has more than one element child or has a Text node child. Otherwise, if
+                // node has one element child and either parent has an element child that is not
+                // child or a doctype is following child.
+                NodeType::DocumentFragment
+                    if self.is_invalid_document_fragment(node)
+                        || (self.children(node).any(|c| self.element(c).is_some())
+                            && (others().any(|other| self.element(other).is_some())
+                                || self.following_siblings(child).any(|s| self.is_doctype(s)))) =>
+                {
+                    return Err(DomError::HierarchyRequest);
+                }
+                // If node is an element, and parent has an element child that is not child or a
+                // doctype is following child.
+                NodeType::Element(_)
+                    if others().any(|other| self.element(other).is_some())
+                        || self.following_siblings(child).any(|s| self.is_doctype(s)) =>
+                {
+                    return Err(DomError::HierarchyRequest);
+                }
+                // If node is a doctype, and parent has a doctype child that is not child, or an
+                // element is preceding child.
+                NodeType::Doctype(_)
+                    if others().any(|other| self.is_doctype(other))
+                        || self
+                            .preceding_siblings(child)
+                            .any(|s| self.element(s).is_some()) =>
+                {
+                    return Err(DomError::HierarchyRequest);
+                }
+                _ => {}
+            }
+        }
+
+        if child == node {
+            return Ok(());
+        }
+        let mut reference_child = self.next_sibling(child);
+        if reference_child == Some(node) {
+            reference_child = self.next_sibling(node);
+        }
+        self.detach(child);
+        self.insert_node_or_fragment(parent, node, reference_child);
+        Ok(())
+    }
+
+    /// Remove `child` from `parent`. The node stays in the document's arena, so it can be inserted
+    /// again later.
+    ///
+    /// https://dom.spec.whatwg.org/#concept-node-pre-remove
+    pub fn remove_child(&mut self, parent: NodeId, child: NodeId) -> Result<(), DomError> {
+        self.check_owned(&[parent, child])?;
+        if self.parent(child) != Some(parent) {
+            return Err(DomError::NotFound);
+        }
+        self.detach(child);
+        Ok(())
+    }
+
+    /// Set the attribute `name` of the element `id` to `value`. The names of attributes on HTML
+    /// elements are lowercased.
+    ///
+    /// https://dom.spec.whatwg.org/#dom-element-setattribute
+    pub fn set_attribute(&mut self, id: NodeId, name: &str, value: &str) -> Result<(), DomError> {
+        self.check_owned(&[id])?;
+        // If qualifiedName is not a valid attribute local name, then throw an
+        // "InvalidCharacterError" DOMException.
+        if name.is_empty()
+            || name
+                .contains(|c: char| c.is_ascii_whitespace() || matches!(c, '\0' | '/' | '>' | '='))
+        {
+            return Err(DomError::InvalidCharacter);
+        }
+        match self.node_type_mut(id) {
+            NodeType::Element(elem) => {
+                let name = elem.attribute_name(name);
+                elem.attributes.insert(name, value.to_string());
+                Ok(())
+            }
+            _ => Err(DomError::InvalidNodeType),
+        }
+    }
+
+    /// Remove the attribute `name` of the element `id`, returning its old value.
+    ///
+    /// https://dom.spec.whatwg.org/#dom-element-removeattribute
+    pub fn remove_attribute(&mut self, id: NodeId, name: &str) -> Result<Option<String>, DomError> {
+        self.check_owned(&[id])?;
+        match self.node_type_mut(id) {
+            NodeType::Element(elem) => Ok(elem.attributes.remove(&elem.attribute_name(name))),
+            _ => Err(DomError::InvalidNodeType),
+        }
+    }
+
+    /// Replace the data of a text, comment or processing instruction node.
+    ///
+    /// https://dom.spec.whatwg.org/#dom-characterdata-data
+    pub fn set_data(&mut self, id: NodeId, data: &str) -> Result<(), DomError> {
+        self.check_owned(&[id])?;
+        match self.node_type_mut(id) {
+            NodeType::Text(old) | NodeType::Comment(old) => *old = data.to_string(),
+            NodeType::ProcessingInstruction(pi) => pi.data = data.to_string(),
+            _ => return Err(DomError::InvalidNodeType),
+        }
+        Ok(())
+    }
+
+    /// Create a copy of `id` that has no parent. If `deep` is set, its descendants are copied as
+    /// well. Documents can't be cloned into themselves.
+    ///
+    /// https://dom.spec.whatwg.org/#concept-node-clone
+    pub fn clone_node(&mut self, id: NodeId, deep: bool) -> Result<NodeId, DomError> {
+        self.check_owned(&[id])?;
+        if id == self.root() {
+            return Err(DomError::NotSupported);
+        }
+        let copy = self.create_node(self.node_type(id).clone());
+        if deep {
+            let children: Vec<NodeId> = self.children(id).collect();
+            for child in children {
+                let child_copy = self.clone_node(child, true)?;
+                self.append(copy, child_copy);
+            }
+            // The cloning steps for template elements also copy the template contents.
+            if let (Some(contents), Some(copy_contents)) =
+                (self.template_contents(id), self.template_contents(copy))
+            {
+                let children: Vec<NodeId> = self.children(contents).collect();
+                for child in children {
+                    let child_copy = self.clone_node(child, true)?;
+                    self.append(copy_contents, child_copy);
+                }
+            }
+        }
+        Ok(copy)
+    }
+
+    fn can_have_children(&self, id: NodeId) -> bool {
+        matches!(
+            self.node_type(id),
+            NodeType::Document(_) | NodeType::DocumentFragment | NodeType::Element(_)
+        )
+    }
+
+    /// Does the document fragment `id` have more than one element child, or a Text node child?
+    /// Then it can't be inserted into a document.
+    fn is_invalid_document_fragment(&self, id: NodeId) -> bool {
+        self.children(id)
+            .filter(|&c| self.element(c).is_some())
+            .count()
+            > 1
+            || self
+                .children(id)
+                .any(|c| matches!(self.node_type(c), NodeType::Text(_)))
+    }
+
+    fn is_doctype(&self, id: NodeId) -> bool {
+        matches!(self.node_type(id), NodeType::Doctype(_))
+    }
+
+    /// Is `ancestor` either `id` or one of its ancestors? The template element holding a
+    /// document fragment counts as the fragment's parent here.
+    ///
+    /// https://dom.spec.whatwg.org/#concept-tree-host-including-inclusive-ancestor
+    fn is_inclusive_ancestor(&self, ancestor: NodeId, id: NodeId) -> bool {
+        let mut node = Some(id);
+        while let Some(current) = node {
+            if current == ancestor {
+                return true;
+            }
+            node = self.parent(current).or(self.node(current).host);
+        }
+        false
+    }
+
+    fn following_siblings(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::successors(self.next_sibling(id), |&s| self.next_sibling(s))
+    }
+
+    fn preceding_siblings(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::successors(self.previous_sibling(id), |&s| self.previous_sibling(s))
+    }
+
+    /// Steps 4 and 5 of ensuring pre-insertion validity, which are shared with replacing a child.
+    fn check_child_type(&self, parent: NodeId, node: NodeId) -> Result<(), DomError> {
+        match self.node_type(node) {
+            // If node is not a DocumentFragment, DocumentType, Element, or CharacterData node,
+            // then throw a "HierarchyRequestError" DOMException.
+            NodeType::Document(_) => Err(DomError::HierarchyRequest),
+            // If either node is a Text node and parent is a document, or node is a doctype and
+            // parent is not a document, then throw a "HierarchyRequestError" DOMException.
+            NodeType::Text(_) if parent == self.root() => Err(DomError::HierarchyRequest),
+            NodeType::Doctype(_) if parent != self.root() => Err(DomError::HierarchyRequest),
+            _ => Ok(()),
+        }
+    }
+
+    /// https://dom.spec.whatwg.org/#concept-node-ensure-pre-insertion-validity
+    fn ensure_pre_insertion_validity(
+        &self,
+        parent: NodeId,
+        node: NodeId,
+        child: Option<NodeId>,
+    ) -> Result<(), DomError> {
+        // If parent is not a Document, DocumentFragment, or Element node, then throw a
+        // "HierarchyRequestError" DOMException.
+        if !self.can_have_children(parent) {
+            return Err(DomError::HierarchyRequest);
+        }
+        // If node is a host-including inclusive ancestor of parent, then throw a
+        // "HierarchyRequestError" DOMException.
+        if self.is_inclusive_ancestor(node, parent) {
+            return Err(DomError::HierarchyRequest);
+        }
+        // If child is non-null and its parent is not parent, then throw a "NotFoundError"
+        // DOMException.
+        if child.is_some_and(|child| self.parent(child) != Some(parent)) {
+            return Err(DomError::NotFound);
+        }
+        self.check_child_type(parent, node)?;
+        // If parent is a document, and any of the statements below, switched on the interface
+        // node implements, are true, then throw a "HierarchyRequestError" DOMException.
+        if parent == self.root() {
+            let invalid = match self.node_type(node) {
+                // node has more than one element child or has a Text node child, or node has one
+                // element child and either parent has an element child, child is a doctype, or
+                // child is non-null and a doctype is following child.
+                NodeType::DocumentFragment => {
+                    self.is_invalid_document_fragment(node)
+                        || (self.children(node).any(|c| self.element(c).is_some())
+                            && (self.document_element().is_some()
+                                || child.is_some_and(|child| {
+                                    self.is_doctype(child)
+                                        || self
+                                            .following_siblings(child)
+                                            .any(|s| self.is_doctype(s))
+                                })))
+                }
+                // parent has an element child, child is a doctype, or child is non-null and a
+                // doctype is following child.
+                NodeType::Element(_) => {
+                    self.document_element().is_some()
+                        || child.is_some_and(|child| {
+                            self.is_doctype(child)
+                                || self.following_siblings(child).any(|s| self.is_doctype(s))
+                        })
+                }
+                // parent has a doctype child, child is non-null and an element is preceding
+                // child, or child is null and parent has an element child.
+                NodeType::Doctype(_) => {
+                    self.children(parent).any(|other| self.is_doctype(other))
+                        || match child {
+                            Some(child) => self
+                                .preceding_siblings(child)
+                                .any(|s| self.element(s).is_some()),
+                            None => self.document_element().is_some(),
+                        }
+                }
+                _ => false,
+            };
+            if invalid {
+                return Err(DomError::HierarchyRequest);
+            }
+        }
+        Ok(())
+    }
+
+    /// The concatenation of the data of all the Text node descendants of `id`, in tree order.
+    ///
+    /// https://dom.spec.whatwg.org/#concept-descendant-text-content
+    pub fn text_content(&self, id: NodeId) -> String {
+        match self.node_type(id) {
+            NodeType::Text(data) => data.clone(),
+            _ => self
+                .children(id)
+                .map(|child| self.text_content(child))
+                .collect(),
+        }
+    }
+
+    /// Build an owned `Node` tree from the subtree rooted at `id`. Template contents are not
+    /// children of their template, so they are left out; call this on `template_contents` to copy
+    /// them.
+    pub fn to_node(&self, id: NodeId) -> Node {
+        Node {
+            children: self.children(id).map(|child| self.to_node(child)).collect(),
+            node_type: self.node_type(id).clone(),
+        }
+    }
+}
+
+/// An iterator over the children of a node in a `Document`.
+pub struct Children<'a> {
+    document: &'a Document,
+    next: Option<NodeId>,
+}
+
+impl Iterator for Children<'_> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<NodeId> {
+        let id = self.next?;
+        self.next = self.document.next_sibling(id);
+        Some(id)
+    }
+}
+
+/// An iterator over the descendants of a node in a `Document`, in tree order.
+pub struct Descendants<'a> {
+    document: &'a Document,
+    root: NodeId,
+    next: Option<NodeId>,
+}
+
+impl Iterator for Descendants<'_> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<NodeId> {
+        let id = self.next?;
+        // Go down to the first child if there is one, otherwise to the next sibling of the
+        // nearest inclusive ancestor that has one, without leaving the subtree.
+        self.next = self.document.first_child(id).or_else(|| {
+            let mut node = id;
+            loop {
+                if node == self.root {
+                    return None;
+                }
+                if let Some(next) = self.document.next_sibling(node) {
+                    return Some(next);
+                }
+                node = self.document.parent(node)?;
+            }
+        });
+        Some(id)
+    }
+}
+
+/// https://dom.spec.whatwg.org/#valid-element-local-name
+fn is_valid_element_local_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        // If name's length is 0, then return false.
+        None => false,
+        // If name's 0th code point is an ASCII alpha, then: If name contains ASCII whitespace,
+        // U+0000 NULL, U+002F (/), or U+003E (>), then return false.
+        Some(c) if c.is_ascii_alphabetic() => {
+            !chars.any(|c| c.is_ascii_whitespace() || matches!(c, '\0' | '/' | '>'))
+        }
+        // If name's 0th code point is not U+003A (:), U+005F (_), or in the range U+0080 to
+        // U+10FFFF, inclusive, then return false. If name's subsequent code points, if any, are
+        // not ASCII alphas, ASCII digits, U+002D (-), U+002E (.), U+003A (:), U+005F (_), or in
+        // the range U+0080 to U+10FFFF, inclusive, then return false.
+        Some(c) if matches!(c, ':' | '_') || !c.is_ascii() => chars.all(|c| {
+            c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | ':' | '_') || !c.is_ascii()
+        }),
+        Some(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(document: &mut Document, tag_name: &str) -> NodeId {
+        document.create_node(NodeType::Element(ElementData {
+            tag_name: tag_name.to_string(),
+            namespace: Namespace::Html,
+            attributes: AttrMap::new(),
+        }))
+    }
+
+    #[test]
+    fn links() {
+        let mut document = Document::default();
+        let root = document.root();
+        let html = element(&mut document, "html");
+        let a = element(&mut document, "a");
+        let b = element(&mut document, "b");
+        let c = element(&mut document, "c");
+        document.append(root, html);
+        document.append(html, a);
+        document.append(html, b);
+        document.append(html, c);
+
+        assert_eq!(document.document_element(), Some(html));
+        assert_eq!(
+            document.descendants(root).collect::<Vec<_>>(),
+            vec![html, a, b, c]
+        );
+        assert_eq!(document.descendants(b).next(), None);
+        assert_eq!(document.children(html).collect::<Vec<_>>(), vec![a, b, c]);
+        assert_eq!(document.parent(b), Some(html));
+        assert_eq!(document.first_child(html), Some(a));
+        assert_eq!(document.last_child(html), Some(c));
+        assert_eq!(document.previous_sibling(b), Some(a));
+        assert_eq!(document.next_sibling(b), Some(c));
+        assert_eq!(document.previous_sibling(a), None);
+        assert_eq!(document.next_sibling(c), None);
+
+        // Appending a node that is already in the tree moves it.
+        document.append(html, a);
+        assert_eq!(document.children(html).collect::<Vec<_>>(), vec![b, c, a]);
+        assert_eq!(document.previous_sibling(b), None);
+        assert_eq!(document.next_sibling(c), Some(a));
+
+        document.detach(c);
+        assert_eq!(document.children(html).collect::<Vec<_>>(), vec![b, a]);
+        assert_eq!(document.parent(c), None);
+        assert_eq!(document.next_sibling(b), Some(a));
+        assert_eq!(document.previous_sibling(a), Some(b));
+
+        document.detach(b);
+        document.detach(a);
+        assert_eq!(document.first_child(html), None);
+        assert_eq!(document.last_child(html), None);
+    }
+
+    #[test]
+    fn to_node() {
+        let mut document = Document::default();
+        let root = document.root();
+        let html = element(&mut document, "html");
+        let text = document.create_node(NodeType::Text("x".to_string()));
+        document.append(root, html);
+        document.append(html, text);
+
+        assert_eq!(document.text_content(root), "x");
+        assert_eq!(
+            document.to_node(root),
+            Node::document(
+                QuirksMode::NoQuirks,
+                vec![Node::elem(
+                    "html".to_string(),
+                    AttrMap::new(),
+                    vec![Node::text("x".to_string())]
+                )]
+            )
+        );
+    }
+
+    #[test]
+    fn mutation() {
+        let mut document = Document::default();
+        let root = document.root();
+        let html = document.create_element("HTML").unwrap();
+        let body = document.create_element("body").unwrap();
+        let a = document.create_text_node("a");
+        let b = document.create_comment("b");
+        document.append_child(root, html).unwrap();
+        document.append_child(html, body).unwrap();
+        document.append_child(body, a).unwrap();
+        document.insert_before(body, b, Some(a)).unwrap();
+        assert_eq!(document.children(body).collect::<Vec<_>>(), vec![b, a]);
+
+        let c = document.create_text_node("c");
+        document.replace_child(body, c, b).unwrap();
+        assert_eq!(document.children(body).collect::<Vec<_>>(), vec![c, a]);
+        assert_eq!(document.parent(b), None);
+
+        document.set_data(a, "d").unwrap();
+        document.set_attribute(body, "Class", "x").unwrap();
+        assert_eq!(
+            document.to_node(html),
+            Node::elem(
+                "html".to_string(),
+                AttrMap::new(),
+                vec![Node::elem(
+                    "body".to_string(),
+                    AttrMap::from([("class".to_string(), "x".to_string())]),
+                    vec![Node::text("c".to_string()), Node::text("d".to_string())]
+                )]
+            )
+        );
+        assert_eq!(
+            document.remove_attribute(body, "class"),
+            Ok(Some("x".to_string()))
+        );
+        assert_eq!(document.remove_attribute(body, "class"), Ok(None));
+
+        // SVG attribute names keep their case.
+        let svg = document.create_node(NodeType::Element(ElementData {
+            tag_name: "svg".to_string(),
+            namespace: Namespace::Svg,
+            attributes: AttrMap::new(),
+        }));
+        document.set_attribute(svg, "viewBox", "0 0 1 1").unwrap();
+        assert_eq!(
+            document.element(svg).unwrap().attributes.get("viewBox"),
+            Some(&"0 0 1 1".to_string())
+        );
+        assert_eq!(document.remove_attribute(svg, "viewbox"), Ok(None));
+
+        document.remove_child(body, c).unwrap();
+        assert_eq!(document.children(body).collect::<Vec<_>>(), vec![a]);
+        assert_eq!(document.remove_child(body, c), Err(DomError::NotFound));
+    }
+
+    #[test]
+    fn mutation_keeps_the_tree_valid() {
+        let mut document = Document::default();
+        let root = document.root();
+        let html = document.create_element("html").unwrap();
+        let div = document.create_element("div").unwrap();
+        let text = document.create_text_node("x");
+        document.append_child(root, html).unwrap();
+        document.append_child(html, div).unwrap();
+        document.append_child(div, text).unwrap();
+
+        // No cycles.
+        assert_eq!(
+            document.append_child(div, html),
+            Err(DomError::HierarchyRequest)
+        );
+        assert_eq!(
+            document.append_child(div, div),
+            Err(DomError::HierarchyRequest)
+        );
+        // Text nodes have no children.
+        let span = document.create_element("span").unwrap();
+        assert_eq!(
+            document.append_child(text, span),
+            Err(DomError::HierarchyRequest)
+        );
+        // A document has at most one element and no text.
+        assert_eq!(
+            document.append_child(root, span),
+            Err(DomError::HierarchyRequest)
+        );
+        let text2 = document.create_text_node("y");
+        assert_eq!(
+            document.append_child(root, text2),
+            Err(DomError::HierarchyRequest)
+        );
+        // The reference child must be a child of the parent.
+        assert_eq!(
+            document.insert_before(html, span, Some(text)),
+            Err(DomError::NotFound)
+        );
+        assert_eq!(
+            document.set_attribute(text, "id", "a"),
+            Err(DomError::InvalidNodeType)
+        );
+        assert_eq!(
+            document.set_attribute(div, "a b", "c"),
+            Err(DomError::InvalidCharacter)
+        );
+        assert_eq!(document.set_data(div, "a"), Err(DomError::InvalidNodeType));
+        assert_eq!(
+            document.create_element("a b"),
+            Err(DomError::InvalidCharacter)
+        );
+        assert_eq!(document.create_element(""), Err(DomError::InvalidCharacter));
+        assert_eq!(
+            document.create_element("1a"),
+            Err(DomError::InvalidCharacter)
+        );
+        assert!(document.create_element("my-élément").is_ok());
+        assert!(document.create_element("_x.y").is_ok());
+        // Nodes of another document can't be used here, even if this one has as many nodes.
+        let mut other = Document::default();
+        let foreign = other.create_text_node("z");
+        assert_eq!(document.append_child(div, foreign), Err(DomError::NotFound));
+        assert_eq!(
+            document.set_attribute(other.root(), "id", "a"),
+            Err(DomError::NotFound)
+        );
+        assert_eq!(document.clone_node(foreign, true), Err(DomError::NotFound));
+        assert_eq!(
+            document.to_node(html),
+            Node::elem(
+                "html".to_string(),
+                AttrMap::new(),
+                vec![Node::elem(
+                    "div".to_string(),
+                    AttrMap::new(),
+                    vec![Node::text("x".to_string())]
+                )]
+            )
+        );
+    }
+
+    #[test]
+    fn clone_node() {
+        let mut document = Document::default();
+        let div = document.create_element("div").unwrap();
+        let text = document.create_text_node("x");
+        document.append_child(div, text).unwrap();
+
+        let shallow = document.clone_node(div, false).unwrap();
+        assert_eq!(document.first_child(shallow), None);
+
+        let deep = document.clone_node(div, true).unwrap();
+        assert_eq!(document.parent(deep), None);
+        assert_eq!(document.to_node(deep), document.to_node(div));
+        // The copy is independent of the original.
+        let copied_text = document.first_child(deep).unwrap();
+        assert_ne!(copied_text, text);
+        document.set_data(copied_text, "y").unwrap();
+        assert_eq!(document.text_content(div), "x");
+
+        assert_eq!(
+            document.clone_node(document.root(), true),
+            Err(DomError::NotSupported)
+        );
+    }
+
+    #[test]
+    fn template_contents() {
+        let mut document = Document::default();
+        let template = document.create_element("template").unwrap();
+        let contents = document.template_contents(template).unwrap();
+        assert_eq!(document.node_type(contents), &NodeType::DocumentFragment);
+        assert_eq!(document.template_contents(contents), None);
+
+        // The contents are not children of the template.
+        let p = document.create_element("p").unwrap();
+        document.append_child(contents, p).unwrap();
+        assert_eq!(document.first_child(template), None);
+        assert_eq!(document.parent(p), Some(contents));
+        // A template can't be put inside its own contents.
+        assert_eq!(
+            document.append_child(p, template),
+            Err(DomError::HierarchyRequest)
+        );
+
+        // Cloning a template deeply clones its contents too.
+        let copy = document.clone_node(template, true).unwrap();
+        let copy_contents = document.template_contents(copy).unwrap();
+        assert_ne!(copy_contents, contents);
+        assert_eq!(document.to_node(copy_contents), document.to_node(contents));
+        let shallow = document.clone_node(template, false).unwrap();
+        let shallow_contents = document.template_contents(shallow).unwrap();
+        assert_eq!(document.first_child(shallow_contents), None);
+
+        // Inserting a fragment inserts its children.
+        let body = document.create_element("body").unwrap();
+        let fragment = document.clone_node(contents, true).unwrap();
+        let text = document.create_text_node("x");
+        document.append_child(fragment, text).unwrap();
+        document.append_child(body, fragment).unwrap();
+        assert_eq!(document.children(body).count(), 2);
+        assert_eq!(document.first_child(fragment), None);
+        assert_eq!(document.text_content(body), "x");
+        // A fragment with text can't become the child of a document.
+        let fragment = document.create_document_fragment();
+        let text = document.create_text_node("x");
+        document.append_child(fragment, text).unwrap();
+        let root = document.root();
+        assert_eq!(
+            document.append_child(root, fragment),
+            Err(DomError::HierarchyRequest)
+        );
+    }
+}
@@ -5,6 +5,7 @@
 /// When the HTML parser is decoding an input byte stream, it uses a character encoding and a confidence. The confidence is either tentative, certain, or irrelevant. The encoding used, and whether the confidence in that encoding is tentative or certain, is used during the parsing to determine whether to change the encoding. If no encoding is necessary, e.g. because the parser is operating on a Unicode stream and doesn't have to use a character encoding at all, then the confidence is irrelevant.
 ///
 /// https://html.spec.whatwg.org/multipage/parsing.html#the-input-byte-stream
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum Confidence {
     Certain,
     Tentative,
@@ -14,7 +15,519 @@ pub enum Confidence {
 /// User agents must support the encodings defined in Encoding, including, but not limited to, UTF-8, ISO-8859-2, ISO-8859-7, ISO-8859-8, windows-874, windows-1250, windows-1251, windows-1252, windows-1254, windows-1255, windows-1256, windows-1257, windows-1258, GBK, Big5, ISO-2022-JP, Shift_JIS, EUC-KR, UTF-16BE, UTF-16LE, UTF-16BE/LE, and x-user-defined. User agents must not support other encodings.
 ///
 /// https://html.spec.whatwg.org/multipage/parsing.html#character-encodings
+/// https://encoding.spec.whatwg.org/#names-and-labels
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum Encoding {
     Utf8,
-    Utf16,
+    Ibm866,
+    Iso8859_2,
+    Iso8859_3,
+    Iso8859_4,
+    Iso8859_5,
+    Iso8859_6,
+    Iso8859_7,
+    Iso8859_8,
+    Iso8859_8I,
+    Iso8859_10,
+    Iso8859_13,
+    Iso8859_14,
+    Iso8859_15,
+    Iso8859_16,
+    Koi8R,
+    Koi8U,
+    Macintosh,
+    Windows874,
+    Windows1250,
+    Windows1251,
+    Windows1252,
+    Windows1253,
+    Windows1254,
+    Windows1255,
+    Windows1256,
+    Windows1257,
+    Windows1258,
+    XMacCyrillic,
+    Gbk,
+    Gb18030,
+    Big5,
+    EucJp,
+    Iso2022Jp,
+    ShiftJis,
+    EucKr,
+    Replacement,
+    Utf16Be,
+    Utf16Le,
+    XUserDefined,
+}
+
+impl Encoding {
+    const ALL: [Encoding; 40] = [
+        Encoding::Utf8,
+        Encoding::Ibm866,
+        Encoding::Iso8859_2,
+        Encoding::Iso8859_3,
+        Encoding::Iso8859_4,
+        Encoding::Iso8859_5,
+        Encoding::Iso8859_6,
+        Encoding::Iso8859_7,
+        Encoding::Iso8859_8,
+        Encoding::Iso8859_8I,
+        Encoding::Iso8859_10,
+        Encoding::Iso8859_13,
+        Encoding::Iso8859_14,
+        Encoding::Iso8859_15,
+        Encoding::Iso8859_16,
+        Encoding::Koi8R,
+        Encoding::Koi8U,
+        Encoding::Macintosh,
+        Encoding::Windows874,
+        Encoding::Windows1250,
+        Encoding::Windows1251,
+        Encoding::Windows1252,
+        Encoding::Windows1253,
+        Encoding::Windows1254,
+        Encoding::Windows1255,
+        Encoding::Windows1256,
+        Encoding::Windows1257,
+        Encoding::Windows1258,
+        Encoding::XMacCyrillic,
+        Encoding::Gbk,
+        Encoding::Gb18030,
+        Encoding::Big5,
+        Encoding::EucJp,
+        Encoding::Iso2022Jp,
+        Encoding::ShiftJis,
+        Encoding::EucKr,
+        Encoding::Replacement,
+        Encoding::Utf16Be,
+        Encoding::Utf16Le,
+        Encoding::XUserDefined,
+    ];
+
+    /// To get an encoding from a string label, remove any leading and trailing ASCII whitespace from label, and then return the encoding that label is one of the labels of, compared ASCII case-insensitively, or failure if there is none.
+    ///
+    /// https://encoding.spec.whatwg.org/#concept-encoding-get
+    pub fn for_label(label: &str) -> Option<Encoding> {
+        let encoding = encoding_rs::Encoding::for_label(label.as_bytes())?;
+        Encoding::ALL
+            .into_iter()
+            .find(|candidate| candidate.decoder() == encoding)
+    }
+
+    /// The name of the encoding, as given by the Encoding standard.
+    pub fn name(self) -> &'static str {
+        self.decoder().name()
+    }
+
+    fn decoder(self) -> &'static encoding_rs::Encoding {
+        match self {
+            Encoding::Utf8 => encoding_rs::UTF_8,
+            Encoding::Ibm866 => encoding_rs::IBM866,
+            Encoding::Iso8859_2 => encoding_rs::ISO_8859_2,
+            Encoding::Iso8859_3 => encoding_rs::ISO_8859_3,
+            Encoding::Iso8859_4 => encoding_rs::ISO_8859_4,
+            Encoding::Iso8859_5 => encoding_rs::ISO_8859_5,
+            Encoding::Iso8859_6 => encoding_rs::ISO_8859_6,
+            Encoding::Iso8859_7 => encoding_rs::ISO_8859_7,
+            Encoding::Iso8859_8 => encoding_rs::ISO_8859_8,
+            Encoding::Iso8859_8I => encoding_rs::ISO_8859_8_I,
+            Encoding::Iso8859_10 => encoding_rs::ISO_8859_10,
+            Encoding::Iso8859_13 => encoding_rs::ISO_8859_13,
+            Encoding::Iso8859_14 => encoding_rs::ISO_8859_14,
+            Encoding::Iso8859_15 => encoding_rs::ISO_8859_15,
+            Encoding::Iso8859_16 => encoding_rs::ISO_8859_16,
+            Encoding::Koi8R => encoding_rs::KOI8_R,
+            Encoding::Koi8U => encoding_rs::KOI8_U,
+            Encoding::Macintosh => encoding_rs::MACINTOSH,
+            Encoding::Windows874 => encoding_rs::WINDOWS_874,
+            Encoding::Windows1250 => encoding_rs::WINDOWS_1250,
+            Encoding::Windows1251 => encoding_rs::WINDOWS_1251,
+            Encoding::Windows1252 => encoding_rs::WINDOWS_1252,
+            Encoding::Windows1253 => encoding_rs::WINDOWS_1253,
+            Encoding::Windows1254 => encoding_rs::WINDOWS_1254,
+            Encoding::Windows1255 => encoding_rs::WINDOWS_1255,
+            Encoding::Windows1256 => encoding_rs::WINDOWS_1256,
+            Encoding::Windows1257 => encoding_rs::WINDOWS_1257,
+            Encoding::Windows1258 => encoding_rs::WINDOWS_1258,
+            Encoding::XMacCyrillic => encoding_rs::X_MAC_CYRILLIC,
+            Encoding::Gbk => encoding_rs::GBK,
+            Encoding::Gb18030 => encoding_rs::GB18030,
+            Encoding::Big5 => encoding_rs::BIG5,
+            Encoding::EucJp => encoding_rs::EUC_JP,
+            Encoding::Iso2022Jp => encoding_rs::ISO_2022_JP,
+            Encoding::ShiftJis => encoding_rs::SHIFT_JIS,
+            Encoding::EucKr => encoding_rs::EUC_KR,
+            Encoding::Replacement => encoding_rs::REPLACEMENT,
+            Encoding::Utf16Be => encoding_rs::UTF_16BE,
+            Encoding::Utf16Le => encoding_rs::UTF_16LE,
+            Encoding::XUserDefined => encoding_rs::X_USER_DEFINED,
+        }
+    }
+
+    /// Decode `bytes` with this encoding, removing a byte order mark for it if there is one. Byte
+    /// sequences that are invalid in the encoding become U+FFFD REPLACEMENT CHARACTER.
+    ///
+    /// https://encoding.spec.whatwg.org/#decode
+    pub fn decode(self, bytes: &[u8]) -> String {
+        let (decoded, _) = self.decoder().decode_with_bom_removal(bytes);
+        decoded.into_owned()
+    }
+
+    /// A decoder for a byte stream in this encoding that arrives in chunks.
+    pub(crate) fn new_decoder(self) -> Decoder {
+        Decoder(self.decoder().new_decoder_with_bom_removal())
+    }
+
+    pub(crate) fn is_utf16(self) -> bool {
+        matches!(self, Encoding::Utf16Be | Encoding::Utf16Le)
+    }
+
+    /// Meta elements can't switch the parser to UTF-16 or x-user-defined: UTF-16 is replaced by
+    /// UTF-8, and x-user-defined by windows-1252.
+    pub(crate) fn for_meta_element(self) -> Encoding {
+        match self {
+            Encoding::Utf16Be | Encoding::Utf16Le => Encoding::Utf8,
+            Encoding::XUserDefined => Encoding::Windows1252,
+            encoding => encoding,
+        }
+    }
+}
+
+/// Decodes a byte stream that arrives in chunks. A character whose bytes are split between two
+/// chunks is kept until the rest of it arrives.
+pub(crate) struct Decoder(encoding_rs::Decoder);
+
+impl Decoder {
+    /// Decode the next chunk of the byte stream. `last` is set for the final chunk, so that an
+    /// incomplete character at its end becomes U+FFFD REPLACEMENT CHARACTER.
+    pub(crate) fn decode(&mut self, bytes: &[u8], last: bool) -> String {
+        let mut decoded = String::with_capacity(
+            self.0
+                .max_utf8_buffer_length(bytes.len())
+                .expect("chunks are far too small to overflow"),
+        );
+        let (_, read, _) = self.0.decode_to_string(bytes, &mut decoded, last);
+        debug_assert_eq!(read, bytes.len(), "the output has room for every character");
+        decoded
+    }
+}
+
+/// The number of bytes that are pre-scanned for a meta element declaring the encoding.
+pub(crate) const PRESCAN_LENGTH: usize = 1024;
+
+/// Determine the encoding of a byte stream, and the confidence in that encoding. Only the byte
+/// stream itself is examined: a byte order mark, then a meta element near the start of the
+/// document. If neither declares an encoding, windows-1252 is assumed.
+///
+/// https://html.spec.whatwg.org/multipage/parsing.html#encoding-sniffing-algorithm
+pub fn sniff(bytes: &[u8]) -> (Encoding, Confidence) {
+    if let Some(encoding) = bom_sniff(bytes) {
+        return (encoding, Confidence::Certain);
+    }
+    if let Some(encoding) = prescan(&bytes[..bytes.len().min(PRESCAN_LENGTH)]) {
+        return (encoding, Confidence::Tentative);
+    }
+    (Encoding::Windows1252, Confidence::Tentative)
+}
+
+/// https://encoding.spec.whatwg.org/#bom-sniff
+fn bom_sniff(bytes: &[u8]) -> Option<Encoding> {
+    match bytes {
+        [0xEF, 0xBB, 0xBF, ..] => Some(Encoding::Utf8),
+        [0xFE, 0xFF, ..] => Some(Encoding::Utf16Be),
+        [0xFF, 0xFE, ..] => Some(Encoding::Utf16Le),
+        _ => None,
+    }
+}
+
+fn is_whitespace(byte: u8) -> bool {
+    matches!(byte, 0x09 | 0x0A | 0x0C | 0x0D | 0x20)
+}
+
+/// Does `bytes` start with `prefix`, compared ASCII case-insensitively?
+fn starts_with_ignore_case(bytes: &[u8], prefix: &[u8]) -> bool {
+    bytes.len() >= prefix.len() && bytes[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+/// Prescan a byte stream to determine its encoding.
+///
+/// https://html.spec.whatwg.org/multipage/parsing.html#prescan-a-byte-stream-to-determine-its-encoding
+fn prescan(input: &[u8]) -> Option<Encoding> {
+    // Prescan for UTF-16 XML declarations.
+    if input.starts_with(&[0x3C, 0x00, 0x3F, 0x00, 0x78, 0x00]) {
+        return Some(Encoding::Utf16Le);
+    }
+    if input.starts_with(&[0x00, 0x3C, 0x00, 0x3F, 0x00, 0x78]) {
+        return Some(Encoding::Utf16Be);
+    }
+
+    let mut position = 0;
+    while position < input.len() {
+        let rest = &input[position..];
+        if rest.starts_with(b"<!--") {
+            // Advance the position pointer so that it points at the first 0x3E byte which is preceded by two 0x2D bytes (i.e. at the end of an ASCII '-->' sequence) and comes after the 0x3C byte that was found. (The two 0x2D bytes can be the same as those in the '<!--' sequence.)
+            position += rest[2..].windows(3).position(|w| w == b"-->")? + 4;
+        } else if starts_with_ignore_case(rest, b"<meta")
+            && rest.get(5).is_some_and(|&b| is_whitespace(b) || b == b'/')
+        {
+            position += 5;
+            if let Some(encoding) = prescan_meta(input, &mut position)? {
+                return Some(encoding.for_meta_element());
+            }
+        } else if rest.len() >= 2
+            && rest[0] == b'<'
+            && (rest[1].is_ascii_alphabetic()
+                || (rest[1] == b'/' && rest.get(2).is_some_and(u8::is_ascii_alphabetic)))
+        {
+            // Advance the position pointer so that it points at the next 0x09 (HT), 0x0A (LF), 0x0C (FF), 0x0D (CR), 0x20 (SP), or 0x3E (>) byte.
+            position += rest.iter().position(|&b| is_whitespace(b) || b == b'>')?;
+            // Repeatedly get an attribute until no further attributes can be found, then jump to the step below labeled next byte.
+            while get_attribute(input, &mut position)?.is_some() {}
+        } else if rest.starts_with(b"<!") || rest.starts_with(b"</") || rest.starts_with(b"<?") {
+            // Advance the position pointer so that it points at the first 0x3E byte (>) that comes after the 0x3C byte that was found.
+            position += rest.iter().position(|&b| b == b'>')?;
+        }
+        position += 1;
+    }
+    None
+}
+
+/// The steps of the prescan for a meta element, starting just after `<meta`. Returns `None` if
+/// the input ends first, and `Some(None)` if the element doesn't declare an encoding.
+fn prescan_meta(input: &[u8], position: &mut usize) -> Option<Option<Encoding>> {
+    let mut attribute_list = Vec::new();
+    let mut got_pragma = false;
+    let mut need_pragma = None;
+    let mut charset = None;
+
+    while let Some((name, value)) = get_attribute(input, position)? {
+        if attribute_list.contains(&name) {
+            continue;
+        }
+        match name.as_str() {
+            "http-equiv" if value == "content-type" => got_pragma = true,
+            "content" => {
+                if let Some(encoding) = extract_from_meta_content(&value) {
+                    if charset.is_none() {
+                        charset = Some(encoding);
+                        need_pragma = Some(true);
+                    }
+                }
+            }
+            "charset" if charset.is_none() => {
+                charset = Encoding::for_label(&value);
+                need_pragma = Some(false);
+            }
+            _ => {}
+        }
+        attribute_list.push(name);
+    }
+
+    // Processing: If need pragma is null, or if need pragma is true but got pragma is false, then jump to the step below labeled next byte.
+    Some(match need_pragma {
+        Some(true) if got_pragma => charset,
+        Some(false) => charset,
+        _ => None,
+    })
+}
+
+/// Get an attribute from the byte stream, returning its lowercased name and value. Returns
+/// `None` if the input ends first, and `Some(None)` if there are no more attributes.
+///
+/// https://html.spec.whatwg.org/multipage/parsing.html#concept-get-attributes-when-sniffing
+fn get_attribute(input: &[u8], position: &mut usize) -> Option<Option<(String, String)>> {
+    let byte = |position: usize| input.get(position).copied();
+
+    // If the byte at position is one of 0x09 (HT), 0x0A (LF), 0x0C (FF), 0x0D (CR), 0x20 (SP), or 0x2F (/) then advance position to the next byte and redo this step.
+    while byte(*position).is_some_and(|b| is_whitespace(b) || b == b'/') {
+        *position += 1;
+    }
+    if byte(*position)? == b'>' {
+        return Some(None);
+    }
+
+    let mut name = String::new();
+    let mut value = String::new();
+    loop {
+        match byte(*position)? {
+            b'=' if !name.is_empty() => {
+                *position += 1;
+                break;
+            }
+            b if is_whitespace(b) => {
+                while byte(*position).is_some_and(is_whitespace) {
+                    *position += 1;
+                }
+                if byte(*position)? != b'=' {
+                    return Some(Some((name, value)));
+                }
+                *position += 1;
+                break;
+            }
+            b'/' | b'>' => return Some(Some((name, value))),
+            b => name.push(b.to_ascii_lowercase() as char),
+        }
+        *position += 1;
+    }
+
+    while byte(*position).is_some_and(is_whitespace) {
+        *position += 1;
+    }
+    match byte(*position)? {
+        quote @ (b'"' | b'\'') => loop {
+            *position += 1;
+            let b = byte(*position)?;
+            if b == quote {
+                *position += 1;
+                return Some(Some((name, value)));
+            }
+            value.push(b.to_ascii_lowercase() as char);
+        },
+        b'>' => return Some(Some((name, value))),
+        _ => {}
+    }
+    loop {
+        match byte(*position)? {
+            b if is_whitespace(b) || b == b'>' => return Some(Some((name, value))),
+            b => value.push(b.to_ascii_lowercase() as char),
+        }
+        *position += 1;
+    }
+}
+
+/// The algorithm for extracting a character encoding from a meta element, given a string s
+/// (the value of its content attribute).
+///
+/// https://html.spec.whatwg.org/multipage/urls-and-fetching.html#algorithm-for-extracting-a-character-encoding-from-a-meta-element
+pub(crate) fn extract_from_meta_content(s: &str) -> Option<Encoding> {
+    let s = s.as_bytes();
+    let mut position = 0;
+    loop {
+        // Loop: Find the first seven characters in s after position that are an ASCII case-insensitive match for the word "charset". If no such match is found, return nothing.
+        position += (position..s.len()).find(|&i| starts_with_ignore_case(&s[i..], b"charset"))?
+            - position
+            + 7;
+        while s.get(position).is_some_and(|&b| is_whitespace(b)) {
+            position += 1;
+        }
+        // If the next character is not a U+003D EQUALS SIGN (=), then move position to point just before that next character, and jump back to the step labeled loop.
+        if s.get(position) == Some(&b'=') {
+            break;
+        }
+    }
+    position += 1;
+    while s.get(position).is_some_and(|&b| is_whitespace(b)) {
+        position += 1;
+    }
+    let label = match *s.get(position)? {
+        quote @ (b'"' | b'\'') => {
+            let end = s[position + 1..].iter().position(|&b| b == quote)?;
+            &s[position + 1..position + 1 + end]
+        }
+        _ => {
+            let end = s[position..]
+                .iter()
+                .position(|&b| is_whitespace(b) || b == b';')
+                .unwrap_or(s.len() - position);
+            &s[position..position + end]
+        }
+    };
+    Encoding::for_label(std::str::from_utf8(label).ok()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_order_marks() {
+        assert_eq!(
+            sniff(b"\xEF\xBB\xBF<meta charset=big5>"),
+            (Encoding::Utf8, Confidence::Certain)
+        );
+        assert_eq!(
+            sniff(b"\xFE\xFF\0<"),
+            (Encoding::Utf16Be, Confidence::Certain)
+        );
+        assert_eq!(
+            sniff(b"\xFF\xFE<\0"),
+            (Encoding::Utf16Le, Confidence::Certain)
+        );
+    }
+
+    #[test]
+    fn meta_prescan() {
+        assert_eq!(
+            sniff(b"<!DOCTYPE html><html><head><meta charset=\"Shift_JIS\">"),
+            (Encoding::ShiftJis, Confidence::Tentative)
+        );
+        assert_eq!(
+            sniff(b"<meta http-equiv=Content-Type content='text/html; charset=koi8-r'>"),
+            (Encoding::Koi8R, Confidence::Tentative)
+        );
+        // The content attribute is ignored without the http-equiv pragma.
+        assert_eq!(
+            sniff(b"<meta content='text/html; charset=koi8-r'>"),
+            (Encoding::Windows1252, Confidence::Tentative)
+        );
+        // Meta elements in comments and attribute values are skipped.
+        assert_eq!(
+            sniff(
+                b"<!-- <meta charset=gbk> --><p title='<meta charset=gbk>'><meta charset=euc-kr>"
+            ),
+            (Encoding::EucKr, Confidence::Tentative)
+        );
+        // Only the first charset counts, whether it's from a repeated attribute or the content.
+        assert_eq!(
+            sniff(b"<meta charset=koi8-r charset=gbk>"),
+            (Encoding::Koi8R, Confidence::Tentative)
+        );
+        assert_eq!(
+            sniff(
+                b"<meta http-equiv=Content-Type content='text/html; charset=koi8-r' charset=gbk>"
+            ),
+            (Encoding::Koi8R, Confidence::Tentative)
+        );
+        assert_eq!(
+            sniff(b"<meta charset=utf-16le>"),
+            (Encoding::Utf8, Confidence::Tentative)
+        );
+        assert_eq!(
+            sniff(b"<meta charset=x-user-defined>"),
+            (Encoding::Windows1252, Confidence::Tentative)
+        );
+    }
+
+    #[test]
+    fn prescan_stops_after_1024_bytes() {
+        let mut input = vec![b' '; PRESCAN_LENGTH];
+        input.extend_from_slice(b"<meta charset=utf-8>");
+        assert_eq!(
+            sniff(&input),
+            (Encoding::Windows1252, Confidence::Tentative)
+        );
+    }
+
+    #[test]
+    fn extracting_from_meta_content() {
+        assert_eq!(
+            extract_from_meta_content("text/html; charset=ISO-8859-2"),
+            Some(Encoding::Iso8859_2)
+        );
+        assert_eq!(
+            extract_from_meta_content("charsetx; CHARSET = \"windows-1251\""),
+            Some(Encoding::Windows1251)
+        );
+        assert_eq!(extract_from_meta_content("charset='utf-8"), None);
+        assert_eq!(extract_from_meta_content("text/html"), None);
+    }
+
+    #[test]
+    fn decoding() {
+        assert_eq!(Encoding::Windows1252.decode(b"caf\xE9 \x80"), "café €");
+        assert_eq!(Encoding::ShiftJis.decode(b"\x93\xfa\x96\x7b"), "日本");
+        assert_eq!(Encoding::Utf8.decode(b"\xEF\xBB\xBFa\xFF"), "a\u{FFFD}");
+        assert_eq!(Encoding::for_label(" latin1 "), Some(Encoding::Windows1252));
+        assert_eq!(Encoding::for_label("utf-7"), None);
+    }
 }
@@ -3,5 +3,9 @@
 
 pub mod dom;
 pub mod encoding;
+pub mod parse_error;
 pub mod parse_state;
 pub mod parser;
+pub mod serializer;
+pub mod source;
+pub mod tokenizer;
@@ -0,0 +1,183 @@
+// This file is based on section 13.2 of the HTML5 specification
+// The goal is to implement parse errors (13.2.2)
+// https://html.spec.whatwg.org/multipage/parsing.html#parse-errors
+
+/// A parse error found while parsing a document, and the byte offset into the decoded input at
+/// which it was found.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct ParseError {
+    pub code: ParseErrorCode,
+    pub offset: usize,
+}
+
+/// The codes from the table of parse errors, plus the errors of the tree construction stage,
+/// which the specification does not name.
+///
+/// https://html.spec.whatwg.org/multipage/parsing.html#parse-errors
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum ParseErrorCode {
+    AbruptClosingOfEmptyComment,
+    AbruptDoctypePublicIdentifier,
+    AbruptDoctypeSystemIdentifier,
+    AbsenceOfDigitsInNumericCharacterReference,
+    CdataInHtmlContent,
+    CharacterReferenceOutsideUnicodeRange,
+    ControlCharacterInInputStream,
+    ControlCharacterReference,
+    DuplicateAttribute,
+    EndTagWithAttributes,
+    EndTagWithTrailingSolidus,
+    EofBeforeTagName,
+    EofInCdata,
+    EofInComment,
+    EofInDoctype,
+    EofInScriptHtmlCommentLikeText,
+    EofInTag,
+    IncorrectlyClosedComment,
+    IncorrectlyOpenedComment,
+    InvalidCharacterSequenceAfterDoctypeName,
+    InvalidFirstCharacterOfTagName,
+    MissingAttributeValue,
+    MissingDoctypeName,
+    MissingDoctypePublicIdentifier,
+    MissingDoctypeSystemIdentifier,
+    MissingEndTagName,
+    MissingQuoteBeforeDoctypePublicIdentifier,
+    MissingQuoteBeforeDoctypeSystemIdentifier,
+    MissingSemicolonAfterCharacterReference,
+    MissingWhitespaceAfterDoctypePublicKeyword,
+    MissingWhitespaceAfterDoctypeSystemKeyword,
+    MissingWhitespaceBeforeDoctypeName,
+    MissingWhitespaceBetweenAttributes,
+    MissingWhitespaceBetweenDoctypePublicAndSystemIdentifiers,
+    NestedComment,
+    NoncharacterCharacterReference,
+    NoncharacterInInputStream,
+    NonVoidHtmlElementStartTagWithTrailingSolidus,
+    NullCharacterReference,
+    SurrogateCharacterReference,
+    SurrogateInInputStream,
+    UnexpectedCharacterAfterDoctypeSystemIdentifier,
+    UnexpectedCharacterInAttributeName,
+    UnexpectedCharacterInUnquotedAttributeValue,
+    UnexpectedEqualsSignBeforeAttributeName,
+    UnexpectedNullCharacter,
+    UnexpectedQuestionMarkInsteadOfTagName,
+    UnexpectedSolidusInTag,
+    UnknownNamedCharacterReference,
+
+    // Tree construction errors
+    /// The document does not start with a DOCTYPE.
+    MissingDoctype,
+    NonConformingDoctype,
+    /// A DOCTYPE appeared after the start of the document.
+    UnexpectedDoctype,
+    /// A start tag appeared where it is not allowed, and was ignored or moved.
+    UnexpectedStartTag,
+    /// An end tag appeared where it is not allowed, or did not match the current node.
+    UnexpectedEndTag,
+    /// Non-whitespace characters appeared where they are not allowed.
+    UnexpectedCharacter,
+    /// The input ended while elements were still open.
+    UnexpectedEof,
+}
+
+impl ParseErrorCode {
+    /// The code of the error, as it appears in the specification.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ParseErrorCode::AbruptClosingOfEmptyComment => "abrupt-closing-of-empty-comment",
+            ParseErrorCode::AbruptDoctypePublicIdentifier => "abrupt-doctype-public-identifier",
+            ParseErrorCode::AbruptDoctypeSystemIdentifier => "abrupt-doctype-system-identifier",
+            ParseErrorCode::AbsenceOfDigitsInNumericCharacterReference => {
+                "absence-of-digits-in-numeric-character-reference"
+            }
+            ParseErrorCode::CdataInHtmlContent => "cdata-in-html-content",
+            ParseErrorCode::CharacterReferenceOutsideUnicodeRange => {
+                "character-reference-outside-unicode-range"
+            }
+            ParseErrorCode::ControlCharacterInInputStream => "control-character-in-input-stream",
+            ParseErrorCode::ControlCharacterReference => "control-character-reference",
+            ParseErrorCode::DuplicateAttribute => "duplicate-attribute",
+            ParseErrorCode::EndTagWithAttributes => "end-tag-with-attributes",
+            ParseErrorCode::EndTagWithTrailingSolidus => "end-tag-with-trailing-solidus",
+            ParseErrorCode::EofBeforeTagName => "eof-before-tag-name",
+            ParseErrorCode::EofInCdata => "eof-in-cdata",
+            ParseErrorCode::EofInComment => "eof-in-comment",
+            ParseErrorCode::EofInDoctype => "eof-in-doctype",
+            ParseErrorCode::EofInScriptHtmlCommentLikeText => {
+                "eof-in-script-html-comment-like-text"
+            }
+            ParseErrorCode::EofInTag => "eof-in-tag",
+            ParseErrorCode::IncorrectlyClosedComment => "incorrectly-closed-comment",
+            ParseErrorCode::IncorrectlyOpenedComment => "incorrectly-opened-comment",
+            ParseErrorCode::InvalidCharacterSequenceAfterDoctypeName => {
+                "invalid-character-sequence-after-doctype-name"
+            }
+            ParseErrorCode::InvalidFirstCharacterOfTagName => "invalid-first-character-of-tag-name",
+            ParseErrorCode::MissingAttributeValue => "missing-attribute-value",
+            ParseErrorCode::MissingDoctypeName => "missing-doctype-name",
+            ParseErrorCode::MissingDoctypePublicIdentifier => "missing-doctype-public-identifier",
+            ParseErrorCode::MissingDoctypeSystemIdentifier => "missing-doctype-system-identifier",
+            ParseErrorCode::MissingEndTagName => "missing-end-tag-name",
+            ParseErrorCode::MissingQuoteBeforeDoctypePublicIdentifier => {
+                "missing-quote-before-doctype-public-identifier"
+            }
+            ParseErrorCode::MissingQuoteBeforeDoctypeSystemIdentifier => {
+                "missing-quote-before-doctype-system-identifier"
+            }
+            ParseErrorCode::MissingSemicolonAfterCharacterReference => {
+                "missing-semicolon-after-character-reference"
+            }
+            ParseErrorCode::MissingWhitespaceAfterDoctypePublicKeyword => {
+                "missing-whitespace-after-doctype-public-keyword"
+            }
+            ParseErrorCode::MissingWhitespaceAfterDoctypeSystemKeyword => {
+                "missing-whitespace-after-doctype-system-keyword"
+            }
+            ParseErrorCode::MissingWhitespaceBeforeDoctypeName => {
+                "missing-whitespace-before-doctype-name"
+            }
+            ParseErrorCode::MissingWhitespaceBetweenAttributes => {
+                "missing-whitespace-between-attributes"
+            }
+            ParseErrorCode::MissingWhitespaceBetweenDoctypePublicAndSystemIdentifiers => {
+                "missing-whitespace-between-doctype-public-and-system-identifiers"
+            }
+            ParseErrorCode::NestedComment => "nested-comment",
+            ParseErrorCode::NoncharacterCharacterReference => "noncharacter-character-reference",
+            ParseErrorCode::NoncharacterInInputStream => "noncharacter-in-input-stream",
+            ParseErrorCode::NonVoidHtmlElementStartTagWithTrailingSolidus => {
+                "non-void-html-element-start-tag-with-trailing-solidus"
+            }
+            ParseErrorCode::NullCharacterReference => "null-character-reference",
+            ParseErrorCode::SurrogateCharacterReference => "surrogate-character-reference",
+            ParseErrorCode::SurrogateInInputStream => "surrogate-in-input-stream",
+            ParseErrorCode::UnexpectedCharacterAfterDoctypeSystemIdentifier => {
+                "unexpected-character-after-doctype-system-identifier"
+            }
+            ParseErrorCode::UnexpectedCharacterInAttributeName => {
+                "unexpected-character-in-attribute-name"
+            }
+            ParseErrorCode::UnexpectedCharacterInUnquotedAttributeValue => {
+                "unexpected-character-in-unquoted-attribute-value"
+            }
+            ParseErrorCode::UnexpectedEqualsSignBeforeAttributeName => {
+                "unexpected-equals-sign-before-attribute-name"
+            }
+            ParseErrorCode::UnexpectedNullCharacter => "unexpected-null-character",
+            ParseErrorCode::UnexpectedQuestionMarkInsteadOfTagName => {
+                "unexpected-question-mark-instead-of-tag-name"
+            }
+            ParseErrorCode::UnexpectedSolidusInTag => "unexpected-solidus-in-tag",
+            ParseErrorCode::UnknownNamedCharacterReference => "unknown-named-character-reference",
+            ParseErrorCode::MissingDoctype => "missing-doctype",
+            ParseErrorCode::NonConformingDoctype => "non-conforming-doctype",
+            ParseErrorCode::UnexpectedDoctype => "unexpected-doctype",
+            ParseErrorCode::UnexpectedStartTag => "unexpected-start-tag",
+            ParseErrorCode::UnexpectedEndTag => "unexpected-end-tag",
+            ParseErrorCode::UnexpectedCharacter => "unexpected-character",
+            ParseErrorCode::UnexpectedEof => "unexpected-eof",
+        }
+    }
+}
@@ -2,44 +2,53 @@
 // The goal is to implement the parse state (13.2.4)
 // https://html.spec.whatwg.org/multipage/parsing.html#parse-state
 
-/// temp
-struct Element;
+use crate::{dom::NodeId, tokenizer::Tag};
 
-pub struct ParseState<'a> {
+pub(crate) struct ParseState {
     /// The insertion mode is a state variable that controls the primary operation of the tree construction stage.
     ///
     /// https://html.spec.whatwg.org/multipage/parsing.html#the-insertion-mode
-    insertion_mode: InsertionMode,
+    pub(crate) insertion_mode: InsertionMode,
+    /// When the insertion mode is switched to "text" or "in table text", the original insertion mode is also set. This is the insertion mode to which the tree construction stage will return.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#original-insertion-mode
+    pub(crate) original_insertion_mode: InsertionMode,
+    /// The stack of template insertion modes is used to handle the insertion modes of nested template elements. The current template insertion mode is the insertion mode that was most recently pushed onto the stack of template insertion modes.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#stack-of-template-insertion-modes
+    pub(crate) template_insertion_modes: Vec<InsertionMode>,
     /// Initially, the stack of open elements is empty. The stack grows downwards; the topmost node on the stack is the first one added to the stack, and the bottommost node of the stack is the most recently added node in the stack.
     ///
     /// https://html.spec.whatwg.org/multipage/parsing.html#the-stack-of-open-elements
-    open_elements: Vec<Element>,
+    pub(crate) open_elements: Vec<NodeId>,
     /// Initially, the list of active formatting elements is empty. It is used to handle mis-nested formatting element tags.
     ///
     /// https://html.spec.whatwg.org/multipage/parsing.html#the-list-of-active-formatting-elements
-    active_formatting_elements: Vec<Element>,
+    pub(crate) active_formatting_elements: Vec<ActiveFormattingElement>,
     /// Once a head element has been parsed (whether implicitly or explicitly) the head element pointer gets set to point to this node.
     ///
     /// https://html.spec.whatwg.org/multipage/parsing.html#the-element-pointers
-    head: Option<&'a Element>,
+    pub(crate) head: Option<NodeId>,
     /// The form element pointer points to the last form element that was opened and whose end tag has not yet been seen. It is used to make form controls associate with forms in the face of dramatically bad markup, for historical reasons.
     ///
     /// https://html.spec.whatwg.org/multipage/parsing.html#the-element-pointers
-    form: Option<&'a Element>,
+    pub(crate) form: Option<NodeId>,
     /// The scripting flag is set to "enabled" if scripting was enabled for the Document with which the parser is associated when the parser was created, and "disabled" otherwise.
     ///
     /// https://html.spec.whatwg.org/multipage/parsing.html#other-parsing-state-flags
-    scripting: Scripting,
+    pub(crate) scripting: Scripting,
     /// The frameset-ok flag is set to "ok" when the parser is created. It is set to "not ok" after certain tokens are seen.
     ///
     /// https://html.spec.whatwg.org/multipage/parsing.html#other-parsing-state-flags
-    frameset_ok: FramesetOk,
+    pub(crate) frameset_ok: FramesetOk,
 }
 
-impl Default for ParseState<'_> {
+impl Default for ParseState {
     fn default() -> Self {
         ParseState {
             insertion_mode: InsertionMode::Initial,
+            original_insertion_mode: InsertionMode::Initial,
+            template_insertion_modes: Vec::new(),
             open_elements: Vec::new(),
             active_formatting_elements: Vec::new(),
             head: None,
@@ -50,7 +59,19 @@ impl Default for ParseState<'_> {
     }
 }
 
-enum InsertionMode {
+/// An entry in the list of active formatting elements.
+///
+/// https://html.spec.whatwg.org/multipage/parsing.html#the-list-of-active-formatting-elements
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub(crate) enum ActiveFormattingElement {
+    /// Markers are inserted when entering applet, object, marquee, template, td, th, and caption elements, and are used to prevent formatting from "leaking" into applet, object, marquee, template, td, th, and caption elements.
+    Marker,
+    /// A formatting element, and the token for which it was created, so that it can be recreated.
+    Element(NodeId, Tag),
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub(crate) enum InsertionMode {
     Initial,
     BeforeHtml,
     BeforeHead,
@@ -77,12 +98,14 @@ enum InsertionMode {
     AfterAfterFrameset,
 }
 
-enum Scripting {
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub(crate) enum Scripting {
     Enabled,
     Disabled,
 }
 
-enum FramesetOk {
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub(crate) enum FramesetOk {
     Ok,
     NotOk,
 }
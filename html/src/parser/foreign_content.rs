@@ -0,0 +1,364 @@
+// This file is based on section 13.2.6.5 of the HTML5 specification
+// The goal is to implement the rules for parsing tokens in foreign content (13.2.6.5)
+// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inforeign
+
+use super::Parser;
+use crate::{
+    dom::{Namespace, NodeId},
+    parse_error::ParseErrorCode,
+    parse_state::FramesetOk,
+    tokenizer::{Tag, Token},
+};
+
+/// The tokenizer lowercases tag names, but some SVG elements have mixed-case names.
+///
+/// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inforeign
+static SVG_TAG_NAMES: &[(&str, &str)] = &[
+    ("altglyph", "altGlyph"),
+    ("altglyphdef", "altGlyphDef"),
+    ("altglyphitem", "altGlyphItem"),
+    ("animatecolor", "animateColor"),
+    ("animatemotion", "animateMotion"),
+    ("animatetransform", "animateTransform"),
+    ("clippath", "clipPath"),
+    ("feblend", "feBlend"),
+    ("fecolormatrix", "feColorMatrix"),
+    ("fecomponenttransfer", "feComponentTransfer"),
+    ("fecomposite", "feComposite"),
+    ("feconvolvematrix", "feConvolveMatrix"),
+    ("fediffuselighting", "feDiffuseLighting"),
+    ("fedisplacementmap", "feDisplacementMap"),
+    ("fedistantlight", "feDistantLight"),
+    ("fedropshadow", "feDropShadow"),
+    ("feflood", "feFlood"),
+    ("fefunca", "feFuncA"),
+    ("fefuncb", "feFuncB"),
+    ("fefuncg", "feFuncG"),
+    ("fefuncr", "feFuncR"),
+    ("fegaussianblur", "feGaussianBlur"),
+    ("feimage", "feImage"),
+    ("femerge", "feMerge"),
+    ("femergenode", "feMergeNode"),
+    ("femorphology", "feMorphology"),
+    ("feoffset", "feOffset"),
+    ("fepointlight", "fePointLight"),
+    ("fespecularlighting", "feSpecularLighting"),
+    ("fespotlight", "feSpotLight"),
+    ("fetile", "feTile"),
+    ("feturbulence", "feTurbulence"),
+    ("foreignobject", "foreignObject"),
+    ("glyphref", "glyphRef"),
+    ("lineargradient", "linearGradient"),
+    ("radialgradient", "radialGradient"),
+    ("textpath", "textPath"),
+];
+
+/// The mixed-case SVG attribute names.
+///
+/// https://html.spec.whatwg.org/multipage/parsing.html#adjust-svg-attributes
+static SVG_ATTRIBUTE_NAMES: &[(&str, &str)] = &[
+    ("attributename", "attributeName"),
+    ("attributetype", "attributeType"),
+    ("basefrequency", "baseFrequency"),
+    ("baseprofile", "baseProfile"),
+    ("calcmode", "calcMode"),
+    ("clippathunits", "clipPathUnits"),
+    ("diffuseconstant", "diffuseConstant"),
+    ("edgemode", "edgeMode"),
+    ("filterunits", "filterUnits"),
+    ("glyphref", "glyphRef"),
+    ("gradienttransform", "gradientTransform"),
+    ("gradientunits", "gradientUnits"),
+    ("kernelmatrix", "kernelMatrix"),
+    ("kernelunitlength", "kernelUnitLength"),
+    ("keypoints", "keyPoints"),
+    ("keysplines", "keySplines"),
+    ("keytimes", "keyTimes"),
+    ("lengthadjust", "lengthAdjust"),
+    ("limitingconeangle", "limitingConeAngle"),
+    ("markerheight", "markerHeight"),
+    ("markerunits", "markerUnits"),
+    ("markerwidth", "markerWidth"),
+    ("maskcontentunits", "maskContentUnits"),
+    ("maskunits", "maskUnits"),
+    ("numoctaves", "numOctaves"),
+    ("pathlength", "pathLength"),
+    ("patterncontentunits", "patternContentUnits"),
+    ("patterntransform", "patternTransform"),
+    ("patternunits", "patternUnits"),
+    ("pointsatx", "pointsAtX"),
+    ("pointsaty", "pointsAtY"),
+    ("pointsatz", "pointsAtZ"),
+    ("preservealpha", "preserveAlpha"),
+    ("preserveaspectratio", "preserveAspectRatio"),
+    ("primitiveunits", "primitiveUnits"),
+    ("refx", "refX"),
+    ("refy", "refY"),
+    ("repeatcount", "repeatCount"),
+    ("repeatdur", "repeatDur"),
+    ("requiredextensions", "requiredExtensions"),
+    ("requiredfeatures", "requiredFeatures"),
+    ("specularconstant", "specularConstant"),
+    ("specularexponent", "specularExponent"),
+    ("spreadmethod", "spreadMethod"),
+    ("startoffset", "startOffset"),
+    ("stddeviation", "stdDeviation"),
+    ("stitchtiles", "stitchTiles"),
+    ("surfacescale", "surfaceScale"),
+    ("systemlanguage", "systemLanguage"),
+    ("tablevalues", "tableValues"),
+    ("targetx", "targetX"),
+    ("targety", "targetY"),
+    ("textlength", "textLength"),
+    ("viewbox", "viewBox"),
+    ("viewtarget", "viewTarget"),
+    ("xchannelselector", "xChannelSelector"),
+    ("ychannelselector", "yChannelSelector"),
+    ("zoomandpan", "zoomAndPan"),
+];
+
+/// Start tags that end foreign content, because they are only ever meant as HTML.
+static BREAKOUT_TAGS: &[&str] = &[
+    "b",
+    "big",
+    "blockquote",
+    "body",
+    "br",
+    "center",
+    "code",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "embed",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "hr",
+    "i",
+    "img",
+    "li",
+    "listing",
+    "menu",
+    "meta",
+    "nobr",
+    "ol",
+    "p",
+    "pre",
+    "ruby",
+    "s",
+    "small",
+    "span",
+    "strong",
+    "strike",
+    "sub",
+    "sup",
+    "table",
+    "tt",
+    "u",
+    "ul",
+    "var",
+];
+
+/// When the steps below require the user agent to adjust MathML attributes for a token, then, if the token has an attribute named definitionurl, change its name to definitionURL (note the case difference).
+///
+/// https://html.spec.whatwg.org/multipage/parsing.html#adjust-mathml-attributes
+pub(super) fn adjust_mathml_attributes(tag: &mut Tag) {
+    for attribute in &mut tag.attributes {
+        if attribute.name == "definitionurl" {
+            attribute.name = "definitionURL".to_string();
+        }
+    }
+}
+
+/// When the steps below require the user agent to adjust SVG attributes for a token, then, for each attribute on the token whose attribute name is one of the ones in the first column of the following table, change the attribute's name to the name given in the corresponding cell in the second column.
+///
+/// The foreign attributes like `xlink:href` don't need adjusting, since attributes are stored by
+/// their qualified name.
+///
+/// https://html.spec.whatwg.org/multipage/parsing.html#adjust-svg-attributes
+pub(super) fn adjust_svg_attributes(tag: &mut Tag) {
+    for attribute in &mut tag.attributes {
+        if let Some(&(_, name)) = SVG_ATTRIBUTE_NAMES
+            .iter()
+            .find(|(lowercase, _)| *lowercase == attribute.name)
+        {
+            attribute.name = name.to_string();
+        }
+    }
+}
+
+fn adjust_svg_tag_name(tag: &mut Tag) {
+    if let Some(&(_, name)) = SVG_TAG_NAMES
+        .iter()
+        .find(|(lowercase, _)| *lowercase == tag.name)
+    {
+        tag.name = name.to_string();
+    }
+}
+
+impl Parser {
+    /// A node is a MathML text integration point if it is one of the following elements: A MathML mi element, a MathML mo element, a MathML mn element, a MathML ms element, a MathML mtext element.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#mathml-text-integration-point
+    fn is_mathml_text_integration_point(&self, node: NodeId) -> bool {
+        matches!(
+            self.element_name(node),
+            (Namespace::MathMl, "mi" | "mo" | "mn" | "ms" | "mtext")
+        )
+    }
+
+    /// A node is an HTML integration point if it is one of the following elements: A MathML annotation-xml element whose start tag token had an attribute with the name "encoding" whose value was an ASCII case-insensitive match for the string "text/html" or "application/xhtml+xml", an SVG foreignObject element, an SVG desc element, an SVG title element.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#html-integration-point
+    fn is_html_integration_point(&self, node: NodeId) -> bool {
+        match self.element_name(node) {
+            (Namespace::MathMl, "annotation-xml") => self
+                .document
+                .element(node)
+                .and_then(|elem| elem.attributes.get("encoding"))
+                .is_some_and(|encoding| {
+                    encoding.eq_ignore_ascii_case("text/html")
+                        || encoding.eq_ignore_ascii_case("application/xhtml+xml")
+                }),
+            (Namespace::Svg, name) => matches!(name, "foreignObject" | "desc" | "title"),
+            _ => false,
+        }
+    }
+
+    /// Should `token` be processed using the rules for parsing tokens in foreign content, rather
+    /// than those of the current insertion mode?
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#tree-construction-dispatcher
+    pub(super) fn is_foreign_content(&self, token: &Token) -> bool {
+        let Some(node) = self.adjusted_current_node() else {
+            return false;
+        };
+        let (namespace, name) = self.element_name(node);
+        let html_content = namespace == Namespace::Html
+            || (self.is_mathml_text_integration_point(node)
+                && match token {
+                    Token::StartTag(tag) => !matches!(tag.name.as_str(), "mglyph" | "malignmark"),
+                    Token::Character(_) => true,
+                    _ => false,
+                })
+            || (namespace == Namespace::MathMl
+                && name == "annotation-xml"
+                && matches!(token, Token::StartTag(tag) if tag.name == "svg"))
+            || (self.is_html_integration_point(node)
+                && matches!(token, Token::StartTag(_) | Token::Character(_)))
+            || *token == Token::Eof;
+        !html_content
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inforeign
+    pub(super) fn in_foreign_content(&mut self, token: Token) {
+        match token {
+            Token::Character('\0') => {
+                self.unexpected_token(&token);
+                self.insert_character('\u{FFFD}');
+            }
+            Token::Character(c @ ('\t' | '\n' | '\x0C' | '\r' | ' ')) => self.insert_character(c),
+            Token::Character(c) => {
+                self.insert_character(c);
+                self.parse_state.frameset_ok = FramesetOk::NotOk;
+            }
+            Token::Comment(data) => self.insert_comment(data),
+            Token::Doctype(_) => {
+                self.unexpected_token(&token);
+            }
+            Token::StartTag(ref tag)
+                if BREAKOUT_TAGS.contains(&tag.name.as_str())
+                    || (tag.name == "font"
+                        && tag.attributes.iter().any(|attr| {
+                            matches!(attr.name.as_str(), "color" | "face" | "size")
+                        })) =>
+            {
+                self.break_out_of_foreign_content(token);
+            }
+            Token::EndTag(ref tag) if matches!(tag.name.as_str(), "br" | "p") => {
+                self.break_out_of_foreign_content(token);
+            }
+            Token::StartTag(mut tag) => {
+                let namespace = self
+                    .adjusted_current_node()
+                    .map(|node| self.element_name(node).0)
+                    .unwrap_or_default();
+                match namespace {
+                    Namespace::MathMl => adjust_mathml_attributes(&mut tag),
+                    Namespace::Svg => {
+                        adjust_svg_tag_name(&mut tag);
+                        adjust_svg_attributes(&mut tag);
+                    }
+                    Namespace::Html => {}
+                }
+                self.insert_foreign_element(&tag, namespace);
+                // If the token has its self-closing flag set, then pop the current node off the
+                // stack of open elements and acknowledge the token's self-closing flag. Scripts
+                // are never run, so an SVG script element is handled the same way.
+                if tag.self_closing {
+                    self.parse_state.open_elements.pop();
+                    self.self_closing_acknowledged = true;
+                }
+            }
+            Token::EndTag(ref tag)
+                if tag.name == "script"
+                    && self.element_name(self.current_node()) == (Namespace::Svg, "script") =>
+            {
+                self.parse_state.open_elements.pop();
+            }
+            Token::EndTag(ref tag) => {
+                let matches_token = |parser: &Parser, node| {
+                    parser.element_name(node).1.eq_ignore_ascii_case(&tag.name)
+                };
+                // If node's tag name, converted to ASCII lowercase, is not the same as the tag name of the token, then this is a parse error.
+                if !matches_token(self, self.current_node()) {
+                    self.parse_error(ParseErrorCode::UnexpectedEndTag);
+                }
+                for index in (0..self.parse_state.open_elements.len()).rev() {
+                    let node = self.parse_state.open_elements[index];
+                    // If node is the topmost element in the stack of open elements, then return. (fragment case)
+                    if index == 0 {
+                        return;
+                    }
+                    // If node's tag name, converted to ASCII lowercase, is the same as the tag name of the token, pop elements from the stack of open elements until node has been popped from the stack, and then return.
+                    if matches_token(self, node) {
+                        self.parse_state.open_elements.truncate(index);
+                        return;
+                    }
+                    // If node is not an element in the HTML namespace, return to the step labeled loop.
+                    // Otherwise, process the token according to the rules given in the section corresponding to the current insertion mode in HTML content.
+                    let previous = self.parse_state.open_elements[index - 1];
+                    if self.element_name(previous).0 == Namespace::Html {
+                        self.process_token_using(self.parse_state.insertion_mode, token);
+                        return;
+                    }
+                }
+            }
+            Token::Eof => unreachable!("end-of-file tokens are never processed as foreign content"),
+        }
+    }
+
+    /// Pop elements until the current node is an HTML element or an integration point, and
+    /// process `token` there as HTML content.
+    fn break_out_of_foreign_content(&mut self, token: Token) {
+        self.unexpected_token(&token);
+        // While the current node is not a MathML text integration point, an HTML integration point, or an element in the HTML namespace, pop elements from the stack of open elements.
+        loop {
+            let node = self.current_node();
+            if self.is_mathml_text_integration_point(node)
+                || self.is_html_integration_point(node)
+                || self.element_name(node).0 == Namespace::Html
+            {
+                break;
+            }
+            self.parse_state.open_elements.pop();
+        }
+        self.process_token_using(self.parse_state.insertion_mode, token);
+    }
+}
@@ -0,0 +1,1453 @@
+mod foreign_content;
+mod tree_construction;
+
+use std::mem;
+
+use crate::{
+    dom::{AttrMap, Document, ElementData, Namespace, Node, NodeId, NodeType},
+    encoding::{self, Confidence, Decoder, Encoding},
+    parse_error::{ParseError, ParseErrorCode},
+    parse_state::{InsertionMode, ParseState, Scripting},
+    tokenizer::{State, Token, Tokenizer},
+};
+
+pub struct Parser {
+    // Properties from the specification
+    encoding: Encoding,
+    confidence: Confidence,
+    parse_state: ParseState,
+    /// Parsers have a script nesting level, which must be initially set to zero.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#overview-of-the-parsing-model
+    script_nesting_level: u32,
+    /// Parsers have a parser pause flag, which must be initially set to false.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#overview-of-the-parsing-model
+    pause_flag: bool,
+
+    // Properties for internal use
+    tokenizer: Tokenizer,
+    document: Document,
+    errors: Vec<ParseError>,
+    /// Set when the self-closing flag of the start tag being processed is acknowledged.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#acknowledge-self-closing-flag
+    self_closing_acknowledged: bool,
+    /// Set when a line feed at the start of the next token should be dropped, as it is after a
+    /// `<pre>` start tag.
+    ignore_line_feed: bool,
+    /// The undecoded input, kept so that parsing can restart if the encoding changes. A streaming
+    /// parser drops it once the encoding is certain, since a restart can no longer happen then.
+    bytes: Option<Vec<u8>>,
+    /// The decoder for input that arrives in chunks. It is created once enough of the input has
+    /// arrived to determine the encoding.
+    decoder: Option<Decoder>,
+    /// The script element that the parser is paused at, for the embedder to run.
+    ///
+    /// https://html.spec.whatwg.org/multipage/scripting.html#pending-parsing-blocking-script
+    pending_parsing_blocking_script: Option<NodeId>,
+    /// Set when a meta element changes the encoding, and parsing has to restart with it.
+    restart_with_encoding: Option<Encoding>,
+    /// When foster parenting is enabled, content that is misnested in a table is inserted before
+    /// the table instead.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#foster-parent
+    foster_parenting: bool,
+    /// The character tokens collected in the "in table text" insertion mode.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#concept-pending-table-char-tokens
+    pending_table_characters: Vec<char>,
+    /// The element whose contents are being parsed in the fragment case. It is not part of the
+    /// document tree.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#concept-frag-parse-context
+    context_element: Option<NodeId>,
+}
+
+impl Parser {
+    fn new(input: &str, encoding: Encoding, confidence: Confidence) -> Self {
+        Parser {
+            encoding,
+            confidence,
+            parse_state: ParseState::default(),
+            script_nesting_level: 0,
+            pause_flag: false,
+            tokenizer: Tokenizer::new(input),
+            document: Document::default(),
+            errors: Vec::new(),
+            self_closing_acknowledged: false,
+            ignore_line_feed: false,
+            bytes: None,
+            decoder: None,
+            pending_parsing_blocking_script: None,
+            restart_with_encoding: None,
+            foster_parenting: false,
+            pending_table_characters: Vec::new(),
+            context_element: None,
+        }
+    }
+
+    /// Decode `bytes` with `encoding`, keeping them in case the encoding changes later.
+    fn decode(bytes: Vec<u8>, encoding: Encoding, confidence: Confidence) -> Self {
+        let mut parser = Parser::new(&encoding.decode(&bytes), encoding, confidence);
+        parser.bytes = Some(bytes);
+        parser
+    }
+
+    /// Parse a string. No decoding is necessary, so the confidence is irrelevant.
+    pub fn from_string(input: &str) -> Self {
+        Parser::new(input, Encoding::Utf8, Confidence::Irrelevant)
+    }
+
+    /// Parse a byte stream in an unknown encoding, which is determined with the encoding sniffing
+    /// algorithm.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#encoding-sniffing-algorithm
+    pub fn from_bytes(input: Vec<u8>) -> Self {
+        let (encoding, confidence) = encoding::sniff(&input);
+        Parser::decode(input, encoding, confidence)
+    }
+
+    pub fn from_bytes_utf8(input: Vec<u8>) -> Self {
+        Parser::new(
+            &String::from_utf8_lossy(&input),
+            Encoding::Utf8,
+            Confidence::Certain,
+        )
+    }
+
+    pub fn from_bytes_utf16(input: Vec<u16>) -> Self {
+        Parser::new(
+            &String::from_utf16_lossy(&input),
+            Encoding::Utf16Le,
+            Confidence::Certain,
+        )
+    }
+
+    /// Parse a byte stream that arrives in chunks. Pass each chunk to `feed` as it arrives, then
+    /// call `finish` at the end of the stream. The encoding is determined with the encoding
+    /// sniffing algorithm once the first 1024 bytes have arrived, or the stream has ended.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#encoding-sniffing-algorithm
+    pub fn streaming() -> Self {
+        let mut parser = Parser::new("", Encoding::Windows1252, Confidence::Tentative);
+        parser.tokenizer = Tokenizer::streaming();
+        parser.bytes = Some(Vec::new());
+        parser
+    }
+
+    /// Enable scripting, as in a browsing context that runs scripts. `noscript` elements are then
+    /// parsed as raw text, and the parser pauses after each script element so that the embedder
+    /// can run it.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#scripting-flag
+    pub fn enable_scripting(&mut self) {
+        self.parse_state.scripting = Scripting::Enabled;
+        self.document.set_scripting_enabled(true);
+    }
+
+    /// The encoding that the input is decoded with.
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub fn confidence(&self) -> Confidence {
+        self.confidence
+    }
+
+    /// Change the encoding when a meta element declares one while the confidence is tentative.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#changing-the-encoding-while-parsing
+    fn change_encoding(&mut self, new_encoding: Encoding) {
+        if self.confidence != Confidence::Tentative {
+            return;
+        }
+        // If the encoding that is already being used to interpret the input stream is UTF-16BE/LE, then set the confidence to certain and return. The new encoding is ignored; if it was anything but the same encoding, then it would be clearly incorrect.
+        if self.encoding.is_utf16() {
+            self.confidence = Confidence::Certain;
+            return;
+        }
+        let new_encoding = new_encoding.for_meta_element();
+        if new_encoding == self.encoding {
+            self.confidence = Confidence::Certain;
+            return;
+        }
+        // Otherwise, restart the navigate algorithm, with historyHandling set to "replace" and other inputs kept the same, but this time skip the encoding sniffing algorithm and instead just set the encoding to the new encoding and the confidence to certain.
+        self.restart_with_encoding = Some(new_encoding);
+    }
+
+    /// The parse errors found so far, in the order they were found.
+    ///
+    /// Parse errors never stop the parser: it always recovers and produces a document.
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    /// Parse an HTML document and return it. Tree construction always creates an html element,
+    /// even for an empty document.
+    pub fn run(&mut self) -> Document {
+        self.process_tokens();
+        // There is no embedder to run scripts, so carry on past each one.
+        while self.pause_flag {
+            self.resume();
+        }
+        mem::take(&mut self.document)
+    }
+
+    /// Add the next chunk of a streaming parser's input, and parse as much of it as possible. The
+    /// last few characters are only parsed once more input arrives, since they might start a
+    /// longer token.
+    pub fn feed(&mut self, chunk: &[u8]) {
+        self.decode_chunk(chunk, false);
+    }
+
+    /// End a streaming parser's input, and parse the rest of it unless the parser is paused.
+    pub fn finish(&mut self) {
+        self.decode_chunk(&[], true);
+    }
+
+    fn decode_chunk(&mut self, chunk: &[u8], last: bool) {
+        let input = match &mut self.decoder {
+            Some(decoder) => {
+                if let Some(bytes) = &mut self.bytes {
+                    bytes.extend_from_slice(chunk);
+                }
+                decoder.decode(chunk, last)
+            }
+            None => {
+                let bytes = self
+                    .bytes
+                    .as_mut()
+                    .expect("only streaming parsers are fed chunks");
+                bytes.extend_from_slice(chunk);
+                // The user agent may wait for more bytes of the resource to be available, either in this step or at any later step in this algorithm. We wait for the bytes that are pre-scanned for a meta element.
+                if !last && bytes.len() < encoding::PRESCAN_LENGTH {
+                    return;
+                }
+                let (encoding, confidence) = encoding::sniff(bytes);
+                self.encoding = encoding;
+                self.confidence = confidence;
+                let mut decoder = encoding.new_decoder();
+                let input = decoder.decode(bytes, last);
+                self.decoder = Some(decoder);
+                input
+            }
+        };
+        self.tokenizer.push_str(&input);
+        if last {
+            self.tokenizer.end_input();
+        }
+        self.process_tokens();
+        // A meta element can only change the encoding while the confidence is tentative.
+        if self.confidence != Confidence::Tentative {
+            self.bytes = None;
+        }
+    }
+
+    /// The document as it has been parsed so far. While the input is still arriving, elements
+    /// that are open have only some of their children.
+    pub fn document(&self) -> &Document {
+        &self.document
+    }
+
+    /// Take the parsed document, once `finish` has been called and the parser isn't paused.
+    pub fn into_document(self) -> Document {
+        self.document
+    }
+
+    /// Whether the parser is paused, waiting for the embedder to run a script and call `resume`.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parser-pause-flag
+    pub fn is_paused(&self) -> bool {
+        self.pause_flag
+    }
+
+    /// The script element that the parser is paused at.
+    pub fn pending_parsing_blocking_script(&self) -> Option<NodeId> {
+        self.pending_parsing_blocking_script
+    }
+
+    /// Continue parsing after the embedder has run the script that the parser paused at.
+    pub fn resume(&mut self) {
+        self.pending_parsing_blocking_script = None;
+        self.pause_flag = false;
+        self.process_tokens();
+    }
+
+    /// Start parsing again from the beginning of the input, decoding it with `encoding`, which is
+    /// now certain.
+    fn restart(&mut self, bytes: Vec<u8>, encoding: Encoding) {
+        let scripting = self.parse_state.scripting;
+        match self.decoder {
+            None => *self = Parser::decode(bytes, encoding, Confidence::Certain),
+            Some(_) => {
+                // Decode the bytes that have arrived so far, and the rest as they arrive.
+                let input_complete = self.tokenizer.is_input_complete();
+                let mut decoder = encoding.new_decoder();
+                let mut parser = Parser::streaming();
+                parser.encoding = encoding;
+                parser.confidence = Confidence::Certain;
+                parser
+                    .tokenizer
+                    .push_str(&decoder.decode(&bytes, input_complete));
+                if input_complete {
+                    parser.tokenizer.end_input();
+                }
+                parser.decoder = Some(decoder);
+                // The encoding is certain now, so there won't be another restart.
+                parser.bytes = None;
+                *self = parser;
+            }
+        }
+        if scripting == Scripting::Enabled {
+            self.enable_scripting();
+        }
+    }
+
+    /// Parse the input as the contents of an HTML element named `context`, the way setting
+    /// `innerHTML` does, and return the resulting nodes. The context element is assumed to be in a
+    /// no-quirks document.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-html-fragments
+    pub fn run_fragment(&mut self, context: &str) -> Vec<Node> {
+        // The input is a string, so a meta element can't change its encoding.
+        self.confidence = Confidence::Irrelevant;
+        let context = context.to_ascii_lowercase();
+        // Set the state of the HTML parser's tokenization stage as follows, switching on the
+        // context element:
+        self.tokenizer.set_state(match context.as_str() {
+            "title" | "textarea" => State::Rcdata,
+            "style" | "xmp" | "iframe" | "noembed" | "noframes" => State::Rawtext,
+            "script" => State::ScriptData,
+            "noscript" if self.parse_state.scripting == Scripting::Enabled => State::Rawtext,
+            "plaintext" => State::Plaintext,
+            _ => State::Data,
+        });
+
+        let context_element = self.document.create_node(NodeType::Element(ElementData {
+            tag_name: context,
+            namespace: Namespace::Html,
+            attributes: AttrMap::new(),
+        }));
+        self.context_element = Some(context_element);
+
+        // Let root be the result of creating an element given inside, "html", the HTML namespace.
+        // Append the element root to the Document node created above. Set up the parser's stack
+        // of open elements so that it contains just the single element root.
+        let root = self.document.create_node(NodeType::Element(ElementData {
+            tag_name: "html".to_string(),
+            namespace: Namespace::Html,
+            attributes: AttrMap::new(),
+        }));
+        self.document.append(self.document.root(), root);
+        self.parse_state.open_elements.push(root);
+        // If the context element is a template element, push "in template" onto the stack of
+        // template insertion modes so that it is the new current template insertion mode.
+        if self.tag_name(context_element) == "template" {
+            self.parse_state
+                .template_insertion_modes
+                .push(InsertionMode::InTemplate);
+        }
+        self.reset_insertion_mode_appropriately();
+        // Set the HTML parser's form element pointer to the nearest node to the context element
+        // that is a form element (going straight up the ancestor chain, and including the element
+        // itself, if it is a form element), if any.
+        if self.tag_name(context_element) == "form" {
+            self.parse_state.form = Some(context_element);
+        }
+
+        self.process_tokens();
+        while self.pause_flag {
+            self.resume();
+        }
+        self.document
+            .children(root)
+            .map(|child| self.document.to_node(child))
+            .collect()
+    }
+
+    fn process_tokens(&mut self) {
+        // https://html.spec.whatwg.org/multipage/parsing.html#overview-of-the-parsing-model
+        // Each token emitted by the tokenizer is immediately handled by the tree construction stage.
+        // The tokenizer doesn't run while the parser is paused, or while it waits for more input.
+        while !self.pause_flag {
+            let Some(token) = self.tokenizer.try_next_token() else {
+                break;
+            };
+            self.errors.extend(self.tokenizer.take_errors());
+            if mem::take(&mut self.ignore_line_feed) && token == Token::Character('\n') {
+                continue;
+            }
+            let eof = token == Token::Eof;
+            let self_closing = matches!(&token, Token::StartTag(tag) if tag.self_closing);
+            self.self_closing_acknowledged = false;
+            self.process_token(token);
+            if let Some(encoding) = self.restart_with_encoding.take() {
+                if let Some(bytes) = self.bytes.take() {
+                    self.restart(bytes, encoding);
+                    continue;
+                }
+            }
+            // When a start tag token is emitted with its self-closing flag set, if the flag is not acknowledged when it is processed by the tree construction stage, that is a parse error.
+            if self_closing && !self.self_closing_acknowledged {
+                self.parse_error(ParseErrorCode::NonVoidHtmlElementStartTagWithTrailingSolidus);
+            }
+            // CDATA sections are only allowed if there is an adjusted current node and it is not an element in the HTML namespace.
+            let allow_cdata = self.adjusted_current_node().is_some_and(|node| {
+                self.document
+                    .element(node)
+                    .is_some_and(|elem| elem.namespace != Namespace::Html)
+            });
+            self.tokenizer.set_allow_cdata(allow_cdata);
+            if eof {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{dom::QuirksMode, serializer::serialize};
+
+    fn elem(name: &str, children: Vec<Node>) -> Node {
+        Node::elem(name.to_string(), HashMap::new(), children)
+    }
+
+    fn text(data: &str) -> Node {
+        Node::text(data.to_string())
+    }
+
+    fn comment(data: &str) -> Node {
+        Node::comment(data.to_string())
+    }
+
+    /// Return an owned copy of the document element of `document`.
+    fn document_element(document: &Document) -> Node {
+        let html = document
+            .document_element()
+            .expect("the parser always creates a document element");
+        document.to_node(html)
+    }
+
+    /// Parse `input` and return its document element.
+    fn parse(input: &str) -> Node {
+        document_element(&Parser::from_string(input).run())
+    }
+
+    #[test]
+    fn basic_tests() {
+        assert_eq!(
+            parse("<div></div>"),
+            elem(
+                "html",
+                vec![
+                    elem("head", vec![]),
+                    elem("body", vec![elem("div", vec![])])
+                ]
+            )
+        );
+
+        assert_eq!(
+            parse("<html><body>Hello, world!</body></html>"),
+            elem(
+                "html",
+                vec![
+                    elem("head", vec![]),
+                    elem("body", vec![text("Hello, world!")])
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn attributes() {
+        let mut attribute_map = HashMap::new();
+        attribute_map.insert("height".to_string(), "3".to_string());
+        attribute_map.insert("width".to_string(), "100%".to_string());
+
+        assert_eq!(
+            parse(r#"<div height="3" width="100%"></div>"#),
+            elem(
+                "html",
+                vec![
+                    elem("head", vec![]),
+                    elem(
+                        "body",
+                        vec![Node::elem("div".to_string(), attribute_map, Vec::new())]
+                    )
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn adds_root_node() {
+        assert_eq!(
+            parse("<h1>Heading 1</h1> <h2>Heading 2</h2>"),
+            elem(
+                "html",
+                vec![
+                    elem("head", vec![]),
+                    elem(
+                        "body",
+                        vec![
+                            elem("h1", vec![text("Heading 1")]),
+                            text(" "),
+                            elem("h2", vec![text("Heading 2")])
+                        ]
+                    )
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn head_and_body_are_implied() {
+        assert_eq!(
+            parse("<title>a <b></title><p>x"),
+            elem(
+                "html",
+                vec![
+                    elem("head", vec![elem("title", vec![text("a <b>")])]),
+                    elem("body", vec![elem("p", vec![text("x")])])
+                ]
+            )
+        );
+
+        assert_eq!(
+            parse(""),
+            elem("html", vec![elem("head", vec![]), elem("body", vec![])])
+        );
+    }
+
+    #[test]
+    fn head_content_after_body_start() {
+        assert_eq!(
+            parse("<html><head></head><style>p {}</style><body></body>"),
+            elem(
+                "html",
+                vec![
+                    elem("head", vec![elem("style", vec![text("p {}")])]),
+                    elem("body", vec![])
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn stray_end_tags_are_ignored() {
+        assert_eq!(
+            parse("</div><span>a</i>b</span></html>c"),
+            elem(
+                "html",
+                vec![
+                    elem("head", vec![]),
+                    elem("body", vec![elem("span", vec![text("ab")]), text("c")])
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn unclosed_elements_are_closed_at_eof() {
+        assert_eq!(
+            parse("<div><span>a"),
+            elem(
+                "html",
+                vec![
+                    elem("head", vec![]),
+                    elem(
+                        "body",
+                        vec![elem("div", vec![elem("span", vec![text("a")])])]
+                    )
+                ]
+            )
+        );
+    }
+
+    fn error_codes(input: &str) -> Vec<ParseErrorCode> {
+        let mut parser = Parser::from_string(input);
+        parser.run();
+        parser.errors().iter().map(|error| error.code).collect()
+    }
+
+    #[test]
+    fn malformed_markup_does_not_panic() {
+        for input in [
+            "<",
+            "</",
+            "<!",
+            "<!--",
+            "<!DOCTYPE",
+            "<div",
+            "<div a=\"",
+            "</html></body><body>",
+            "<frameset></frameset></frameset>x",
+            "<title>",
+            "<script><!--<script>",
+            "&#xFFFFFFFF;&",
+            "\0<\0>\0",
+        ] {
+            let root = parse(input);
+            assert!(matches!(root.node_type, NodeType::Element(elem) if elem.tag_name == "html"));
+        }
+
+        let root = document_element(&Parser::from_bytes_utf8(b"<p>\xFF</p>".to_vec()).run());
+        assert_eq!(
+            root,
+            elem(
+                "html",
+                vec![
+                    elem("head", vec![]),
+                    elem("body", vec![elem("p", vec![text("\u{FFFD}")])])
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(error_codes("<!DOCTYPE html><p>ok</p>"), vec![]);
+        assert_eq!(error_codes("<p>"), vec![ParseErrorCode::MissingDoctype]);
+        assert_eq!(
+            error_codes("<!DOCTYPE html><div><span></div>"),
+            vec![ParseErrorCode::UnexpectedEndTag]
+        );
+        assert_eq!(
+            error_codes("<!DOCTYPE html><div/>"),
+            vec![
+                ParseErrorCode::NonVoidHtmlElementStartTagWithTrailingSolidus,
+                ParseErrorCode::UnexpectedEof
+            ]
+        );
+        assert_eq!(
+            error_codes(r#"<!DOCTYPE html><meta charset="utf-8"/>"#),
+            vec![]
+        );
+        assert_eq!(error_codes("<!DOCTYPE html><br/><img/>"), vec![]);
+
+        let mut parser = Parser::from_string("<!DOCTYPE html><body></i>");
+        parser.run();
+        assert_eq!(
+            parser.errors(),
+            &[ParseError {
+                code: ParseErrorCode::UnexpectedEndTag,
+                offset: 21,
+            }]
+        );
+    }
+
+    #[test]
+    fn comments_and_doctype() {
+        assert_eq!(
+            {
+                let document = Parser::from_string(
+                    "<!DOCTYPE html><!--a--><html><!--b--><p><!--c--></p></html><!--d-->",
+                )
+                .run();
+                document.to_node(document.root())
+            },
+            Node::document(
+                QuirksMode::NoQuirks,
+                vec![
+                    Node::doctype("html".to_string(), String::new(), String::new()),
+                    comment("a"),
+                    elem(
+                        "html",
+                        vec![
+                            comment("b"),
+                            elem("head", vec![]),
+                            elem("body", vec![elem("p", vec![comment("c")])])
+                        ]
+                    ),
+                    comment("d")
+                ]
+            )
+        );
+
+        assert_eq!(
+            parse("<p>a</p></body><!--e-->"),
+            elem(
+                "html",
+                vec![
+                    elem("head", vec![]),
+                    elem("body", vec![elem("p", vec![text("a")])]),
+                    comment("e")
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn quirks_mode() {
+        fn mode(input: &str) -> QuirksMode {
+            Parser::from_string(input).run().mode()
+        }
+
+        assert_eq!(mode("<!DOCTYPE html>"), QuirksMode::NoQuirks);
+        assert_eq!(
+            mode("<!doctype HTML SYSTEM 'about:legacy-compat'>"),
+            QuirksMode::NoQuirks
+        );
+        assert_eq!(mode("<p>"), QuirksMode::Quirks);
+        assert_eq!(mode("<!DOCTYPE>"), QuirksMode::Quirks);
+        assert_eq!(mode("<!DOCTYPE svg>"), QuirksMode::Quirks);
+        assert_eq!(
+            mode(r#"<!DOCTYPE HTML PUBLIC "-//W3C//DTD HTML 4.01 Transitional//EN">"#),
+            QuirksMode::Quirks
+        );
+        assert_eq!(
+            mode(
+                r#"<!DOCTYPE HTML PUBLIC "-//W3C//DTD HTML 4.01 Transitional//EN" "http://www.w3.org/TR/html4/loose.dtd">"#
+            ),
+            QuirksMode::LimitedQuirks
+        );
+        assert_eq!(
+            mode(
+                r#"<!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Transitional//EN" "http://www.w3.org/TR/xhtml1/DTD/xhtml1-transitional.dtd">"#
+            ),
+            QuirksMode::LimitedQuirks
+        );
+        assert_eq!(
+            mode(
+                r#"<!DOCTYPE html PUBLIC "-//W3C//DTD HTML 4.01//EN" "http://www.w3.org/TR/html4/strict.dtd">"#
+            ),
+            QuirksMode::NoQuirks
+        );
+    }
+
+    /// Parse `input` and return the children of its body element.
+    fn parse_body(input: &str) -> Vec<Node> {
+        parse(input).children.pop().unwrap().children
+    }
+
+    fn attrs(attributes: &[(&str, &str)]) -> HashMap<String, String> {
+        attributes
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn void_elements() {
+        assert_eq!(
+            parse_body("a<br>b<img src=a.png><input type=text>c"),
+            vec![
+                text("a"),
+                elem("br", vec![]),
+                text("b"),
+                Node::elem("img".to_string(), attrs(&[("src", "a.png")]), vec![]),
+                Node::elem("input".to_string(), attrs(&[("type", "text")]), vec![]),
+                text("c")
+            ]
+        );
+        assert_eq!(
+            parse_body("<hr/><wbr/>x</br>"),
+            vec![
+                elem("hr", vec![]),
+                elem("wbr", vec![]),
+                text("x"),
+                elem("br", vec![])
+            ]
+        );
+    }
+
+    #[test]
+    fn self_closing_non_void_elements() {
+        // The self-closing flag is ignored on elements that aren't void.
+        assert_eq!(
+            parse_body("<div/>x<span/>y"),
+            vec![elem("div", vec![text("x"), elem("span", vec![text("y")])])]
+        );
+    }
+
+    #[test]
+    fn optional_end_tags() {
+        assert_eq!(
+            parse_body("<p>one<p>two<div>three</div></p>"),
+            vec![
+                elem("p", vec![text("one")]),
+                elem("p", vec![text("two")]),
+                elem("div", vec![text("three")]),
+                elem("p", vec![])
+            ]
+        );
+        assert_eq!(
+            parse_body("<ul><li>a<li>b<ul><li>c</ul></ul>"),
+            vec![elem(
+                "ul",
+                vec![
+                    elem("li", vec![text("a")]),
+                    elem(
+                        "li",
+                        vec![text("b"), elem("ul", vec![elem("li", vec![text("c")])])]
+                    )
+                ]
+            )]
+        );
+        assert_eq!(
+            parse_body("<dl><dt>a<dd>b<dt>c</dl>"),
+            vec![elem(
+                "dl",
+                vec![
+                    elem("dt", vec![text("a")]),
+                    elem("dd", vec![text("b")]),
+                    elem("dt", vec![text("c")])
+                ]
+            )]
+        );
+        assert_eq!(
+            parse_body("<select><option>a<option>b<optgroup><option>c</select>d"),
+            vec![
+                elem(
+                    "select",
+                    vec![
+                        elem("option", vec![text("a")]),
+                        elem("option", vec![text("b")]),
+                        elem("optgroup", vec![elem("option", vec![text("c")])])
+                    ]
+                ),
+                text("d")
+            ]
+        );
+        assert_eq!(
+            parse_body("<table><tbody><tr><td>a<td>b<tr><th>c</table>"),
+            vec![elem(
+                "table",
+                vec![elem(
+                    "tbody",
+                    vec![
+                        elem(
+                            "tr",
+                            vec![elem("td", vec![text("a")]), elem("td", vec![text("b")])]
+                        ),
+                        elem("tr", vec![elem("th", vec![text("c")])])
+                    ]
+                )]
+            )]
+        );
+        assert_eq!(
+            parse_body("<h1>a<h2>b</h1>"),
+            vec![elem("h1", vec![text("a")]), elem("h2", vec![text("b")])]
+        );
+        assert_eq!(
+            parse_body("<pre>\nx\n</pre>"),
+            vec![elem("pre", vec![text("x\n")])]
+        );
+    }
+
+    #[test]
+    fn from_bytes_sniffs_the_encoding() {
+        let mut parser = Parser::from_bytes(b"<p>caf\xE9</p>".to_vec());
+        let root = document_element(&parser.run());
+        assert_eq!(
+            (parser.encoding(), parser.confidence()),
+            (Encoding::Windows1252, Confidence::Tentative)
+        );
+        assert_eq!(
+            root.children[1],
+            elem("body", vec![elem("p", vec![text("caf\u{e9}")])])
+        );
+
+        let mut parser = Parser::from_bytes(b"\xEF\xBB\xBF<p>caf\xC3\xA9</p>".to_vec());
+        let root = document_element(&parser.run());
+        assert_eq!(
+            (parser.encoding(), parser.confidence()),
+            (Encoding::Utf8, Confidence::Certain)
+        );
+        assert_eq!(
+            root.children[1],
+            elem("body", vec![elem("p", vec![text("caf\u{e9}")])])
+        );
+    }
+
+    #[test]
+    fn meta_element_changes_the_encoding() {
+        // The meta element is beyond the bytes that are pre-scanned, so parsing starts with the
+        // fallback encoding and restarts when the meta element is found.
+        let mut input = b"<title>\x93\xfa\x96\x7b</title>".to_vec();
+        input.extend_from_slice(&[b' '; 1024]);
+        input.extend_from_slice(b"<meta charset=shift_jis>");
+        let mut parser = Parser::from_bytes(input);
+        let root = document_element(&parser.run());
+        assert_eq!(
+            (parser.encoding(), parser.confidence()),
+            (Encoding::ShiftJis, Confidence::Certain)
+        );
+        let head = &root.children[0];
+        assert_eq!(
+            head.children[0],
+            elem("title", vec![text("\u{65e5}\u{672c}")])
+        );
+    }
+
+    #[test]
+    fn raw_text_elements() {
+        assert_eq!(
+            parse("<style>p > a { content: '</p>' }</style><script>if (a<b && c>d) {}</script>"),
+            elem(
+                "html",
+                vec![
+                    elem(
+                        "head",
+                        vec![
+                            elem("style", vec![text("p > a { content: '</p>' }")]),
+                            elem("script", vec![text("if (a<b && c>d) {}")])
+                        ]
+                    ),
+                    elem("body", vec![])
+                ]
+            )
+        );
+        assert_eq!(
+            parse_body("<textarea>\n<b>&amp;</b></textarea><xmp><i></xmp><iframe><p></iframe>"),
+            vec![
+                elem("textarea", vec![text("<b>&</b>")]),
+                elem("xmp", vec![text("<i>")]),
+                elem("iframe", vec![text("<p>")])
+            ]
+        );
+        assert_eq!(
+            parse_body("<p>a<plaintext></plaintext>&amp;"),
+            vec![
+                elem("p", vec![text("a")]),
+                elem("plaintext", vec![text("</plaintext>&amp;")])
+            ]
+        );
+    }
+
+    #[test]
+    fn fragments() {
+        let fragment =
+            |input: &str, context: &str| Parser::from_string(input).run_fragment(context);
+
+        assert_eq!(
+            fragment("<p>a<p>b", "div"),
+            vec![elem("p", vec![text("a")]), elem("p", vec![text("b")])]
+        );
+        // Multiple roots are returned as they are, without an html, head or body element.
+        assert_eq!(
+            fragment("a<!--b--><br>", "body"),
+            vec![text("a"), comment("b"), elem("br", vec![])]
+        );
+        assert_eq!(
+            fragment("<tr><td>x</td></tr>", "tbody"),
+            vec![elem("tr", vec![elem("td", vec![text("x")])])]
+        );
+        // The context element decides the insertion mode...
+        assert_eq!(
+            fragment("<option>a<p>b</p><option>c", "select"),
+            vec![
+                elem("option", vec![text("ab")]),
+                elem("option", vec![text("c")])
+            ]
+        );
+        // ...and the tokenizer state.
+        assert_eq!(
+            fragment("a</textarea><b>&amp;", "textarea"),
+            vec![text("a</textarea><b>&")]
+        );
+        assert_eq!(
+            fragment("p > a {}</style>", "style"),
+            vec![text("p > a {}</style>")]
+        );
+        assert_eq!(fragment("", "div"), vec![]);
+    }
+
+    #[test]
+    fn misnested_formatting_elements() {
+        assert_eq!(
+            parse_body("<b><i>x</b>y</i>"),
+            vec![
+                elem("b", vec![elem("i", vec![text("x")])]),
+                elem("i", vec![text("y")])
+            ]
+        );
+        assert_eq!(
+            parse_body("<a><p>x</a>"),
+            vec![
+                elem("a", vec![]),
+                elem("p", vec![elem("a", vec![text("x")])])
+            ]
+        );
+        assert_eq!(
+            parse_body("<b>1<p>2</b>3</p>"),
+            vec![
+                elem("b", vec![text("1")]),
+                elem("p", vec![elem("b", vec![text("2")]), text("3")])
+            ]
+        );
+        // A new a element closes the open one.
+        assert_eq!(
+            parse_body("<a>1<a>2"),
+            vec![elem("a", vec![text("1")]), elem("a", vec![text("2")])]
+        );
+        // Formatting elements that are closed implicitly are reopened.
+        assert_eq!(
+            parse_body("<p><b>a<p>b"),
+            vec![
+                elem("p", vec![elem("b", vec![text("a")])]),
+                elem("p", vec![elem("b", vec![text("b")])])
+            ]
+        );
+        // But no more than three identical ones.
+        let b = |children| elem("b", children);
+        assert_eq!(
+            parse_body("<p><b><b><b><b>x<p>y"),
+            vec![
+                elem("p", vec![b(vec![b(vec![b(vec![b(vec![text("x")])])])])]),
+                elem("p", vec![b(vec![b(vec![b(vec![text("y")])])])])
+            ]
+        );
+        // Markers stop formatting from leaking into objects.
+        assert_eq!(
+            parse_body("<b><object><i>x</object>y"),
+            vec![elem(
+                "b",
+                vec![elem("object", vec![elem("i", vec![text("x")])]), text("y")]
+            )]
+        );
+    }
+
+    #[test]
+    fn implied_table_elements() {
+        let row = |cells| elem("tr", cells);
+        let td = |data| elem("td", vec![text(data)]);
+        assert_eq!(
+            parse_body("<table><tr><td>a<td>b<tr><td>c</table>"),
+            vec![elem(
+                "table",
+                vec![elem(
+                    "tbody",
+                    vec![row(vec![td("a"), td("b")]), row(vec![td("c")])]
+                )]
+            )]
+        );
+        assert_eq!(
+            parse_body("<table><td>a</table>"),
+            vec![elem("table", vec![elem("tbody", vec![row(vec![td("a")])])])]
+        );
+        assert_eq!(
+            parse_body("<table><caption>c<col><thead><tr><th>h<tbody><tr><td>a</table>"),
+            vec![elem(
+                "table",
+                vec![
+                    elem("caption", vec![text("c")]),
+                    elem("colgroup", vec![elem("col", vec![])]),
+                    elem("thead", vec![row(vec![elem("th", vec![text("h")])])]),
+                    elem("tbody", vec![row(vec![td("a")])])
+                ]
+            )]
+        );
+        // Whitespace stays in the table.
+        assert_eq!(
+            parse_body("<table> <tr> </tr> </table>"),
+            vec![elem(
+                "table",
+                vec![
+                    text(" "),
+                    elem("tbody", vec![row(vec![text(" ")]), text(" ")])
+                ]
+            )]
+        );
+        // Table tags outside of a table are ignored.
+        assert_eq!(parse_body("<tr><td>a</td></tr>"), vec![text("a")]);
+    }
+
+    #[test]
+    fn foster_parenting() {
+        assert_eq!(
+            parse_body("<table>a<tr><td>b</table>"),
+            vec![
+                text("a"),
+                elem(
+                    "table",
+                    vec![elem(
+                        "tbody",
+                        vec![elem("tr", vec![elem("td", vec![text("b")])])]
+                    )]
+                )
+            ]
+        );
+        assert_eq!(
+            parse_body("<p>x<table><tr><b>y</b>z<td>w</table>"),
+            vec![elem(
+                "p",
+                vec![
+                    text("x"),
+                    elem("b", vec![text("y")]),
+                    text("z"),
+                    elem(
+                        "table",
+                        vec![elem(
+                            "tbody",
+                            vec![elem("tr", vec![elem("td", vec![text("w")])])]
+                        )]
+                    )
+                ]
+            )]
+        );
+        // Formatting elements opened in the table are reconstructed after it.
+        assert_eq!(
+            parse_body("<table><b>x<tr><td>y</table>z"),
+            vec![
+                elem("b", vec![text("x")]),
+                elem(
+                    "table",
+                    vec![elem(
+                        "tbody",
+                        vec![elem("tr", vec![elem("td", vec![text("y")])])]
+                    )]
+                ),
+                elem("b", vec![text("z")])
+            ]
+        );
+        assert_eq!(
+            parse_body("<table><input type=hidden><input></table>"),
+            vec![
+                Node::elem("input".to_string(), attrs(&[]), vec![]),
+                Node::elem(
+                    "table".to_string(),
+                    attrs(&[]),
+                    vec![Node::elem(
+                        "input".to_string(),
+                        attrs(&[("type", "hidden")]),
+                        vec![]
+                    )]
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn selects_in_tables() {
+        assert_eq!(
+            parse_body("<table><tr><td><select><option>a<td>b</table>"),
+            vec![elem(
+                "table",
+                vec![elem(
+                    "tbody",
+                    vec![elem(
+                        "tr",
+                        vec![
+                            elem(
+                                "td",
+                                vec![elem("select", vec![elem("option", vec![text("a")])])]
+                            ),
+                            elem("td", vec![text("b")])
+                        ]
+                    )]
+                )]
+            )]
+        );
+    }
+
+    #[test]
+    fn foreign_content() {
+        let svg = |name: &str, attributes: &[(&str, &str)], children| {
+            Node::elem_ns(
+                Namespace::Svg,
+                name.to_string(),
+                attrs(attributes),
+                children,
+            )
+        };
+        let math = |name: &str, attributes: &[(&str, &str)], children| {
+            Node::elem_ns(
+                Namespace::MathMl,
+                name.to_string(),
+                attrs(attributes),
+                children,
+            )
+        };
+
+        // Tag and attribute names are case-adjusted, and elements can be self-closing.
+        assert_eq!(
+            parse_body("<svg viewbox='0 0 1 1'><lineargradient/><circle r=1 /><title>t</title>"),
+            vec![svg(
+                "svg",
+                &[("viewBox", "0 0 1 1")],
+                vec![
+                    svg("linearGradient", &[], vec![]),
+                    svg("circle", &[("r", "1")], vec![]),
+                    svg("title", &[], vec![text("t")])
+                ]
+            )]
+        );
+        assert_eq!(
+            parse_body("<math definitionurl=u><mi>x</mi></math>"),
+            vec![math(
+                "math",
+                &[("definitionURL", "u")],
+                vec![math("mi", &[], vec![text("x")])]
+            )]
+        );
+        // HTML integration points contain HTML elements.
+        assert_eq!(
+            parse_body("<svg><foreignobject><p>a</p></foreignobject></svg>"),
+            vec![svg(
+                "svg",
+                &[],
+                vec![svg("foreignObject", &[], vec![elem("p", vec![text("a")])])]
+            )]
+        );
+        assert_eq!(
+            parse_body("<math><annotation-xml encoding=text/html><b>a</b></annotation-xml>"),
+            vec![math(
+                "math",
+                &[],
+                vec![math(
+                    "annotation-xml",
+                    &[("encoding", "text/html")],
+                    vec![elem("b", vec![text("a")])]
+                )]
+            )]
+        );
+        // HTML elements break out of foreign content.
+        assert_eq!(
+            parse_body("<svg><g><p>a"),
+            vec![
+                svg("svg", &[], vec![svg("g", &[], vec![])]),
+                elem("p", vec![text("a")])
+            ]
+        );
+        // CDATA sections are only allowed in foreign content.
+        assert_eq!(
+            parse_body("<svg><![CDATA[a<b>]]></svg><![CDATA[c]]>"),
+            vec![svg("svg", &[], vec![text("a<b>")]), comment("[CDATA[c]]")]
+        );
+    }
+
+    #[test]
+    fn templates() {
+        let document = Parser::from_string(
+            "<template><tr><td>a</template><body><template><p>b<b>c</template>d",
+        )
+        .run();
+        let html = document.document_element().unwrap();
+        let templates: Vec<NodeId> = document
+            .descendants(html)
+            .filter(|&id| document.template_contents(id).is_some())
+            .collect();
+        assert_eq!(templates.len(), 2);
+        // Templates have no children; their contents are parsed into a separate fragment.
+        assert_eq!(
+            document_element(&document),
+            elem(
+                "html",
+                vec![
+                    elem("head", vec![elem("template", vec![])]),
+                    elem("body", vec![elem("template", vec![]), text("d")])
+                ]
+            )
+        );
+        let contents = |template| {
+            let contents = document.template_contents(template).unwrap();
+            document.to_node(contents).children
+        };
+        // Table parts don't need a table inside a template.
+        assert_eq!(
+            contents(templates[0]),
+            vec![elem("tr", vec![elem("td", vec![text("a")])])]
+        );
+        // Formatting elements don't leak out of templates.
+        assert_eq!(
+            contents(templates[1]),
+            vec![elem("p", vec![text("b"), elem("b", vec![text("c")])])]
+        );
+
+        assert_eq!(
+            Parser::from_string("<td>a<td>b").run_fragment("template"),
+            vec![elem("td", vec![text("a")]), elem("td", vec![text("b")])]
+        );
+    }
+
+    #[test]
+    fn source_spans() {
+        use crate::source::{SourcePosition, SourceSpan};
+
+        let span = |start: (usize, usize), end: (usize, usize)| {
+            Some(SourceSpan {
+                start: SourcePosition {
+                    line: start.0,
+                    column: start.1,
+                },
+                end: SourcePosition {
+                    line: end.0,
+                    column: end.1,
+                },
+            })
+        };
+        let document = Parser::from_string("<p class=a>x\ny</p>\n<!--c--><b>&amp;</b>").run();
+        let body = document
+            .children(document.document_element().unwrap())
+            .nth(1)
+            .unwrap();
+        let mut nodes = document.descendants(body);
+        let p = nodes.next().unwrap();
+        assert_eq!(document.span(p), span((1, 1), (1, 12)));
+        // Text nodes grow as characters are added to them.
+        let text = nodes.next().unwrap();
+        assert_eq!(document.span(text), span((1, 12), (2, 2)));
+        let newline = nodes.next().unwrap();
+        assert_eq!(document.span(newline), span((2, 6), (3, 1)));
+        let comment = nodes.next().unwrap();
+        assert_eq!(document.span(comment), span((3, 1), (3, 9)));
+        let b = nodes.next().unwrap();
+        assert_eq!(document.span(b), span((3, 9), (3, 12)));
+        let reference = nodes.next().unwrap();
+        assert_eq!(document.span(reference), span((3, 12), (3, 17)));
+        // Implied elements get the span of the token that caused them.
+        assert_eq!(document.span(body), document.span(p));
+        assert_eq!(document.span(document.root()), None);
+    }
+
+    #[test]
+    fn streaming() {
+        let mut input = b"<!DOCTYPE html><meta charset=utf-8><ul>".to_vec();
+        for i in 0..100 {
+            input.extend_from_slice(format!("<li>item {i}").as_bytes());
+        }
+        input.extend_from_slice(b"</ul><p>caf\xC3\xA9");
+        let expected = Parser::from_bytes(input.clone()).run();
+
+        let mut parser = Parser::streaming();
+        let mut items = Vec::new();
+        for chunk in input.chunks(100) {
+            parser.feed(chunk);
+            let html = serialize(parser.document(), parser.document().root());
+            items.push(html.matches("<li>").count());
+        }
+        // Nothing is parsed until the bytes that are pre-scanned for the encoding have arrived,
+        // and then the list grows as the rest of it arrives.
+        assert!(items[..10].iter().all(|&count| count == 0));
+        assert!(items.windows(2).all(|counts| counts[0] <= counts[1]));
+        assert!(items[10] > 0 && *items.last().unwrap() < 100);
+        parser.finish();
+        assert_eq!(
+            (parser.encoding(), parser.confidence()),
+            (Encoding::Utf8, Confidence::Certain)
+        );
+        let document = parser.into_document();
+        assert_eq!(
+            serialize(&document, document.root()),
+            serialize(&expected, expected.root())
+        );
+
+        // Characters split between chunks are decoded once the rest of them arrives.
+        let mut parser = Parser::streaming();
+        for byte in &input {
+            parser.feed(&[*byte]);
+        }
+        parser.finish();
+        let document = parser.into_document();
+        assert_eq!(
+            serialize(&document, document.root()),
+            serialize(&expected, expected.root())
+        );
+    }
+
+    #[test]
+    fn streaming_restarts_when_the_encoding_changes() {
+        // The unterminated comment in the script stops the prescan before it finds the meta
+        // element.
+        let mut input = b"<title>\x93\xfa\x96\x7b</title><script>\"<!--\"</script>".to_vec();
+        input.extend_from_slice(&[b' '; 900]);
+        input.extend_from_slice(b"<meta charset=shift_jis><p>");
+        input.extend_from_slice(&b"\x93\xfa\x96\x7b".repeat(100));
+        let mut parser = Parser::streaming();
+        for chunk in input.chunks(7) {
+            parser.feed(chunk);
+        }
+        assert!(parser.bytes.is_none());
+        parser.finish();
+        assert_eq!(
+            (parser.encoding(), parser.confidence()),
+            (Encoding::ShiftJis, Confidence::Certain)
+        );
+        let root = document_element(parser.document());
+        assert_eq!(
+            root.children[0].children[0],
+            elem("title", vec![text("\u{65e5}\u{672c}")])
+        );
+        assert_eq!(
+            root.children[1],
+            elem(
+                "body",
+                vec![elem("p", vec![text(&"\u{65e5}\u{672c}".repeat(100))])]
+            )
+        );
+    }
+
+    #[test]
+    fn streaming_matches_from_bytes_for_any_chunk_size() {
+        let mut input = b"<title>\x93\xfa\x96\x7b</title>".to_vec();
+        input.extend_from_slice(&[b' '; 1000]);
+        input.extend_from_slice(b"<meta charset=shift_jis>");
+        input.extend_from_slice(&[b' '; 200]);
+        let mut parser = Parser::from_bytes(input.clone());
+        let expected = parser.run();
+        assert_eq!(
+            (parser.encoding(), parser.confidence()),
+            (Encoding::ShiftJis, Confidence::Certain)
+        );
+        for size in [1, 7, 100, 1024, 4096] {
+            let mut parser = Parser::streaming();
+            for chunk in input.chunks(size) {
+                parser.feed(chunk);
+            }
+            parser.finish();
+            assert_eq!(
+                (parser.encoding(), parser.confidence()),
+                (Encoding::ShiftJis, Confidence::Certain),
+                "chunks of {size} bytes"
+            );
+            assert_eq!(
+                serialize(parser.document(), parser.document().root()),
+                serialize(&expected, expected.root()),
+                "chunks of {size} bytes"
+            );
+        }
+    }
+
+    #[test]
+    fn scripts_pause_the_parser() {
+        let mut parser = Parser::streaming();
+        parser.enable_scripting();
+        parser.feed(b"<p>a<script>one()</script><noscript><p>b</noscript><script>two()</script>c");
+        parser.finish();
+        let body = |parser: &Parser| {
+            let html = parser.document().document_element().unwrap();
+            let body = parser.document().children(html).nth(1).unwrap();
+            serialize(parser.document(), body)
+        };
+
+        // The parser stops after each script until the embedder has run it.
+        assert!(parser.is_paused());
+        let script = parser.pending_parsing_blocking_script().unwrap();
+        assert_eq!(parser.document().text_content(script), "one()");
+        assert_eq!(body(&parser), "<p>a<script>one()</script></p>");
+
+        parser.resume();
+        assert!(parser.is_paused());
+        let script = parser.pending_parsing_blocking_script().unwrap();
+        assert_eq!(parser.document().text_content(script), "two()");
+        // With scripting enabled, noscript elements contain text, which is serialized as it is.
+        assert_eq!(
+            body(&parser),
+            "<p>a<script>one()</script><noscript><p>b</noscript><script>two()</script></p>"
+        );
+
+        parser.resume();
+        assert!(!parser.is_paused());
+        assert_eq!(parser.pending_parsing_blocking_script(), None);
+        assert_eq!(
+            body(&parser),
+            "<p>a<script>one()</script><noscript><p>b</noscript><script>two()</script>c</p>"
+        );
+    }
+}
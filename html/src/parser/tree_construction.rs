@@ -0,0 +1,2700 @@
+// This file is based on section 13.2 of the HTML5 specification
+// The goal is to implement tree construction (13.2.6)
+// https://html.spec.whatwg.org/multipage/parsing.html#tree-construction
+
+use super::{
+    foreign_content::{adjust_mathml_attributes, adjust_svg_attributes},
+    Parser,
+};
+use crate::{
+    dom::{DoctypeData, ElementData, Namespace, NodeId, NodeType, QuirksMode},
+    encoding::{self, Encoding},
+    parse_error::{ParseError, ParseErrorCode},
+    parse_state::{ActiveFormattingElement, FramesetOk, InsertionMode, Scripting},
+    source::SourceSpan,
+    tokenizer::{Doctype, State, Tag, Token},
+};
+
+/// Is `c` one of U+0009 CHARACTER TABULATION, U+000A LINE FEED (LF), U+000C FORM FEED (FF), U+000D CARRIAGE RETURN (CR), or U+0020 SPACE?
+fn is_whitespace(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\x0C' | '\r' | ' ')
+}
+
+static HEADINGS: &[&str] = &["h1", "h2", "h3", "h4", "h5", "h6"];
+
+fn is_heading(tag_name: &str) -> bool {
+    HEADINGS.contains(&tag_name)
+}
+
+/// Elements in the special category have special parsing rules.
+///
+/// https://html.spec.whatwg.org/multipage/parsing.html#special
+fn is_special(tag_name: &str) -> bool {
+    matches!(
+        tag_name,
+        "address"
+            | "applet"
+            | "area"
+            | "article"
+            | "aside"
+            | "base"
+            | "basefont"
+            | "bgsound"
+            | "blockquote"
+            | "body"
+            | "br"
+            | "button"
+            | "caption"
+            | "center"
+            | "col"
+            | "colgroup"
+            | "dd"
+            | "details"
+            | "dir"
+            | "div"
+            | "dl"
+            | "dt"
+            | "embed"
+            | "fieldset"
+            | "figcaption"
+            | "figure"
+            | "footer"
+            | "form"
+            | "frame"
+            | "frameset"
+            | "h1"
+            | "h2"
+            | "h3"
+            | "h4"
+            | "h5"
+            | "h6"
+            | "head"
+            | "header"
+            | "hgroup"
+            | "hr"
+            | "html"
+            | "iframe"
+            | "img"
+            | "input"
+            | "keygen"
+            | "li"
+            | "link"
+            | "listing"
+            | "main"
+            | "marquee"
+            | "menu"
+            | "meta"
+            | "nav"
+            | "noembed"
+            | "noframes"
+            | "noscript"
+            | "object"
+            | "ol"
+            | "p"
+            | "param"
+            | "plaintext"
+            | "pre"
+            | "script"
+            | "search"
+            | "section"
+            | "select"
+            | "source"
+            | "style"
+            | "summary"
+            | "table"
+            | "tbody"
+            | "td"
+            | "template"
+            | "textarea"
+            | "tfoot"
+            | "th"
+            | "thead"
+            | "title"
+            | "tr"
+            | "track"
+            | "ul"
+            | "wbr"
+            | "xmp"
+    )
+}
+
+/// Public identifiers that put the document in quirks mode when a DOCTYPE's public identifier
+/// starts with them, compared ASCII case-insensitively.
+///
+/// https://html.spec.whatwg.org/multipage/parsing.html#the-initial-insertion-mode
+static QUIRKS_PUBLIC_ID_PREFIXES: &[&str] = &[
+    "+//Silmaril//dtd html Pro v0r11 19970101//",
+    "-//AS//DTD HTML 3.0 asWedit + extensions//",
+    "-//AdvaSoft Ltd//DTD HTML 3.0 asWedit + extensions//",
+    "-//IETF//DTD HTML 2.0 Level 1//",
+    "-//IETF//DTD HTML 2.0 Level 2//",
+    "-//IETF//DTD HTML 2.0 Strict Level 1//",
+    "-//IETF//DTD HTML 2.0 Strict Level 2//",
+    "-//IETF//DTD HTML 2.0 Strict//",
+    "-//IETF//DTD HTML 2.0//",
+    "-//IETF//DTD HTML 2.1E//",
+    "-//IETF//DTD HTML 3.0//",
+    "-//IETF//DTD HTML 3.2 Final//",
+    "-//IETF//DTD HTML 3.2//",
+    "-//IETF//DTD HTML 3//",
+    "-//IETF//DTD HTML Level 0//",
+    "-//IETF//DTD HTML Level 1//",
+    "-//IETF//DTD HTML Level 2//",
+    "-//IETF//DTD HTML Level 3//",
+    "-//IETF//DTD HTML Strict Level 0//",
+    "-//IETF//DTD HTML Strict Level 1//",
+    "-//IETF//DTD HTML Strict Level 2//",
+    "-//IETF//DTD HTML Strict Level 3//",
+    "-//IETF//DTD HTML Strict//",
+    "-//IETF//DTD HTML//",
+    "-//Metrius//DTD Metrius Presentational//",
+    "-//Microsoft//DTD Internet Explorer 2.0 HTML Strict//",
+    "-//Microsoft//DTD Internet Explorer 2.0 HTML//",
+    "-//Microsoft//DTD Internet Explorer 2.0 Tables//",
+    "-//Microsoft//DTD Internet Explorer 3.0 HTML Strict//",
+    "-//Microsoft//DTD Internet Explorer 3.0 HTML//",
+    "-//Microsoft//DTD Internet Explorer 3.0 Tables//",
+    "-//Netscape Comm. Corp.//DTD HTML//",
+    "-//Netscape Comm. Corp.//DTD Strict HTML//",
+    "-//O'Reilly and Associates//DTD HTML 2.0//",
+    "-//O'Reilly and Associates//DTD HTML Extended 1.0//",
+    "-//O'Reilly and Associates//DTD HTML Extended Relaxed 1.0//",
+    "-//SQ//DTD HTML 2.0 HoTMetaL + extensions//",
+    "-//SoftQuad Software//DTD HoTMetaL PRO 6.0::19990601::extensions to HTML 4.0//",
+    "-//SoftQuad//DTD HoTMetaL PRO 4.0::19971010::extensions to HTML 4.0//",
+    "-//Spyglass//DTD HTML 2.0 Extended//",
+    "-//Sun Microsystems Corp.//DTD HotJava HTML//",
+    "-//Sun Microsystems Corp.//DTD HotJava Strict HTML//",
+    "-//W3C//DTD HTML 3 1995-03-24//",
+    "-//W3C//DTD HTML 3.2 Draft//",
+    "-//W3C//DTD HTML 3.2 Final//",
+    "-//W3C//DTD HTML 3.2//",
+    "-//W3C//DTD HTML 3.2S Draft//",
+    "-//W3C//DTD HTML 4.0 Frameset//",
+    "-//W3C//DTD HTML 4.0 Transitional//",
+    "-//W3C//DTD HTML Experimental 19960712//",
+    "-//W3C//DTD HTML Experimental 970421//",
+    "-//W3C//DTD W3 HTML//",
+    "-//W3O//DTD W3 HTML 3.0//",
+    "-//WebTechs//DTD Mozilla HTML 2.0//",
+    "-//WebTechs//DTD Mozilla HTML//",
+];
+
+fn starts_with_ignore_ascii_case(s: &str, prefix: &str) -> bool {
+    s.len() >= prefix.len() && s.as_bytes()[..prefix.len()].eq_ignore_ascii_case(prefix.as_bytes())
+}
+
+/// Decide whether a DOCTYPE token puts the document in quirks mode, limited-quirks mode or
+/// no-quirks mode.
+///
+/// https://html.spec.whatwg.org/multipage/parsing.html#the-initial-insertion-mode
+fn quirks_mode_for(doctype: &Doctype) -> QuirksMode {
+    let public_id = doctype.public_identifier.as_deref();
+    let system_id = doctype.system_identifier.as_deref();
+    let public_id_is = |id: &str| public_id.is_some_and(|public| public.eq_ignore_ascii_case(id));
+    let public_id_starts_with = |prefix: &str| {
+        public_id.is_some_and(|public| starts_with_ignore_ascii_case(public, prefix))
+    };
+    let html_4_01 = public_id_starts_with("-//W3C//DTD HTML 4.01 Frameset//")
+        || public_id_starts_with("-//W3C//DTD HTML 4.01 Transitional//");
+
+    if doctype.force_quirks
+        || doctype.name.as_deref() != Some("html")
+        || public_id_is("-//W3O//DTD W3 HTML Strict 3.0//EN//")
+        || public_id_is("-/W3C/DTD HTML 4.0 Transitional/EN")
+        || public_id_is("HTML")
+        || system_id.is_some_and(|system| {
+            system
+                .eq_ignore_ascii_case("http://www.ibm.com/data/dtd/v11/ibmxhtml1-transitional.dtd")
+        })
+        || QUIRKS_PUBLIC_ID_PREFIXES
+            .iter()
+            .any(|prefix| public_id_starts_with(prefix))
+        || (system_id.is_none() && html_4_01)
+    {
+        QuirksMode::Quirks
+    } else if public_id_starts_with("-//W3C//DTD XHTML 1.0 Frameset//")
+        || public_id_starts_with("-//W3C//DTD XHTML 1.0 Transitional//")
+        || (system_id.is_some() && html_4_01)
+    {
+        QuirksMode::LimitedQuirks
+    } else {
+        QuirksMode::NoQuirks
+    }
+}
+
+/// The kinds of scope that an element can be looked for in the stack of open elements.
+///
+/// https://html.spec.whatwg.org/multipage/parsing.html#has-an-element-in-the-specific-scope
+#[derive(Clone, Copy)]
+enum Scope {
+    Default,
+    ListItem,
+    Button,
+    Table,
+    Select,
+}
+
+impl Scope {
+    /// Does an element named `tag_name` in `namespace` end the search for an element in this
+    /// scope?
+    fn is_boundary(self, namespace: Namespace, tag_name: &str) -> bool {
+        let default = match namespace {
+            Namespace::Html => matches!(
+                tag_name,
+                "applet"
+                    | "caption"
+                    | "html"
+                    | "table"
+                    | "td"
+                    | "th"
+                    | "marquee"
+                    | "object"
+                    | "template"
+            ),
+            Namespace::MathMl => {
+                matches!(
+                    tag_name,
+                    "mi" | "mo" | "mn" | "ms" | "mtext" | "annotation-xml"
+                )
+            }
+            Namespace::Svg => matches!(tag_name, "foreignObject" | "desc" | "title"),
+        };
+        if namespace != Namespace::Html {
+            // Only the default scopes include foreign elements, the others are made of HTML
+            // elements alone.
+            return match self {
+                Scope::Default | Scope::ListItem | Scope::Button => default,
+                Scope::Table => false,
+                Scope::Select => true,
+            };
+        }
+        match self {
+            Scope::Default => default,
+            Scope::ListItem => default || matches!(tag_name, "ol" | "ul"),
+            Scope::Button => default || tag_name == "button",
+            Scope::Table => matches!(tag_name, "html" | "table" | "template"),
+            Scope::Select => !matches!(tag_name, "optgroup" | "option"),
+        }
+    }
+}
+
+impl Parser {
+    /// Dispatch a token to the rules for the current insertion mode.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#tree-construction-dispatcher
+    pub(super) fn process_token(&mut self, token: Token) {
+        if self.is_foreign_content(&token) {
+            self.in_foreign_content(token);
+        } else {
+            self.process_token_using(self.parse_state.insertion_mode, token);
+        }
+    }
+
+    /// Process a token using the rules for the given insertion mode, without switching to it.
+    pub(super) fn process_token_using(&mut self, mode: InsertionMode, token: Token) {
+        match mode {
+            InsertionMode::Initial => self.initial(token),
+            InsertionMode::BeforeHtml => self.before_html(token),
+            InsertionMode::BeforeHead => self.before_head(token),
+            InsertionMode::InHead => self.in_head(token),
+            InsertionMode::InHeadNoscript => self.in_head_noscript(token),
+            InsertionMode::AfterHead => self.after_head(token),
+            InsertionMode::InBody => self.in_body(token),
+            InsertionMode::Text => self.text(token),
+            InsertionMode::AfterBody => self.after_body(token),
+            InsertionMode::InFrameset => self.in_frameset(token),
+            InsertionMode::AfterFrameset => self.after_frameset(token),
+            InsertionMode::AfterAfterBody => self.after_after_body(token),
+            InsertionMode::AfterAfterFrameset => self.after_after_frameset(token),
+            InsertionMode::InTable => self.in_table(token),
+            InsertionMode::InTableText => self.in_table_text(token),
+            InsertionMode::InCaption => self.in_caption(token),
+            InsertionMode::InColumnGroup => self.in_column_group(token),
+            InsertionMode::InTableBody => self.in_table_body(token),
+            InsertionMode::InRow => self.in_row(token),
+            InsertionMode::InCell => self.in_cell(token),
+            InsertionMode::InSelect => self.in_select(token),
+            InsertionMode::InSelectInTable => self.in_select_in_table(token),
+            InsertionMode::InTemplate => self.in_template(token),
+        }
+    }
+
+    /// Record a parse error at the start of the token being processed.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parse-errors
+    pub(super) fn parse_error(&mut self, code: ParseErrorCode) {
+        let offset = self.tokenizer.token_offset();
+        self.errors.push(ParseError { code, offset });
+    }
+
+    /// Record the parse error for a token that isn't allowed where it appears.
+    pub(super) fn unexpected_token(&mut self, token: &Token) {
+        let code = match token {
+            Token::Doctype(_) => ParseErrorCode::UnexpectedDoctype,
+            Token::StartTag(_) => ParseErrorCode::UnexpectedStartTag,
+            Token::EndTag(_) => ParseErrorCode::UnexpectedEndTag,
+            Token::Character('\0') => ParseErrorCode::UnexpectedNullCharacter,
+            Token::Character(_) | Token::Comment(_) => ParseErrorCode::UnexpectedCharacter,
+            Token::Eof => ParseErrorCode::UnexpectedEof,
+        };
+        self.parse_error(code);
+    }
+
+    fn switch_to(&mut self, mode: InsertionMode) {
+        self.parse_state.insertion_mode = mode;
+    }
+
+    /// Switch to `mode`, and reprocess the token there.
+    fn reprocess_in(&mut self, mode: InsertionMode, token: Token) {
+        self.switch_to(mode);
+        self.process_token(token);
+    }
+
+    // Helpers for the stack of open elements:
+
+    /// The current node is the bottommost node in the stack of open elements.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#current-node
+    pub(super) fn current_node(&self) -> NodeId {
+        *self
+            .parse_state
+            .open_elements
+            .last()
+            .expect("the stack of open elements is not empty during tree construction")
+    }
+
+    /// The tag name of `id` if it is an HTML element, and the empty string otherwise. Most of the
+    /// tree construction rules only look for HTML elements, so an SVG `title` element must not be
+    /// mistaken for an HTML one.
+    pub(super) fn tag_name(&self, id: NodeId) -> &str {
+        match self.element_name(id) {
+            (Namespace::Html, tag_name) => tag_name,
+            _ => "",
+        }
+    }
+
+    /// The namespace and tag name of the element `id`.
+    pub(super) fn element_name(&self, id: NodeId) -> (Namespace, &str) {
+        self.document
+            .element(id)
+            .map(|elem| (elem.namespace, elem.tag_name.as_str()))
+            .unwrap_or_default()
+    }
+
+    /// The adjusted current node is the context element if the parser was created as part of the HTML fragment parsing algorithm and the stack of open elements has only one element in it (fragment case); otherwise, the adjusted current node is the current node.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#adjusted-current-node
+    pub(super) fn adjusted_current_node(&self) -> Option<NodeId> {
+        match self.context_element {
+            Some(context) if self.parse_state.open_elements.len() == 1 => Some(context),
+            _ => self.parse_state.open_elements.last().copied(),
+        }
+    }
+
+    /// Is the element `id` in the special category?
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#special
+    fn is_special_element(&self, id: NodeId) -> bool {
+        match self.element_name(id) {
+            (Namespace::Html, tag_name) => is_special(tag_name),
+            (Namespace::MathMl, tag_name) => matches!(
+                tag_name,
+                "mi" | "mo" | "mn" | "ms" | "mtext" | "annotation-xml"
+            ),
+            (Namespace::Svg, tag_name) => matches!(tag_name, "foreignObject" | "desc" | "title"),
+        }
+    }
+
+    fn current_node_is(&self, tag_name: &str) -> bool {
+        self.parse_state
+            .open_elements
+            .last()
+            .is_some_and(|&id| self.tag_name(id) == tag_name)
+    }
+
+    /// The second element on the stack of open elements, if it is a body element.
+    fn body_element(&self) -> Option<NodeId> {
+        self.parse_state
+            .open_elements
+            .get(1)
+            .copied()
+            .filter(|&id| self.tag_name(id) == "body")
+    }
+
+    /// The stack of open elements is said to have a particular element in scope when it has that element in the specific scope consisting of the following element types: applet, caption, html, table, td, th, marquee, object, template.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#has-an-element-in-scope
+    fn has_element_in_scope(&self, tag_name: &str) -> bool {
+        self.has_element_in_specific_scope(&[tag_name], Scope::Default)
+    }
+
+    /// Does the stack of open elements have an element with one of `tag_names` in `scope`?
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#has-an-element-in-the-specific-scope
+    fn has_element_in_specific_scope(&self, tag_names: &[&str], scope: Scope) -> bool {
+        for &id in self.parse_state.open_elements.iter().rev() {
+            if tag_names.contains(&self.tag_name(id)) {
+                return true;
+            }
+            let (namespace, name) = self.element_name(id);
+            if scope.is_boundary(namespace, name) {
+                return false;
+            }
+        }
+        false
+    }
+
+    /// Is the element `node` in scope on the stack of open elements?
+    fn has_node_in_scope(&self, node: NodeId) -> bool {
+        for &id in self.parse_state.open_elements.iter().rev() {
+            if id == node {
+                return true;
+            }
+            let (namespace, name) = self.element_name(id);
+            if Scope::Default.is_boundary(namespace, name) {
+                return false;
+            }
+        }
+        false
+    }
+
+    /// Is there an element named `tag_name` anywhere on the stack of open elements?
+    fn stack_contains(&self, tag_name: &str) -> bool {
+        self.parse_state
+            .open_elements
+            .iter()
+            .any(|&id| self.tag_name(id) == tag_name)
+    }
+
+    /// Pop elements from the stack of open elements until an element with one of `tag_names` has
+    /// been popped from the stack.
+    fn pop_until(&mut self, tag_names: &[&str]) {
+        while let Some(id) = self.parse_state.open_elements.pop() {
+            if tag_names.contains(&self.tag_name(id)) {
+                return;
+            }
+        }
+    }
+
+    /// While the current node is a dd element, a dt element, an li element, an optgroup element, an option element, a p element, an rb element, an rp element, an rt element, or an rtc element, the UA must pop the current node off the stack of open elements. An element can be excluded from this by name.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#generate-implied-end-tags
+    fn generate_implied_end_tags(&mut self, except: Option<&str>) {
+        while let Some(&id) = self.parse_state.open_elements.last() {
+            let name = self.tag_name(id);
+            if Some(name) == except
+                || !matches!(
+                    name,
+                    "dd" | "dt" | "li" | "optgroup" | "option" | "p" | "rb" | "rp" | "rt" | "rtc"
+                )
+            {
+                return;
+            }
+            self.parse_state.open_elements.pop();
+        }
+    }
+
+    /// Like generating implied end tags, but also closing table elements.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#generate-all-implied-end-tags-thoroughly
+    fn generate_all_implied_end_tags_thoroughly(&mut self) {
+        while matches!(
+            self.tag_name(self.current_node()),
+            "caption"
+                | "colgroup"
+                | "dd"
+                | "dt"
+                | "li"
+                | "optgroup"
+                | "option"
+                | "p"
+                | "rb"
+                | "rp"
+                | "rt"
+                | "rtc"
+                | "tbody"
+                | "td"
+                | "tfoot"
+                | "th"
+                | "thead"
+                | "tr"
+        ) {
+            self.parse_state.open_elements.pop();
+        }
+    }
+
+    /// Pop elements from the stack of open elements until the current node is one of `tag_names`,
+    /// which is how the stack is cleared back to a table, table body or table row context.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#clear-the-stack-back-to-a-table-context
+    fn clear_stack_back_to(&mut self, tag_names: &[&str]) {
+        while !tag_names.contains(&self.tag_name(self.current_node())) {
+            self.parse_state.open_elements.pop();
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#close-a-p-element
+    fn close_p_element(&mut self) {
+        self.generate_implied_end_tags(Some("p"));
+        if !self.current_node_is("p") {
+            self.parse_error(ParseErrorCode::UnexpectedEndTag);
+        }
+        self.pop_until(&["p"]);
+    }
+
+    /// If the stack of open elements has a p element in button scope, then close a p element.
+    fn close_p_element_in_button_scope(&mut self) {
+        if self.has_element_in_specific_scope(&["p"], Scope::Button) {
+            self.close_p_element();
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#reset-the-insertion-mode-appropriately
+    pub(super) fn reset_insertion_mode_appropriately(&mut self) {
+        let open_elements = &self.parse_state.open_elements;
+        for (index, &node) in open_elements.iter().enumerate().rev() {
+            let last = index == 0;
+            // If node is the first node in the stack of open elements, then set last to true, and, if the parser was created as part of the HTML fragment parsing algorithm (fragment case), set node to the context element passed to that algorithm.
+            let node = match self.context_element {
+                Some(context) if last => context,
+                _ => node,
+            };
+            let mode = match self.tag_name(node) {
+                "select" => {
+                    // If an ancestor of the select is a table, and there is no template in between, the select is in a table.
+                    let in_table = open_elements[..index]
+                        .iter()
+                        .rev()
+                        .map(|&ancestor| self.tag_name(ancestor))
+                        .find(|&name| matches!(name, "table" | "template"))
+                        == Some("table");
+                    if in_table {
+                        InsertionMode::InSelectInTable
+                    } else {
+                        InsertionMode::InSelect
+                    }
+                }
+                "td" | "th" if !last => InsertionMode::InCell,
+                "tr" => InsertionMode::InRow,
+                "tbody" | "thead" | "tfoot" => InsertionMode::InTableBody,
+                "caption" => InsertionMode::InCaption,
+                "colgroup" => InsertionMode::InColumnGroup,
+                "table" => InsertionMode::InTable,
+                // If node is a template element, then switch the insertion mode to the current template insertion mode and return.
+                "template" => *self
+                    .parse_state
+                    .template_insertion_modes
+                    .last()
+                    .expect("template elements on the stack have a template insertion mode"),
+                "head" if !last => InsertionMode::InHead,
+                "body" => InsertionMode::InBody,
+                "frameset" => InsertionMode::InFrameset,
+                "html" if self.parse_state.head.is_none() => InsertionMode::BeforeHead,
+                "html" => InsertionMode::AfterHead,
+                _ if last => InsertionMode::InBody,
+                _ => continue,
+            };
+            self.switch_to(mode);
+            return;
+        }
+    }
+
+    // Helpers for the list of active formatting elements:
+
+    /// The index of the element `id` in the list of active formatting elements.
+    fn active_formatting_index(&self, id: NodeId) -> Option<usize> {
+        self.parse_state.active_formatting_elements.iter().position(
+            |entry| matches!(entry, ActiveFormattingElement::Element(element, _) if *element == id),
+        )
+    }
+
+    /// The last element named `tag_name` in the list of active formatting elements that is after
+    /// the last marker, with its index in the list.
+    fn formatting_element_after_last_marker(&self, tag_name: &str) -> Option<(usize, NodeId)> {
+        for (index, entry) in self
+            .parse_state
+            .active_formatting_elements
+            .iter()
+            .enumerate()
+            .rev()
+        {
+            match entry {
+                ActiveFormattingElement::Marker => return None,
+                ActiveFormattingElement::Element(id, tag) if tag.name == tag_name => {
+                    return Some((index, *id))
+                }
+                ActiveFormattingElement::Element(..) => {}
+            }
+        }
+        None
+    }
+
+    fn remove_from_active_formatting_elements(&mut self, id: NodeId) {
+        if let Some(index) = self.active_formatting_index(id) {
+            self.parse_state.active_formatting_elements.remove(index);
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#push-onto-the-list-of-active-formatting-elements
+    fn push_active_formatting_element(&mut self, element: NodeId, tag: &Tag) {
+        // If there are already three elements in the list of active formatting elements after the last marker, if any, or anywhere in the list if there are no markers, that have the same tag name, namespace, and attributes as element, then remove the earliest such element from the list of active formatting elements. For these purposes, the attributes must be compared as they were when the elements were created by the parser; two elements have the same attributes if all their parsed attributes can be paired such that the two attributes in each pair have identical names, namespaces, and values (the order of the attributes does not matter).
+        let list = &mut self.parse_state.active_formatting_elements;
+        let same: Vec<usize> = list
+            .iter()
+            .enumerate()
+            .rev()
+            .take_while(|(_, entry)| **entry != ActiveFormattingElement::Marker)
+            .filter(|(_, entry)| {
+                matches!(entry, ActiveFormattingElement::Element(_, other)
+                    if other.name == tag.name
+                        && other.attributes.len() == tag.attributes.len()
+                        && other.attributes.iter().all(|attr| tag.attributes.contains(attr)))
+            })
+            .map(|(index, _)| index)
+            .collect();
+        if let [.., _, _, earliest] = same[..] {
+            list.remove(earliest);
+        }
+        list.push(ActiveFormattingElement::Element(element, tag.clone()));
+    }
+
+    fn insert_marker(&mut self) {
+        self.parse_state
+            .active_formatting_elements
+            .push(ActiveFormattingElement::Marker);
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#clear-the-list-of-active-formatting-elements-up-to-the-last-marker
+    fn clear_active_formatting_elements_to_last_marker(&mut self) {
+        while let Some(entry) = self.parse_state.active_formatting_elements.pop() {
+            if entry == ActiveFormattingElement::Marker {
+                return;
+            }
+        }
+    }
+
+    /// Reopen the formatting elements that were closed implicitly, e.g. by the end of a paragraph,
+    /// so that `<p><b>a<p>b` makes both paragraphs bold.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#reconstruct-the-active-formatting-elements
+    fn reconstruct_active_formatting_elements(&mut self) {
+        // Every entry after the last marker or element that is still in the stack of open elements
+        // is recreated, in order.
+        let open_elements = &self.parse_state.open_elements;
+        let list = &self.parse_state.active_formatting_elements;
+        let start = list
+            .iter()
+            .rposition(|entry| match entry {
+                ActiveFormattingElement::Marker => true,
+                ActiveFormattingElement::Element(id, _) => open_elements.contains(id),
+            })
+            .map_or(0, |index| index + 1);
+        for index in start..list.len() {
+            if let ActiveFormattingElement::Element(old, tag) =
+                self.parse_state.active_formatting_elements[index].clone()
+            {
+                // Insert an HTML element for the token for which the element entry was created, to obtain new element. Replace the entry for entry in the list with an entry for new element.
+                let element = self.insert_html_element(&tag);
+                self.document.set_span(element, self.document.span(old));
+                self.parse_state.active_formatting_elements[index] =
+                    ActiveFormattingElement::Element(element, tag);
+            }
+        }
+    }
+
+    /// Fix up misnested formatting elements, such as `<b><i>x</b>y</i>`, when the end tag of a
+    /// formatting element is seen. Returns false if the end tag should instead be handled like
+    /// any other end tag.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#adoption-agency-algorithm
+    fn adoption_agency(&mut self, subject: &str) -> bool {
+        // If the current node is an HTML element whose tag name is subject, and the current node is not in the list of active formatting elements, then pop the current node off the stack of open elements and return.
+        let current = self.current_node();
+        if self.tag_name(current) == subject && self.active_formatting_index(current).is_none() {
+            self.parse_state.open_elements.pop();
+            return true;
+        }
+
+        // Repeat the outer loop at most eight times.
+        for _ in 0..8 {
+            // Let formatting element be the last element in the list of active formatting elements that is between the end of the list and the last marker in the list, if any, or the start of the list otherwise, and has the tag name subject. If there is no such element, then return and instead act as described in the "any other end tag" entry above.
+            let Some((_, formatting_element)) = self.formatting_element_after_last_marker(subject)
+            else {
+                return false;
+            };
+            let ActiveFormattingElement::Element(_, formatting_tag) =
+                self.parse_state.active_formatting_elements
+                    [self.active_formatting_index(formatting_element).unwrap()]
+                .clone()
+            else {
+                unreachable!("the formatting element is an element entry");
+            };
+
+            // If formatting element is not in the stack of open elements, then this is a parse error; remove the element from the list, and return.
+            let Some(formatting_stack_index) = self
+                .parse_state
+                .open_elements
+                .iter()
+                .position(|&id| id == formatting_element)
+            else {
+                self.parse_error(ParseErrorCode::UnexpectedEndTag);
+                self.remove_from_active_formatting_elements(formatting_element);
+                return true;
+            };
+            // If formatting element is in the stack of open elements, but the element is not in scope, then this is a parse error; return.
+            if !self.has_node_in_scope(formatting_element) {
+                self.parse_error(ParseErrorCode::UnexpectedEndTag);
+                return true;
+            }
+            // If formatting element is not the current node, this is a parse error. (But do not return.)
+            if formatting_element != self.current_node() {
+                self.parse_error(ParseErrorCode::UnexpectedEndTag);
+            }
+
+            // Let furthest block be the topmost node in the stack of open elements that is lower in the stack than formatting element, and is an element in the special category. There might not be one.
+            let furthest_block = self.parse_state.open_elements[formatting_stack_index + 1..]
+                .iter()
+                .position(|&id| self.is_special_element(id))
+                .map(|offset| formatting_stack_index + 1 + offset);
+            // If there is no furthest block, then the UA must first pop all the nodes from the bottom of the stack of open elements, from the current node up to and including formatting element, then remove formatting element from the list of active formatting elements, and finally return.
+            let Some(furthest_block_index) = furthest_block else {
+                self.parse_state
+                    .open_elements
+                    .truncate(formatting_stack_index);
+                self.remove_from_active_formatting_elements(formatting_element);
+                return true;
+            };
+            let furthest_block = self.parse_state.open_elements[furthest_block_index];
+
+            // Let common ancestor be the element immediately above formatting element in the stack of open elements.
+            let common_ancestor = self.parse_state.open_elements[formatting_stack_index - 1];
+            // Let a bookmark note the position of formatting element in the list of active formatting elements relative to the elements on either side of it in the list. `None` means that it is still at formatting element, and `Some(node)` that it is immediately after node.
+            let mut bookmark = None;
+
+            let mut node_index = furthest_block_index;
+            let mut last_node = furthest_block;
+            let mut inner_loop_counter = 0;
+            loop {
+                inner_loop_counter += 1;
+                // Let node be the element immediately above node in the stack of open elements, or if node is no longer in the stack of open elements (e.g. because it got removed by this algorithm), the element that was immediately above node in the stack of open elements before node was removed.
+                node_index -= 1;
+                let node = self.parse_state.open_elements[node_index];
+                // If node is formatting element, then break.
+                if node == formatting_element {
+                    break;
+                }
+                // If inner loop counter is greater than three and node is in the list of active formatting elements, then remove node from the list of active formatting elements.
+                if inner_loop_counter > 3 {
+                    self.remove_from_active_formatting_elements(node);
+                }
+                // If node is not in the list of active formatting elements, then remove node from the stack of open elements and continue.
+                let Some(list_index) = self.active_formatting_index(node) else {
+                    self.parse_state.open_elements.remove(node_index);
+                    continue;
+                };
+                // Create an element for the token for which the element node was created, in the HTML namespace, with common ancestor as the intended parent; replace the entry for node in the list of active formatting elements with an entry for the new element, replace the entry for node in the stack of open elements with an entry for the new element, and let node be the new element.
+                let ActiveFormattingElement::Element(old, tag) =
+                    self.parse_state.active_formatting_elements[list_index].clone()
+                else {
+                    unreachable!("markers are not on the stack of open elements");
+                };
+                let node = self.create_element_for_token(&tag, Namespace::Html);
+                self.document.set_span(node, self.document.span(old));
+                self.parse_state.active_formatting_elements[list_index] =
+                    ActiveFormattingElement::Element(node, tag);
+                self.parse_state.open_elements[node_index] = node;
+                // If last node is furthest block, then move the aforementioned bookmark to be immediately after the new node in the list of active formatting elements.
+                if last_node == furthest_block {
+                    bookmark = Some(node);
+                }
+                // Append last node to node.
+                self.document.append(node, last_node);
+                last_node = node;
+            }
+
+            // Insert whatever last node ended up being at the appropriate place for inserting a node, but using common ancestor as the override target.
+            let (parent, before) = self.appropriate_place_for_inserting(Some(common_ancestor));
+            self.document.insert(parent, last_node, before);
+
+            // Create an element for the token for which formatting element was created, in the HTML namespace, with furthest block as the intended parent.
+            let new_element = self.create_element_for_token(&formatting_tag, Namespace::Html);
+            self.document
+                .set_span(new_element, self.document.span(formatting_element));
+            // Take all of the child nodes of furthest block and append them to the element created in the last step.
+            let children: Vec<NodeId> = self.document.children(furthest_block).collect();
+            for child in children {
+                self.document.append(new_element, child);
+            }
+            // Append that new element to furthest block.
+            self.document.append(furthest_block, new_element);
+
+            // Remove formatting element from the list of active formatting elements, and insert the new element into the list of active formatting elements at the position of the aforementioned bookmark.
+            let entry = ActiveFormattingElement::Element(new_element, formatting_tag);
+            match bookmark {
+                Some(node) => {
+                    self.remove_from_active_formatting_elements(formatting_element);
+                    let index = self
+                        .active_formatting_index(node)
+                        .map_or(0, |index| index + 1);
+                    self.parse_state
+                        .active_formatting_elements
+                        .insert(index, entry);
+                }
+                None => {
+                    let index = self.active_formatting_index(formatting_element).unwrap();
+                    self.parse_state.active_formatting_elements[index] = entry;
+                }
+            }
+
+            // Remove formatting element from the stack of open elements, and insert the new element into the stack of open elements immediately below the position of furthest block in that stack.
+            self.parse_state
+                .open_elements
+                .retain(|&id| id != formatting_element);
+            let index = self
+                .parse_state
+                .open_elements
+                .iter()
+                .position(|&id| id == furthest_block)
+                .unwrap();
+            self.parse_state
+                .open_elements
+                .insert(index + 1, new_element);
+        }
+        true
+    }
+
+    // Creating and inserting nodes:
+
+    /// Create a node for the token being processed, spanning that token in the input.
+    fn create_node(&mut self, node_type: NodeType) -> NodeId {
+        let node = self.document.create_node(node_type);
+        self.document
+            .set_span(node, Some(self.tokenizer.token_span()));
+        node
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#create-an-element-for-the-token
+    fn create_element_for_token(&mut self, tag: &Tag, namespace: Namespace) -> NodeId {
+        let attributes = tag
+            .attributes
+            .iter()
+            .map(|attr| (attr.name.clone(), attr.value.clone()))
+            .collect();
+        self.create_node(NodeType::Element(ElementData {
+            tag_name: tag.name.clone(),
+            namespace,
+            attributes,
+        }))
+    }
+
+    /// The appropriate place for inserting a node, as a parent and the child to insert before, or
+    /// `None` to insert after the last child. This is the end of the override target if there is
+    /// one, and of the current node otherwise, unless foster parenting moves the node out of a
+    /// table.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#appropriate-place-for-inserting-a-node
+    fn appropriate_place_for_inserting(
+        &self,
+        override_target: Option<NodeId>,
+    ) -> (NodeId, Option<NodeId>) {
+        let (parent, before) = self.adjusted_insertion_location(override_target);
+        // If the adjusted insertion location is inside a template element, let it instead be inside the template element's template contents, after its last child (if any).
+        match self.document.template_contents(parent) {
+            Some(contents) => (contents, None),
+            None => (parent, before),
+        }
+    }
+
+    /// The steps of finding the appropriate place for inserting a node before template contents
+    /// are taken into account.
+    fn adjusted_insertion_location(
+        &self,
+        override_target: Option<NodeId>,
+    ) -> (NodeId, Option<NodeId>) {
+        let target = override_target.unwrap_or_else(|| self.current_node());
+        // If foster parenting is enabled and target is a table, tbody, tfoot, thead, or tr element
+        if !self.foster_parenting
+            || !matches!(
+                self.tag_name(target),
+                "table" | "tbody" | "tfoot" | "thead" | "tr"
+            )
+        {
+            return (target, None);
+        }
+        let open_elements = &self.parse_state.open_elements;
+        let last = |tag_name: &str| {
+            open_elements
+                .iter()
+                .rposition(|&id| self.tag_name(id) == tag_name)
+        };
+        match (last("template"), last("table")) {
+            // If there is a last template and either there is no last table, or there is one, but last template is lower (more recently added) than last table in the stack of open elements, then let adjusted insertion location be inside last template's template contents, after its last child (if any), and abort these steps.
+            (Some(template), table) if table.is_none_or(|table| template > table) => {
+                (open_elements[template], None)
+            }
+            // If there is no last table, then let adjusted insertion location be inside the first element in the stack of open elements (the html element), after its last child (if any), and abort these steps. (fragment case)
+            (_, None) => (open_elements[0], None),
+            (_, Some(table)) => match self.document.parent(open_elements[table]) {
+                // If last table has a parent node, then let adjusted insertion location be inside last table's parent node, immediately before last table, and abort these steps.
+                Some(parent) => (parent, Some(open_elements[table])),
+                // Let previous element be the element immediately above last table in the stack of open elements. Let adjusted insertion location be inside previous element, after its last child (if any).
+                None => (open_elements[table - 1], None),
+            },
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#insert-an-html-element
+    fn insert_html_element(&mut self, tag: &Tag) -> NodeId {
+        self.insert_foreign_element(tag, Namespace::Html)
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#insert-a-foreign-element
+    pub(super) fn insert_foreign_element(&mut self, tag: &Tag, namespace: Namespace) -> NodeId {
+        let (parent, before) = self.appropriate_place_for_inserting(None);
+        let element = self.create_element_for_token(tag, namespace);
+        self.document.insert(parent, element, before);
+        self.parse_state.open_elements.push(element);
+        element
+    }
+
+    /// Insert an element for a start tag and immediately pop it off the stack again, acknowledging
+    /// the token's self-closing flag.
+    fn insert_void_element(&mut self, tag: &Tag) {
+        self.insert_html_element(tag);
+        self.parse_state.open_elements.pop();
+        self.self_closing_acknowledged = true;
+    }
+
+    /// If there is a node in the stack of open elements that is not either a dd element, a dt element, an li element, an optgroup element, an option element, a p element, an rb element, an rp element, an rt element, an rtc element, a tbody element, a td element, a tfoot element, a th element, a thead element, a tr element, the body element, or the html element, then this is a parse error.
+    fn check_for_unclosed_elements(&mut self, code: ParseErrorCode) {
+        let unclosed = self.parse_state.open_elements.iter().any(|&id| {
+            !matches!(
+                self.tag_name(id),
+                "dd" | "dt"
+                    | "li"
+                    | "optgroup"
+                    | "option"
+                    | "p"
+                    | "rb"
+                    | "rp"
+                    | "rt"
+                    | "rtc"
+                    | "tbody"
+                    | "td"
+                    | "tfoot"
+                    | "th"
+                    | "thead"
+                    | "tr"
+                    | "body"
+                    | "html"
+            )
+        });
+        if unclosed {
+            self.parse_error(code);
+        }
+    }
+
+    /// Insert a comment at the appropriate place for inserting a node.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#insert-a-comment
+    pub(super) fn insert_comment(&mut self, data: String) {
+        let (parent, before) = self.appropriate_place_for_inserting(None);
+        let comment = self.create_node(NodeType::Comment(data));
+        self.document.insert(parent, comment, before);
+    }
+
+    /// Insert a comment as the last child of `parent`.
+    fn insert_comment_in(&mut self, parent: NodeId, data: String) {
+        let comment = self.create_node(NodeType::Comment(data));
+        self.document.append(parent, comment);
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#insert-a-character
+    pub(super) fn insert_character(&mut self, c: char) {
+        let (parent, before) = self.appropriate_place_for_inserting(None);
+        // If there is a Text node immediately before the adjusted insertion location, append the data to that Text node.
+        let previous = match before {
+            Some(before) => self.document.previous_sibling(before),
+            None => self.document.last_child(parent),
+        };
+        if let Some(previous) = previous {
+            if let NodeType::Text(data) = self.document.node_type_mut(previous) {
+                data.push(c);
+                let span = self.document.span(previous).map(|span| SourceSpan {
+                    end: self.tokenizer.token_span().end,
+                    ..span
+                });
+                self.document.set_span(previous, span);
+                return;
+            }
+        }
+        let text = self.create_node(NodeType::Text(c.to_string()));
+        self.document.insert(parent, text, before);
+    }
+
+    /// Add the attributes of `tag` that are not already present to the element `id`.
+    fn add_missing_attributes(&mut self, id: NodeId, tag: &Tag) {
+        if let NodeType::Element(elem) = self.document.node_type_mut(id) {
+            for attr in &tag.attributes {
+                elem.attributes
+                    .entry(attr.name.clone())
+                    .or_insert_with(|| attr.value.clone());
+            }
+        }
+    }
+
+    /// The generic raw text element parsing algorithm and the generic RCDATA element parsing algorithm.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#generic-raw-text-element-parsing-algorithm
+    fn parse_generic_text_element(&mut self, tag: &Tag, state: State) {
+        self.insert_html_element(tag);
+        self.tokenizer.set_state(state);
+        self.parse_state.original_insertion_mode = self.parse_state.insertion_mode;
+        self.switch_to(InsertionMode::Text);
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#stop-parsing
+    fn stop_parsing(&mut self) {
+        self.parse_state.open_elements.clear();
+    }
+
+    // The rules for each insertion mode:
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#the-initial-insertion-mode
+    fn initial(&mut self, token: Token) {
+        match token {
+            Token::Character(c) if is_whitespace(c) => {}
+            Token::Comment(data) => self.insert_comment_in(self.document.root(), data),
+            Token::Doctype(doctype) => {
+                // If the DOCTYPE token's name is not "html", or the token's public identifier is not missing, or the token's system identifier is neither missing nor "about:legacy-compat", then there is a parse error.
+                if doctype.name.as_deref() != Some("html")
+                    || doctype.public_identifier.is_some()
+                    || !matches!(
+                        doctype.system_identifier.as_deref(),
+                        None | Some("about:legacy-compat")
+                    )
+                {
+                    self.parse_error(ParseErrorCode::NonConformingDoctype);
+                }
+                // Append a DocumentType node to the Document node, with its name set to the name given in the DOCTYPE token, or the empty string if the name was missing; its public ID set to the public identifier given in the DOCTYPE token, or the empty string if the public identifier was missing; and its system ID set to the system identifier given in the DOCTYPE token, or the empty string if the system identifier was missing.
+                let mode = quirks_mode_for(&doctype);
+                let node = self.create_node(NodeType::Doctype(DoctypeData {
+                    name: doctype.name.unwrap_or_default(),
+                    public_id: doctype.public_identifier.unwrap_or_default(),
+                    system_id: doctype.system_identifier.unwrap_or_default(),
+                }));
+                self.document.append(self.document.root(), node);
+                self.document.set_mode(mode);
+                self.switch_to(InsertionMode::BeforeHtml);
+            }
+            _ => {
+                // If the document is not an iframe srcdoc document, then this is a parse error; set the Document to quirks mode.
+                self.parse_error(ParseErrorCode::MissingDoctype);
+                self.document.set_mode(QuirksMode::Quirks);
+                self.reprocess_in(InsertionMode::BeforeHtml, token);
+            }
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#the-before-html-insertion-mode
+    fn before_html(&mut self, token: Token) {
+        match token {
+            Token::Doctype(_) => {
+                self.unexpected_token(&token);
+            }
+            Token::Comment(data) => self.insert_comment_in(self.document.root(), data),
+            Token::Character(c) if is_whitespace(c) => {}
+            Token::StartTag(ref tag) if tag.name == "html" => {
+                let html = self.create_element_for_token(tag, Namespace::Html);
+                self.document.append(self.document.root(), html);
+                self.parse_state.open_elements.push(html);
+                self.switch_to(InsertionMode::BeforeHead);
+            }
+            Token::EndTag(ref tag)
+                if !matches!(tag.name.as_str(), "head" | "body" | "html" | "br") =>
+            {
+                self.unexpected_token(&token);
+            }
+            _ => {
+                let html = self.create_element_for_token(
+                    &Tag {
+                        name: "html".to_string(),
+                        ..Tag::default()
+                    },
+                    Namespace::Html,
+                );
+                self.document.append(self.document.root(), html);
+                self.parse_state.open_elements.push(html);
+                self.reprocess_in(InsertionMode::BeforeHead, token);
+            }
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#the-before-head-insertion-mode
+    fn before_head(&mut self, token: Token) {
+        match token {
+            Token::Character(c) if is_whitespace(c) => {}
+            Token::Comment(data) => self.insert_comment(data),
+            Token::Doctype(_) => {
+                self.unexpected_token(&token);
+            }
+            Token::StartTag(ref tag) if tag.name == "html" => self.in_body(token),
+            Token::StartTag(ref tag) if tag.name == "head" => {
+                let head = self.insert_html_element(tag);
+                self.parse_state.head = Some(head);
+                self.switch_to(InsertionMode::InHead);
+            }
+            Token::EndTag(ref tag)
+                if !matches!(tag.name.as_str(), "head" | "body" | "html" | "br") =>
+            {
+                self.unexpected_token(&token);
+            }
+            _ => {
+                let head = self.insert_html_element(&Tag {
+                    name: "head".to_string(),
+                    ..Tag::default()
+                });
+                self.parse_state.head = Some(head);
+                self.reprocess_in(InsertionMode::InHead, token);
+            }
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inhead
+    fn in_head(&mut self, token: Token) {
+        match token {
+            Token::Character(c) if is_whitespace(c) => self.insert_character(c),
+            Token::Comment(data) => self.insert_comment(data),
+            Token::Doctype(_) => {
+                self.unexpected_token(&token);
+            }
+            Token::StartTag(ref tag) if tag.name == "html" => self.in_body(token),
+            Token::StartTag(ref tag)
+                if matches!(tag.name.as_str(), "base" | "basefont" | "bgsound" | "link") =>
+            {
+                self.insert_void_element(tag);
+            }
+            Token::StartTag(ref tag) if tag.name == "meta" => {
+                self.insert_void_element(tag);
+                // If the element has a charset attribute, and getting an encoding from its value results in an encoding, and the confidence is currently tentative, then change the encoding to the resulting encoding. Otherwise, if the element has an http-equiv attribute whose value is an ASCII case-insensitive match for the string "Content-Type", and the element has a content attribute, and applying the algorithm for extracting a character encoding from a meta element to that attribute's value returns an encoding, and the confidence is currently tentative, then change the encoding to the extracted encoding.
+                let attribute = |name: &str| {
+                    tag.attributes
+                        .iter()
+                        .find(|attr| attr.name == name)
+                        .map(|attr| attr.value.as_str())
+                };
+                let encoding = attribute("charset")
+                    .and_then(Encoding::for_label)
+                    .or_else(|| {
+                        attribute("http-equiv")
+                            .filter(|value| value.eq_ignore_ascii_case("content-type"))
+                            .and(attribute("content"))
+                            .and_then(encoding::extract_from_meta_content)
+                    });
+                if let Some(encoding) = encoding {
+                    self.change_encoding(encoding);
+                }
+            }
+            Token::StartTag(ref tag) if tag.name == "title" => {
+                self.parse_generic_text_element(tag, State::Rcdata);
+            }
+            Token::StartTag(ref tag)
+                if (tag.name == "noscript" && self.parse_state.scripting == Scripting::Enabled)
+                    || matches!(tag.name.as_str(), "noframes" | "style") =>
+            {
+                self.parse_generic_text_element(tag, State::Rawtext);
+            }
+            Token::StartTag(ref tag) if tag.name == "noscript" => {
+                self.insert_html_element(tag);
+                self.switch_to(InsertionMode::InHeadNoscript);
+            }
+            Token::StartTag(ref tag) if tag.name == "script" => {
+                self.parse_generic_text_element(tag, State::ScriptData);
+            }
+            Token::EndTag(ref tag) if tag.name == "head" => {
+                self.parse_state.open_elements.pop();
+                self.switch_to(InsertionMode::AfterHead);
+            }
+            Token::StartTag(ref tag) if tag.name == "template" => {
+                self.insert_html_element(tag);
+                self.insert_marker();
+                self.parse_state.frameset_ok = FramesetOk::NotOk;
+                self.switch_to(InsertionMode::InTemplate);
+                self.parse_state
+                    .template_insertion_modes
+                    .push(InsertionMode::InTemplate);
+            }
+            Token::EndTag(ref tag) if tag.name == "template" => {
+                if !self.stack_contains("template") {
+                    self.unexpected_token(&token);
+                    return;
+                }
+                self.generate_all_implied_end_tags_thoroughly();
+                if !self.current_node_is("template") {
+                    self.unexpected_token(&token);
+                }
+                self.pop_until(&["template"]);
+                self.clear_active_formatting_elements_to_last_marker();
+                self.parse_state.template_insertion_modes.pop();
+                self.reset_insertion_mode_appropriately();
+            }
+            Token::StartTag(ref tag) if tag.name == "head" => {
+                self.unexpected_token(&token);
+            }
+            Token::EndTag(ref tag) if !matches!(tag.name.as_str(), "body" | "html" | "br") => {
+                self.unexpected_token(&token);
+            }
+            _ => {
+                self.parse_state.open_elements.pop();
+                self.reprocess_in(InsertionMode::AfterHead, token);
+            }
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inheadnoscript
+    fn in_head_noscript(&mut self, token: Token) {
+        match token {
+            Token::Doctype(_) => {
+                self.unexpected_token(&token);
+            }
+            Token::StartTag(ref tag) if tag.name == "html" => self.in_body(token),
+            Token::EndTag(ref tag) if tag.name == "noscript" => {
+                self.parse_state.open_elements.pop();
+                self.switch_to(InsertionMode::InHead);
+            }
+            Token::Character(c) if is_whitespace(c) => self.in_head(token),
+            Token::Comment(_) => self.in_head(token),
+            Token::StartTag(ref tag)
+                if matches!(
+                    tag.name.as_str(),
+                    "basefont" | "bgsound" | "link" | "meta" | "noframes" | "style"
+                ) =>
+            {
+                self.in_head(token)
+            }
+            Token::StartTag(ref tag) if matches!(tag.name.as_str(), "head" | "noscript") => {
+                self.unexpected_token(&token);
+            }
+            Token::EndTag(ref tag) if tag.name != "br" => {
+                self.unexpected_token(&token);
+            }
+            _ => {
+                self.unexpected_token(&token);
+                self.parse_state.open_elements.pop();
+                self.reprocess_in(InsertionMode::InHead, token);
+            }
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#the-after-head-insertion-mode
+    fn after_head(&mut self, token: Token) {
+        match token {
+            Token::Character(c) if is_whitespace(c) => self.insert_character(c),
+            Token::Comment(data) => self.insert_comment(data),
+            Token::Doctype(_) => {
+                self.unexpected_token(&token);
+            }
+            Token::StartTag(ref tag) if tag.name == "html" => self.in_body(token),
+            Token::StartTag(ref tag) if tag.name == "body" => {
+                self.insert_html_element(tag);
+                self.parse_state.frameset_ok = FramesetOk::NotOk;
+                self.switch_to(InsertionMode::InBody);
+            }
+            Token::StartTag(ref tag) if tag.name == "frameset" => {
+                self.insert_html_element(tag);
+                self.switch_to(InsertionMode::InFrameset);
+            }
+            Token::StartTag(ref tag)
+                if matches!(
+                    tag.name.as_str(),
+                    "base"
+                        | "basefont"
+                        | "bgsound"
+                        | "link"
+                        | "meta"
+                        | "noframes"
+                        | "script"
+                        | "style"
+                        | "template"
+                        | "title"
+                ) =>
+            {
+                self.unexpected_token(&token);
+                // Push the node pointed to by the head element pointer onto the stack of open elements, process the token using the rules for the "in head" insertion mode, and remove that node from the stack again.
+                let head = self
+                    .parse_state
+                    .head
+                    .expect("the head element pointer is set after the before head insertion mode");
+                self.parse_state.open_elements.push(head);
+                self.in_head(token);
+                self.parse_state.open_elements.retain(|&id| id != head);
+            }
+            Token::StartTag(ref tag) if tag.name == "head" => {
+                self.unexpected_token(&token);
+            }
+            Token::EndTag(ref tag) if !matches!(tag.name.as_str(), "body" | "html" | "br") => {
+                self.unexpected_token(&token);
+            }
+            _ => {
+                self.insert_html_element(&Tag {
+                    name: "body".to_string(),
+                    ..Tag::default()
+                });
+                self.reprocess_in(InsertionMode::InBody, token);
+            }
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inbody
+    fn in_body(&mut self, token: Token) {
+        match token {
+            Token::Character('\0') => {
+                self.unexpected_token(&token);
+            }
+            Token::Character(c) if is_whitespace(c) => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_character(c);
+            }
+            Token::Character(c) => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_character(c);
+                self.parse_state.frameset_ok = FramesetOk::NotOk;
+            }
+            Token::Comment(data) => self.insert_comment(data),
+            Token::Doctype(_) => {
+                self.unexpected_token(&token);
+            }
+            Token::StartTag(ref tag) if tag.name == "html" => {
+                self.unexpected_token(&token);
+                // If there is a template element on the stack of open elements, then ignore the token.
+                if self.stack_contains("template") {
+                    return;
+                }
+                // For each attribute on the token, check to see if the attribute is already present on the top element of the stack of open elements. If it is not, add the attribute and its corresponding value to that element.
+                let html = self.parse_state.open_elements[0];
+                self.add_missing_attributes(html, tag);
+            }
+            Token::StartTag(ref tag)
+                if matches!(
+                    tag.name.as_str(),
+                    "base"
+                        | "basefont"
+                        | "bgsound"
+                        | "link"
+                        | "meta"
+                        | "noframes"
+                        | "script"
+                        | "style"
+                        | "template"
+                        | "title"
+                ) =>
+            {
+                self.in_head(token)
+            }
+            Token::EndTag(ref tag) if tag.name == "template" => self.in_head(token),
+            Token::StartTag(ref tag) if tag.name == "body" => {
+                self.unexpected_token(&token);
+                if let Some(body) = self
+                    .body_element()
+                    .filter(|_| !self.stack_contains("template"))
+                {
+                    self.parse_state.frameset_ok = FramesetOk::NotOk;
+                    self.add_missing_attributes(body, tag);
+                }
+            }
+            Token::StartTag(ref tag) if tag.name == "frameset" => {
+                self.unexpected_token(&token);
+                let body = self.body_element();
+                if let (Some(body), FramesetOk::Ok) = (body, self.parse_state.frameset_ok) {
+                    self.document.detach(body);
+                    self.parse_state.open_elements.truncate(1);
+                    self.insert_html_element(tag);
+                    self.switch_to(InsertionMode::InFrameset);
+                }
+            }
+            Token::StartTag(ref tag)
+                if matches!(
+                    tag.name.as_str(),
+                    "address"
+                        | "article"
+                        | "aside"
+                        | "blockquote"
+                        | "center"
+                        | "details"
+                        | "dialog"
+                        | "dir"
+                        | "div"
+                        | "dl"
+                        | "fieldset"
+                        | "figcaption"
+                        | "figure"
+                        | "footer"
+                        | "header"
+                        | "hgroup"
+                        | "main"
+                        | "menu"
+                        | "nav"
+                        | "ol"
+                        | "p"
+                        | "search"
+                        | "section"
+                        | "summary"
+                        | "ul"
+                ) =>
+            {
+                self.close_p_element_in_button_scope();
+                self.insert_html_element(tag);
+            }
+            Token::StartTag(ref tag) if is_heading(&tag.name) => {
+                self.close_p_element_in_button_scope();
+                if is_heading(self.tag_name(self.current_node())) {
+                    self.unexpected_token(&token);
+                    self.parse_state.open_elements.pop();
+                }
+                self.insert_html_element(tag);
+            }
+            Token::StartTag(ref tag) if matches!(tag.name.as_str(), "pre" | "listing") => {
+                self.close_p_element_in_button_scope();
+                self.insert_html_element(tag);
+                // If the next token is a U+000A LINE FEED (LF) character token, then ignore that token and move on to the next one. (Newlines at the start of pre blocks are ignored as an authoring convenience.)
+                self.ignore_line_feed = true;
+                self.parse_state.frameset_ok = FramesetOk::NotOk;
+            }
+            Token::StartTag(ref tag) if tag.name == "form" => {
+                // If the form element pointer is not null, and there is no template element on the stack of open elements, then this is a parse error; ignore the token.
+                let in_template = self.stack_contains("template");
+                if self.parse_state.form.is_some() && !in_template {
+                    self.unexpected_token(&token);
+                    return;
+                }
+                self.close_p_element_in_button_scope();
+                let form = self.insert_html_element(tag);
+                if !in_template {
+                    self.parse_state.form = Some(form);
+                }
+            }
+            Token::StartTag(ref tag) if tag.name == "li" => self.start_list_item(tag, &["li"]),
+            Token::StartTag(ref tag) if matches!(tag.name.as_str(), "dd" | "dt") => {
+                self.start_list_item(tag, &["dd", "dt"])
+            }
+            Token::StartTag(ref tag) if tag.name == "button" => {
+                if self.has_element_in_scope("button") {
+                    self.unexpected_token(&token);
+                    self.generate_implied_end_tags(None);
+                    self.pop_until(&["button"]);
+                }
+                self.reconstruct_active_formatting_elements();
+                self.insert_html_element(tag);
+                self.parse_state.frameset_ok = FramesetOk::NotOk;
+            }
+            Token::StartTag(ref tag) if tag.name == "a" => {
+                // If the list of active formatting elements contains an a element between the end of the list and the last marker on the list (or the start of the list if there is no marker on the list), then this is a parse error; run the adoption agency algorithm for the token, then remove that element from the list of active formatting elements and the stack of open elements if the adoption agency algorithm didn't already remove it (it might not have if the element is not in table scope).
+                if let Some((_, a)) = self.formatting_element_after_last_marker("a") {
+                    self.unexpected_token(&token);
+                    self.adoption_agency("a");
+                    self.remove_from_active_formatting_elements(a);
+                    self.parse_state.open_elements.retain(|&id| id != a);
+                }
+                self.reconstruct_active_formatting_elements();
+                let element = self.insert_html_element(tag);
+                self.push_active_formatting_element(element, tag);
+            }
+            Token::StartTag(ref tag)
+                if matches!(
+                    tag.name.as_str(),
+                    "b" | "big"
+                        | "code"
+                        | "em"
+                        | "font"
+                        | "i"
+                        | "s"
+                        | "small"
+                        | "strike"
+                        | "strong"
+                        | "tt"
+                        | "u"
+                ) =>
+            {
+                self.reconstruct_active_formatting_elements();
+                let element = self.insert_html_element(tag);
+                self.push_active_formatting_element(element, tag);
+            }
+            Token::StartTag(ref tag) if tag.name == "nobr" => {
+                self.reconstruct_active_formatting_elements();
+                if self.has_element_in_scope("nobr") {
+                    self.unexpected_token(&token);
+                    self.adoption_agency("nobr");
+                    self.reconstruct_active_formatting_elements();
+                }
+                let element = self.insert_html_element(tag);
+                self.push_active_formatting_element(element, tag);
+            }
+            Token::StartTag(ref tag)
+                if matches!(tag.name.as_str(), "applet" | "marquee" | "object") =>
+            {
+                self.reconstruct_active_formatting_elements();
+                self.insert_html_element(tag);
+                self.insert_marker();
+                self.parse_state.frameset_ok = FramesetOk::NotOk;
+            }
+            Token::StartTag(ref tag)
+                if matches!(
+                    tag.name.as_str(),
+                    "area" | "br" | "embed" | "img" | "keygen" | "wbr"
+                ) =>
+            {
+                self.reconstruct_active_formatting_elements();
+                self.insert_void_element(tag);
+                self.parse_state.frameset_ok = FramesetOk::NotOk;
+            }
+            Token::StartTag(ref tag) if tag.name == "input" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_void_element(tag);
+                // If the token does not have an attribute with the name "type", or if it does, but that attribute's value is not an ASCII case-insensitive match for the string "hidden", then set the frameset-ok flag to "not ok".
+                let hidden = tag
+                    .attributes
+                    .iter()
+                    .any(|attr| attr.name == "type" && attr.value.eq_ignore_ascii_case("hidden"));
+                if !hidden {
+                    self.parse_state.frameset_ok = FramesetOk::NotOk;
+                }
+            }
+            Token::StartTag(ref tag)
+                if matches!(tag.name.as_str(), "param" | "source" | "track") =>
+            {
+                self.insert_void_element(tag);
+            }
+            Token::StartTag(ref tag) if tag.name == "hr" => {
+                self.close_p_element_in_button_scope();
+                self.insert_void_element(tag);
+                self.parse_state.frameset_ok = FramesetOk::NotOk;
+            }
+            Token::StartTag(tag) if tag.name == "image" => {
+                // Parse error. Change the token's tag name to "img" and reprocess it. (Don't ask.)
+                self.parse_error(ParseErrorCode::UnexpectedStartTag);
+                self.in_body(Token::StartTag(Tag {
+                    name: "img".to_string(),
+                    ..tag
+                }));
+            }
+            Token::StartTag(ref tag) if tag.name == "select" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_html_element(tag);
+                self.parse_state.frameset_ok = FramesetOk::NotOk;
+                // If the insertion mode is one of "in table", "in caption", "in table body", "in row", or "in cell", then switch the insertion mode to "in select in table". Otherwise, switch the insertion mode to "in select".
+                let in_table = matches!(
+                    self.parse_state.insertion_mode,
+                    InsertionMode::InTable
+                        | InsertionMode::InCaption
+                        | InsertionMode::InTableBody
+                        | InsertionMode::InRow
+                        | InsertionMode::InCell
+                );
+                if in_table {
+                    self.switch_to(InsertionMode::InSelectInTable);
+                } else {
+                    self.switch_to(InsertionMode::InSelect);
+                }
+            }
+            Token::StartTag(ref tag) if matches!(tag.name.as_str(), "optgroup" | "option") => {
+                if self.current_node_is("option") {
+                    self.parse_state.open_elements.pop();
+                }
+                self.reconstruct_active_formatting_elements();
+                self.insert_html_element(tag);
+            }
+            Token::StartTag(ref tag) if matches!(tag.name.as_str(), "rb" | "rtc") => {
+                if self.has_element_in_scope("ruby") {
+                    self.generate_implied_end_tags(None);
+                    if !self.current_node_is("ruby") {
+                        self.unexpected_token(&token);
+                    }
+                }
+                self.insert_html_element(tag);
+            }
+            Token::StartTag(ref tag) if matches!(tag.name.as_str(), "rp" | "rt") => {
+                if self.has_element_in_scope("ruby") {
+                    self.generate_implied_end_tags(Some("rtc"));
+                    if !self.current_node_is("rtc") && !self.current_node_is("ruby") {
+                        self.unexpected_token(&token);
+                    }
+                }
+                self.insert_html_element(tag);
+            }
+            Token::StartTag(ref tag) if tag.name == "plaintext" => {
+                self.close_p_element_in_button_scope();
+                self.insert_html_element(tag);
+                // Once a start tag with the tag name "plaintext" has been seen, that will be the last token ever seen other than character tokens (and the end-of-file token), because there is no way to switch out of the PLAINTEXT state.
+                self.tokenizer.set_state(State::Plaintext);
+            }
+            Token::StartTag(ref tag) if tag.name == "textarea" => {
+                self.parse_generic_text_element(tag, State::Rcdata);
+                // If the next token is a U+000A LINE FEED (LF) character token, then ignore that token and move on to the next one. (Newlines at the start of textarea elements are ignored as an authoring convenience.)
+                self.ignore_line_feed = true;
+                self.parse_state.frameset_ok = FramesetOk::NotOk;
+            }
+            Token::StartTag(ref tag) if tag.name == "xmp" => {
+                self.close_p_element_in_button_scope();
+                self.reconstruct_active_formatting_elements();
+                self.parse_state.frameset_ok = FramesetOk::NotOk;
+                self.parse_generic_text_element(tag, State::Rawtext);
+            }
+            Token::StartTag(ref tag) if tag.name == "iframe" => {
+                self.parse_state.frameset_ok = FramesetOk::NotOk;
+                self.parse_generic_text_element(tag, State::Rawtext);
+            }
+            Token::StartTag(ref tag)
+                if tag.name == "noembed"
+                    || (tag.name == "noscript"
+                        && self.parse_state.scripting == Scripting::Enabled) =>
+            {
+                self.parse_generic_text_element(tag, State::Rawtext);
+            }
+            Token::StartTag(ref tag) if tag.name == "table" => {
+                // If the Document is not set to quirks mode, and the stack of open elements has a p element in button scope, then close a p element.
+                if self.document.mode() != QuirksMode::Quirks {
+                    self.close_p_element_in_button_scope();
+                }
+                self.insert_html_element(tag);
+                self.parse_state.frameset_ok = FramesetOk::NotOk;
+                self.switch_to(InsertionMode::InTable);
+            }
+            Token::StartTag(mut tag) if matches!(tag.name.as_str(), "math" | "svg") => {
+                self.reconstruct_active_formatting_elements();
+                let namespace = if tag.name == "math" {
+                    adjust_mathml_attributes(&mut tag);
+                    Namespace::MathMl
+                } else {
+                    adjust_svg_attributes(&mut tag);
+                    Namespace::Svg
+                };
+                self.insert_foreign_element(&tag, namespace);
+                // If the token has its self-closing flag set, pop the current node off the stack of open elements and acknowledge the token's self-closing flag.
+                if tag.self_closing {
+                    self.parse_state.open_elements.pop();
+                    self.self_closing_acknowledged = true;
+                }
+            }
+            Token::StartTag(ref tag)
+                if matches!(
+                    tag.name.as_str(),
+                    "caption"
+                        | "col"
+                        | "colgroup"
+                        | "frame"
+                        | "head"
+                        | "tbody"
+                        | "td"
+                        | "tfoot"
+                        | "th"
+                        | "thead"
+                        | "tr"
+                ) =>
+            {
+                self.unexpected_token(&token);
+            }
+            Token::Eof if !self.parse_state.template_insertion_modes.is_empty() => {
+                self.in_template(token)
+            }
+            Token::Eof => {
+                self.check_for_unclosed_elements(ParseErrorCode::UnexpectedEof);
+                self.stop_parsing();
+            }
+            Token::EndTag(ref tag) if tag.name == "body" => {
+                if !self.has_element_in_scope("body") {
+                    self.unexpected_token(&token);
+                    return;
+                }
+                self.check_for_unclosed_elements(ParseErrorCode::UnexpectedEndTag);
+                self.switch_to(InsertionMode::AfterBody);
+            }
+            Token::EndTag(ref tag) if tag.name == "html" => {
+                if !self.has_element_in_scope("body") {
+                    self.unexpected_token(&token);
+                    return;
+                }
+                self.check_for_unclosed_elements(ParseErrorCode::UnexpectedEndTag);
+                self.reprocess_in(InsertionMode::AfterBody, token);
+            }
+            Token::EndTag(ref tag)
+                if matches!(
+                    tag.name.as_str(),
+                    "address"
+                        | "article"
+                        | "aside"
+                        | "blockquote"
+                        | "button"
+                        | "center"
+                        | "details"
+                        | "dialog"
+                        | "dir"
+                        | "div"
+                        | "dl"
+                        | "fieldset"
+                        | "figcaption"
+                        | "figure"
+                        | "footer"
+                        | "header"
+                        | "hgroup"
+                        | "listing"
+                        | "main"
+                        | "menu"
+                        | "nav"
+                        | "ol"
+                        | "pre"
+                        | "search"
+                        | "section"
+                        | "summary"
+                        | "ul"
+                ) =>
+            {
+                if !self.has_element_in_scope(&tag.name) {
+                    self.unexpected_token(&token);
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                if !self.current_node_is(&tag.name) {
+                    self.unexpected_token(&token);
+                }
+                self.pop_until(&[&tag.name]);
+            }
+            Token::EndTag(ref tag) if tag.name == "form" => {
+                if self.stack_contains("template") {
+                    if !self.has_element_in_scope("form") {
+                        self.unexpected_token(&token);
+                        return;
+                    }
+                    self.generate_implied_end_tags(None);
+                    if !self.current_node_is("form") {
+                        self.unexpected_token(&token);
+                    }
+                    self.pop_until(&["form"]);
+                    return;
+                }
+                // Let node be the element that the form element pointer is set to, or null if it is not set to an element. Set the form element pointer to null.
+                let node = self.parse_state.form.take();
+                let Some(node) = node.filter(|&node| self.has_node_in_scope(node)) else {
+                    self.unexpected_token(&token);
+                    return;
+                };
+                self.generate_implied_end_tags(None);
+                if self.current_node() != node {
+                    self.unexpected_token(&token);
+                }
+                self.parse_state.open_elements.retain(|&id| id != node);
+            }
+            Token::EndTag(ref tag) if tag.name == "p" => {
+                if !self.has_element_in_specific_scope(&["p"], Scope::Button) {
+                    self.unexpected_token(&token);
+                    self.insert_html_element(&Tag {
+                        name: "p".to_string(),
+                        ..Tag::default()
+                    });
+                }
+                self.close_p_element();
+            }
+            Token::EndTag(ref tag) if tag.name == "li" => {
+                if !self.has_element_in_specific_scope(&["li"], Scope::ListItem) {
+                    self.unexpected_token(&token);
+                    return;
+                }
+                self.generate_implied_end_tags(Some("li"));
+                if !self.current_node_is("li") {
+                    self.unexpected_token(&token);
+                }
+                self.pop_until(&["li"]);
+            }
+            Token::EndTag(ref tag) if matches!(tag.name.as_str(), "dd" | "dt") => {
+                if !self.has_element_in_scope(&tag.name) {
+                    self.unexpected_token(&token);
+                    return;
+                }
+                self.generate_implied_end_tags(Some(&tag.name));
+                if !self.current_node_is(&tag.name) {
+                    self.unexpected_token(&token);
+                }
+                self.pop_until(&[&tag.name]);
+            }
+            Token::EndTag(ref tag) if is_heading(&tag.name) => {
+                if !self.has_element_in_specific_scope(HEADINGS, Scope::Default) {
+                    self.unexpected_token(&token);
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                if !self.current_node_is(&tag.name) {
+                    self.unexpected_token(&token);
+                }
+                self.pop_until(HEADINGS);
+            }
+            Token::EndTag(ref tag)
+                if matches!(
+                    tag.name.as_str(),
+                    "a" | "b"
+                        | "big"
+                        | "code"
+                        | "em"
+                        | "font"
+                        | "i"
+                        | "nobr"
+                        | "s"
+                        | "small"
+                        | "strike"
+                        | "strong"
+                        | "tt"
+                        | "u"
+                ) =>
+            {
+                if !self.adoption_agency(&tag.name) {
+                    self.any_other_end_tag(&tag.name);
+                }
+            }
+            Token::EndTag(ref tag)
+                if matches!(tag.name.as_str(), "applet" | "marquee" | "object") =>
+            {
+                if !self.has_element_in_scope(&tag.name) {
+                    self.unexpected_token(&token);
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                if !self.current_node_is(&tag.name) {
+                    self.unexpected_token(&token);
+                }
+                self.pop_until(&[&tag.name]);
+                self.clear_active_formatting_elements_to_last_marker();
+            }
+            Token::EndTag(ref tag) if tag.name == "br" => {
+                // Parse error. Drop the attributes from the token, and act as described in the next entry; i.e. act as if this was a "br" start tag token with no attributes, rather than the end tag token that it actually is.
+                self.unexpected_token(&token);
+                self.in_body(Token::StartTag(Tag {
+                    name: "br".to_string(),
+                    ..Tag::default()
+                }));
+            }
+            Token::StartTag(ref tag) => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_html_element(tag);
+            }
+            Token::EndTag(ref tag) => self.any_other_end_tag(&tag.name),
+        }
+    }
+
+    /// The "li", "dd" and "dt" start tag steps of the "in body" insertion mode, which close an open
+    /// list item with one of `tag_names` before inserting the new one.
+    fn start_list_item(&mut self, tag: &Tag, tag_names: &[&str]) {
+        self.parse_state.frameset_ok = FramesetOk::NotOk;
+        for index in (0..self.parse_state.open_elements.len()).rev() {
+            let node = self.parse_state.open_elements[index];
+            let name = self.tag_name(node).to_string();
+            if tag_names.contains(&name.as_str()) {
+                self.generate_implied_end_tags(Some(&name));
+                if !self.current_node_is(&name) {
+                    self.parse_error(ParseErrorCode::UnexpectedStartTag);
+                }
+                self.pop_until(&[&name]);
+                break;
+            }
+            // If node is in the special category, but is not an address, div, or p element, then jump to the step labeled done below.
+            if self.is_special_element(node) && !matches!(name.as_str(), "address" | "div" | "p") {
+                break;
+            }
+        }
+        self.close_p_element_in_button_scope();
+        self.insert_html_element(tag);
+    }
+
+    /// The "any other end tag" steps of the "in body" insertion mode.
+    fn any_other_end_tag(&mut self, tag_name: &str) {
+        for index in (0..self.parse_state.open_elements.len()).rev() {
+            let node = self.parse_state.open_elements[index];
+            if self.tag_name(node) == tag_name {
+                self.generate_implied_end_tags(Some(tag_name));
+                // If node is not the current node, then this is a parse error.
+                if node != self.current_node() {
+                    self.parse_error(ParseErrorCode::UnexpectedEndTag);
+                }
+                // Pop all the nodes from the current node up to node, including node, then stop these steps.
+                self.parse_state.open_elements.truncate(index);
+                return;
+            }
+            if self.is_special_element(node) {
+                // Ignore the token, and return.
+                self.parse_error(ParseErrorCode::UnexpectedEndTag);
+                return;
+            }
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-incdata
+    fn text(&mut self, token: Token) {
+        match token {
+            Token::Character(c) => self.insert_character(c),
+            Token::Eof => {
+                self.unexpected_token(&token);
+                self.parse_state.open_elements.pop();
+                let original = self.parse_state.original_insertion_mode;
+                self.reprocess_in(original, token);
+            }
+            Token::EndTag(ref tag) if tag.name == "script" => {
+                // Let script be the current node (which will be a script element).
+                let script = self.current_node();
+                // Pop the current node off the stack of open elements.
+                self.parse_state.open_elements.pop();
+                // Switch the insertion mode to the insertion mode that the tree construction stage will return to.
+                self.switch_to(self.parse_state.original_insertion_mode);
+                // Increment the parser's script nesting level by one.
+                self.script_nesting_level += 1;
+                // Prepare the script element script. Scripts are run by the embedder, so when scripting is enabled every script blocks the parser until the embedder has run it.
+                if self.parse_state.scripting == Scripting::Enabled {
+                    self.pending_parsing_blocking_script = Some(script);
+                }
+                // Decrement the parser's script nesting level by one. If the parser's script nesting level is zero, then set the parser pause flag to false.
+                self.script_nesting_level -= 1;
+                if self.script_nesting_level == 0 {
+                    self.pause_flag = false;
+                }
+                // At this stage, if the pending parsing-blocking script is not null, then set the parser pause flag to true, so that the tokenizer yields control back to the embedder until it has run the script.
+                if self.pending_parsing_blocking_script.is_some() {
+                    self.pause_flag = true;
+                }
+            }
+            _ => {
+                // Any other end tag closes the raw text element.
+                self.parse_state.open_elements.pop();
+                self.switch_to(self.parse_state.original_insertion_mode);
+            }
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intable
+    fn in_table(&mut self, token: Token) {
+        match token {
+            Token::Character(_)
+                if matches!(
+                    self.tag_name(self.current_node()),
+                    "table" | "tbody" | "template" | "tfoot" | "thead" | "tr"
+                ) =>
+            {
+                self.pending_table_characters.clear();
+                self.parse_state.original_insertion_mode = self.parse_state.insertion_mode;
+                self.reprocess_in(InsertionMode::InTableText, token);
+            }
+            Token::Comment(data) => self.insert_comment(data),
+            Token::Doctype(_) => {
+                self.unexpected_token(&token);
+            }
+            Token::StartTag(ref tag) if tag.name == "caption" => {
+                self.clear_stack_back_to(&["table", "template", "html"]);
+                self.insert_marker();
+                self.insert_html_element(tag);
+                self.switch_to(InsertionMode::InCaption);
+            }
+            Token::StartTag(ref tag) if tag.name == "colgroup" => {
+                self.clear_stack_back_to(&["table", "template", "html"]);
+                self.insert_html_element(tag);
+                self.switch_to(InsertionMode::InColumnGroup);
+            }
+            Token::StartTag(ref tag) if tag.name == "col" => {
+                self.clear_stack_back_to(&["table", "template", "html"]);
+                self.insert_html_element(&Tag {
+                    name: "colgroup".to_string(),
+                    ..Tag::default()
+                });
+                self.reprocess_in(InsertionMode::InColumnGroup, token);
+            }
+            Token::StartTag(ref tag)
+                if matches!(tag.name.as_str(), "tbody" | "tfoot" | "thead") =>
+            {
+                self.clear_stack_back_to(&["table", "template", "html"]);
+                self.insert_html_element(tag);
+                self.switch_to(InsertionMode::InTableBody);
+            }
+            Token::StartTag(ref tag) if matches!(tag.name.as_str(), "td" | "th" | "tr") => {
+                self.clear_stack_back_to(&["table", "template", "html"]);
+                self.insert_html_element(&Tag {
+                    name: "tbody".to_string(),
+                    ..Tag::default()
+                });
+                self.reprocess_in(InsertionMode::InTableBody, token);
+            }
+            Token::StartTag(ref tag) if tag.name == "table" => {
+                self.unexpected_token(&token);
+                if self.has_element_in_specific_scope(&["table"], Scope::Table) {
+                    self.pop_until(&["table"]);
+                    self.reset_insertion_mode_appropriately();
+                    self.process_token(token);
+                }
+            }
+            Token::EndTag(ref tag) if tag.name == "table" => {
+                if !self.has_element_in_specific_scope(&["table"], Scope::Table) {
+                    self.unexpected_token(&token);
+                    return;
+                }
+                self.pop_until(&["table"]);
+                self.reset_insertion_mode_appropriately();
+            }
+            Token::EndTag(ref tag)
+                if matches!(
+                    tag.name.as_str(),
+                    "body"
+                        | "caption"
+                        | "col"
+                        | "colgroup"
+                        | "html"
+                        | "tbody"
+                        | "td"
+                        | "tfoot"
+                        | "th"
+                        | "thead"
+                        | "tr"
+                ) =>
+            {
+                self.unexpected_token(&token);
+            }
+            Token::StartTag(ref tag)
+                if matches!(tag.name.as_str(), "style" | "script" | "template") =>
+            {
+                self.in_head(token)
+            }
+            Token::EndTag(ref tag) if tag.name == "template" => self.in_head(token),
+            // If the token does not have an attribute with the name "type", or if it does, but that attribute's value is not an ASCII case-insensitive match for the string "hidden", then act as described in the "anything else" entry below.
+            Token::StartTag(ref tag)
+                if tag.name == "input"
+                    && tag.attributes.iter().any(|attr| {
+                        attr.name == "type" && attr.value.eq_ignore_ascii_case("hidden")
+                    }) =>
+            {
+                self.unexpected_token(&token);
+                self.insert_void_element(tag);
+            }
+            Token::StartTag(ref tag) if tag.name == "form" => {
+                self.unexpected_token(&token);
+                if self.stack_contains("template") || self.parse_state.form.is_some() {
+                    return;
+                }
+                let form = self.insert_html_element(tag);
+                self.parse_state.form = Some(form);
+                self.parse_state.open_elements.pop();
+            }
+            Token::Eof => self.in_body(token),
+            _ => {
+                self.unexpected_token(&token);
+                self.in_body_with_foster_parenting(token);
+            }
+        }
+    }
+
+    /// Process a token using the rules for the "in body" insertion mode, except that content
+    /// which would end up in a table is foster parented out of it.
+    fn in_body_with_foster_parenting(&mut self, token: Token) {
+        self.foster_parenting = true;
+        self.in_body(token);
+        self.foster_parenting = false;
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intabletext
+    fn in_table_text(&mut self, token: Token) {
+        match token {
+            Token::Character('\0') => {
+                self.unexpected_token(&token);
+            }
+            Token::Character(c) => self.pending_table_characters.push(c),
+            _ => {
+                let characters = std::mem::take(&mut self.pending_table_characters);
+                // If any of the tokens in the pending table character tokens list are character tokens that are not ASCII whitespace, then this is a parse error: reprocess the character tokens in the pending table character tokens list using the rules given in the "anything else" entry in the "in table" insertion mode.
+                if characters.iter().any(|&c| !is_whitespace(c)) {
+                    self.parse_error(ParseErrorCode::UnexpectedCharacter);
+                    for c in characters {
+                        self.in_body_with_foster_parenting(Token::Character(c));
+                    }
+                } else {
+                    for c in characters {
+                        self.insert_character(c);
+                    }
+                }
+                let original = self.parse_state.original_insertion_mode;
+                self.reprocess_in(original, token);
+            }
+        }
+    }
+
+    /// Close the caption element, if there is one in table scope, and switch to the "in table"
+    /// insertion mode. Returns false if there is no caption to close.
+    fn close_caption(&mut self, token: &Token) -> bool {
+        if !self.has_element_in_specific_scope(&["caption"], Scope::Table) {
+            self.unexpected_token(token);
+            return false;
+        }
+        self.generate_implied_end_tags(None);
+        if !self.current_node_is("caption") {
+            self.unexpected_token(token);
+        }
+        self.pop_until(&["caption"]);
+        self.clear_active_formatting_elements_to_last_marker();
+        self.switch_to(InsertionMode::InTable);
+        true
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-incaption
+    fn in_caption(&mut self, token: Token) {
+        match token {
+            Token::EndTag(ref tag) if tag.name == "caption" => {
+                self.close_caption(&token);
+            }
+            Token::StartTag(ref tag)
+                if matches!(
+                    tag.name.as_str(),
+                    "caption"
+                        | "col"
+                        | "colgroup"
+                        | "tbody"
+                        | "td"
+                        | "tfoot"
+                        | "th"
+                        | "thead"
+                        | "tr"
+                ) =>
+            {
+                if self.close_caption(&token) {
+                    self.process_token(token);
+                }
+            }
+            Token::EndTag(ref tag) if tag.name == "table" => {
+                if self.close_caption(&token) {
+                    self.process_token(token);
+                }
+            }
+            Token::EndTag(ref tag)
+                if matches!(
+                    tag.name.as_str(),
+                    "body"
+                        | "col"
+                        | "colgroup"
+                        | "html"
+                        | "tbody"
+                        | "td"
+                        | "tfoot"
+                        | "th"
+                        | "thead"
+                        | "tr"
+                ) =>
+            {
+                self.unexpected_token(&token);
+            }
+            _ => self.in_body(token),
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-incolgroup
+    fn in_column_group(&mut self, token: Token) {
+        match token {
+            Token::Character(c) if is_whitespace(c) => self.insert_character(c),
+            Token::Comment(data) => self.insert_comment(data),
+            Token::Doctype(_) => {
+                self.unexpected_token(&token);
+            }
+            Token::StartTag(ref tag) if tag.name == "html" => self.in_body(token),
+            Token::StartTag(ref tag) if tag.name == "col" => self.insert_void_element(tag),
+            Token::EndTag(ref tag) if tag.name == "colgroup" => {
+                if !self.current_node_is("colgroup") {
+                    self.unexpected_token(&token);
+                    return;
+                }
+                self.parse_state.open_elements.pop();
+                self.switch_to(InsertionMode::InTable);
+            }
+            Token::EndTag(ref tag) if tag.name == "col" => {
+                self.unexpected_token(&token);
+            }
+            Token::StartTag(ref tag) if tag.name == "template" => self.in_head(token),
+            Token::EndTag(ref tag) if tag.name == "template" => self.in_head(token),
+            Token::Eof => self.in_body(token),
+            _ => {
+                if !self.current_node_is("colgroup") {
+                    self.unexpected_token(&token);
+                    return;
+                }
+                self.parse_state.open_elements.pop();
+                self.reprocess_in(InsertionMode::InTable, token);
+            }
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intbody
+    fn in_table_body(&mut self, token: Token) {
+        match token {
+            Token::StartTag(ref tag) if tag.name == "tr" => {
+                self.clear_stack_back_to(&["tbody", "tfoot", "thead", "template", "html"]);
+                self.insert_html_element(tag);
+                self.switch_to(InsertionMode::InRow);
+            }
+            Token::StartTag(ref tag) if matches!(tag.name.as_str(), "th" | "td") => {
+                self.unexpected_token(&token);
+                self.clear_stack_back_to(&["tbody", "tfoot", "thead", "template", "html"]);
+                self.insert_html_element(&Tag {
+                    name: "tr".to_string(),
+                    ..Tag::default()
+                });
+                self.reprocess_in(InsertionMode::InRow, token);
+            }
+            Token::EndTag(ref tag) if matches!(tag.name.as_str(), "tbody" | "tfoot" | "thead") => {
+                if !self.has_element_in_specific_scope(&[&tag.name], Scope::Table) {
+                    self.unexpected_token(&token);
+                    return;
+                }
+                self.clear_stack_back_to(&["tbody", "tfoot", "thead", "template", "html"]);
+                self.parse_state.open_elements.pop();
+                self.switch_to(InsertionMode::InTable);
+            }
+            Token::StartTag(ref tag)
+                if matches!(
+                    tag.name.as_str(),
+                    "caption" | "col" | "colgroup" | "tbody" | "tfoot" | "thead"
+                ) =>
+            {
+                self.close_table_body(token);
+            }
+            Token::EndTag(ref tag) if tag.name == "table" => self.close_table_body(token),
+            Token::EndTag(ref tag)
+                if matches!(
+                    tag.name.as_str(),
+                    "body" | "caption" | "col" | "colgroup" | "html" | "td" | "th" | "tr"
+                ) =>
+            {
+                self.unexpected_token(&token);
+            }
+            _ => self.in_table(token),
+        }
+    }
+
+    /// Close the open tbody, thead or tfoot element and reprocess `token` in the "in table"
+    /// insertion mode.
+    fn close_table_body(&mut self, token: Token) {
+        if !self.has_element_in_specific_scope(&["tbody", "thead", "tfoot"], Scope::Table) {
+            self.unexpected_token(&token);
+            return;
+        }
+        self.clear_stack_back_to(&["tbody", "tfoot", "thead", "template", "html"]);
+        self.parse_state.open_elements.pop();
+        self.reprocess_in(InsertionMode::InTable, token);
+    }
+
+    /// Close the open tr element and switch to the "in table body" insertion mode. Returns false
+    /// if there is no row to close.
+    fn close_row(&mut self, token: &Token) -> bool {
+        if !self.has_element_in_specific_scope(&["tr"], Scope::Table) {
+            self.unexpected_token(token);
+            return false;
+        }
+        self.clear_stack_back_to(&["tr", "template", "html"]);
+        self.parse_state.open_elements.pop();
+        self.switch_to(InsertionMode::InTableBody);
+        true
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intr
+    fn in_row(&mut self, token: Token) {
+        match token {
+            Token::StartTag(ref tag) if matches!(tag.name.as_str(), "th" | "td") => {
+                self.clear_stack_back_to(&["tr", "template", "html"]);
+                self.insert_html_element(tag);
+                self.switch_to(InsertionMode::InCell);
+                self.insert_marker();
+            }
+            Token::EndTag(ref tag) if tag.name == "tr" => {
+                self.close_row(&token);
+            }
+            Token::StartTag(ref tag)
+                if matches!(
+                    tag.name.as_str(),
+                    "caption" | "col" | "colgroup" | "tbody" | "tfoot" | "thead" | "tr"
+                ) =>
+            {
+                if self.close_row(&token) {
+                    self.process_token(token);
+                }
+            }
+            Token::EndTag(ref tag) if tag.name == "table" => {
+                if self.close_row(&token) {
+                    self.process_token(token);
+                }
+            }
+            Token::EndTag(ref tag) if matches!(tag.name.as_str(), "tbody" | "tfoot" | "thead") => {
+                if !self.has_element_in_specific_scope(&[&tag.name], Scope::Table) {
+                    self.unexpected_token(&token);
+                    return;
+                }
+                if self.close_row(&token) {
+                    self.process_token(token);
+                }
+            }
+            Token::EndTag(ref tag)
+                if matches!(
+                    tag.name.as_str(),
+                    "body" | "caption" | "col" | "colgroup" | "html" | "td" | "th"
+                ) =>
+            {
+                self.unexpected_token(&token);
+            }
+            _ => self.in_table(token),
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#close-the-cell
+    fn close_cell(&mut self) {
+        self.generate_implied_end_tags(None);
+        if !self.current_node_is("td") && !self.current_node_is("th") {
+            self.parse_error(ParseErrorCode::UnexpectedEndTag);
+        }
+        self.pop_until(&["td", "th"]);
+        self.clear_active_formatting_elements_to_last_marker();
+        self.switch_to(InsertionMode::InRow);
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intd
+    fn in_cell(&mut self, token: Token) {
+        match token {
+            Token::EndTag(ref tag) if matches!(tag.name.as_str(), "td" | "th") => {
+                if !self.has_element_in_specific_scope(&[&tag.name], Scope::Table) {
+                    self.unexpected_token(&token);
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                if !self.current_node_is(&tag.name) {
+                    self.unexpected_token(&token);
+                }
+                self.pop_until(&[&tag.name]);
+                self.clear_active_formatting_elements_to_last_marker();
+                self.switch_to(InsertionMode::InRow);
+            }
+            Token::StartTag(ref tag)
+                if matches!(
+                    tag.name.as_str(),
+                    "caption"
+                        | "col"
+                        | "colgroup"
+                        | "tbody"
+                        | "td"
+                        | "tfoot"
+                        | "th"
+                        | "thead"
+                        | "tr"
+                ) =>
+            {
+                // Assert: The stack of open elements has a td or th element in table scope. If it doesn't, this is the fragment case: ignore the token.
+                if !self.has_element_in_specific_scope(&["td", "th"], Scope::Table) {
+                    self.unexpected_token(&token);
+                    return;
+                }
+                self.close_cell();
+                self.process_token(token);
+            }
+            Token::EndTag(ref tag)
+                if matches!(
+                    tag.name.as_str(),
+                    "body" | "caption" | "col" | "colgroup" | "html"
+                ) =>
+            {
+                self.unexpected_token(&token);
+            }
+            Token::EndTag(ref tag)
+                if matches!(
+                    tag.name.as_str(),
+                    "table" | "tbody" | "tfoot" | "thead" | "tr"
+                ) =>
+            {
+                if !self.has_element_in_specific_scope(&[&tag.name], Scope::Table) {
+                    self.unexpected_token(&token);
+                    return;
+                }
+                self.close_cell();
+                self.process_token(token);
+            }
+            _ => self.in_body(token),
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inselectintable
+    fn in_select_in_table(&mut self, token: Token) {
+        const TABLE_TAGS: &[&str] = &[
+            "caption", "table", "tbody", "tfoot", "thead", "tr", "td", "th",
+        ];
+        match token {
+            Token::StartTag(ref tag) if TABLE_TAGS.contains(&tag.name.as_str()) => {
+                self.unexpected_token(&token);
+                self.pop_until(&["select"]);
+                self.reset_insertion_mode_appropriately();
+                self.process_token(token);
+            }
+            Token::EndTag(ref tag) if TABLE_TAGS.contains(&tag.name.as_str()) => {
+                self.unexpected_token(&token);
+                if !self.has_element_in_specific_scope(&[&tag.name], Scope::Table) {
+                    return;
+                }
+                self.pop_until(&["select"]);
+                self.reset_insertion_mode_appropriately();
+                self.process_token(token);
+            }
+            _ => self.in_select(token),
+        }
+    }
+
+    /// Replace the current template insertion mode with `mode`, and reprocess the token there.
+    fn reprocess_in_template(&mut self, mode: InsertionMode, token: Token) {
+        self.parse_state.template_insertion_modes.pop();
+        self.parse_state.template_insertion_modes.push(mode);
+        self.reprocess_in(mode, token);
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-intemplate
+    fn in_template(&mut self, token: Token) {
+        match token {
+            Token::Character(_) | Token::Comment(_) | Token::Doctype(_) => self.in_body(token),
+            Token::StartTag(ref tag)
+                if matches!(
+                    tag.name.as_str(),
+                    "base"
+                        | "basefont"
+                        | "bgsound"
+                        | "link"
+                        | "meta"
+                        | "noframes"
+                        | "script"
+                        | "style"
+                        | "template"
+                        | "title"
+                ) =>
+            {
+                self.in_head(token)
+            }
+            Token::EndTag(ref tag) if tag.name == "template" => self.in_head(token),
+            Token::StartTag(ref tag)
+                if matches!(
+                    tag.name.as_str(),
+                    "caption" | "colgroup" | "tbody" | "tfoot" | "thead"
+                ) =>
+            {
+                self.reprocess_in_template(InsertionMode::InTable, token);
+            }
+            Token::StartTag(ref tag) if tag.name == "col" => {
+                self.reprocess_in_template(InsertionMode::InColumnGroup, token);
+            }
+            Token::StartTag(ref tag) if tag.name == "tr" => {
+                self.reprocess_in_template(InsertionMode::InTableBody, token);
+            }
+            Token::StartTag(ref tag) if matches!(tag.name.as_str(), "td" | "th") => {
+                self.reprocess_in_template(InsertionMode::InRow, token);
+            }
+            Token::StartTag(_) => self.reprocess_in_template(InsertionMode::InBody, token),
+            Token::EndTag(_) => {
+                self.unexpected_token(&token);
+            }
+            Token::Eof => {
+                // If there is no template element on the stack of open elements, then stop parsing. (fragment case)
+                if !self.stack_contains("template") {
+                    self.stop_parsing();
+                    return;
+                }
+                self.unexpected_token(&token);
+                self.pop_until(&["template"]);
+                self.clear_active_formatting_elements_to_last_marker();
+                self.parse_state.template_insertion_modes.pop();
+                self.reset_insertion_mode_appropriately();
+                self.process_token(token);
+            }
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inselect
+    fn in_select(&mut self, token: Token) {
+        match token {
+            Token::Character('\0') => {
+                self.unexpected_token(&token);
+            }
+            Token::Character(c) => self.insert_character(c),
+            Token::Comment(data) => self.insert_comment(data),
+            Token::Doctype(_) => {
+                self.unexpected_token(&token);
+            }
+            Token::StartTag(ref tag) if tag.name == "html" => self.in_body(token),
+            Token::StartTag(ref tag) if tag.name == "option" => {
+                if self.current_node_is("option") {
+                    self.parse_state.open_elements.pop();
+                }
+                self.insert_html_element(tag);
+            }
+            Token::StartTag(ref tag) if tag.name == "optgroup" => {
+                if self.current_node_is("option") {
+                    self.parse_state.open_elements.pop();
+                }
+                if self.current_node_is("optgroup") {
+                    self.parse_state.open_elements.pop();
+                }
+                self.insert_html_element(tag);
+            }
+            Token::StartTag(ref tag) if tag.name == "hr" => {
+                if self.current_node_is("option") {
+                    self.parse_state.open_elements.pop();
+                }
+                if self.current_node_is("optgroup") {
+                    self.parse_state.open_elements.pop();
+                }
+                self.insert_void_element(tag);
+            }
+            Token::EndTag(ref tag) if tag.name == "optgroup" => {
+                // First, if the current node is an option element, and the node immediately before it in the stack of open elements is an optgroup element, then pop the current node from the stack of open elements.
+                let open_elements = &self.parse_state.open_elements;
+                if open_elements.len() >= 2
+                    && self.current_node_is("option")
+                    && self.tag_name(open_elements[open_elements.len() - 2]) == "optgroup"
+                {
+                    self.parse_state.open_elements.pop();
+                }
+                if self.current_node_is("optgroup") {
+                    self.parse_state.open_elements.pop();
+                } else {
+                    self.unexpected_token(&token);
+                }
+            }
+            Token::EndTag(ref tag) if tag.name == "option" && self.current_node_is("option") => {
+                self.parse_state.open_elements.pop();
+            }
+            Token::EndTag(ref tag) if tag.name == "select" => {
+                if !self.has_element_in_specific_scope(&["select"], Scope::Select) {
+                    self.unexpected_token(&token);
+                    return;
+                }
+                self.pop_until(&["select"]);
+                self.reset_insertion_mode_appropriately();
+            }
+            Token::StartTag(ref tag) if tag.name == "select" => {
+                // Parse error. If the stack of open elements does not have a select element in select scope, ignore the token. Otherwise, pop elements until a select element has been popped, and reset the insertion mode appropriately.
+                self.unexpected_token(&token);
+                if self.has_element_in_specific_scope(&["select"], Scope::Select) {
+                    self.pop_until(&["select"]);
+                    self.reset_insertion_mode_appropriately();
+                }
+            }
+            Token::StartTag(ref tag)
+                if matches!(tag.name.as_str(), "input" | "keygen" | "textarea") =>
+            {
+                self.unexpected_token(&token);
+                if !self.has_element_in_specific_scope(&["select"], Scope::Select) {
+                    return;
+                }
+                self.pop_until(&["select"]);
+                self.reset_insertion_mode_appropriately();
+                self.process_token(token);
+            }
+            Token::StartTag(ref tag) if tag.name == "script" => self.in_head(token),
+            Token::Eof => self.in_body(token),
+            _ => {
+                self.unexpected_token(&token);
+            }
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-afterbody
+    fn after_body(&mut self, token: Token) {
+        match token {
+            Token::Character(c) if is_whitespace(c) => self.in_body(token),
+            Token::Comment(data) => self.insert_comment_in(self.parse_state.open_elements[0], data),
+            Token::Doctype(_) => {
+                self.unexpected_token(&token);
+            }
+            Token::StartTag(ref tag) if tag.name == "html" => self.in_body(token),
+            // If the parser was created as part of the HTML fragment parsing algorithm, this is a parse error; ignore the token.
+            Token::EndTag(ref tag) if tag.name == "html" && self.context_element.is_some() => {
+                self.unexpected_token(&token);
+            }
+            Token::EndTag(ref tag) if tag.name == "html" => {
+                self.switch_to(InsertionMode::AfterAfterBody)
+            }
+            Token::Eof => self.stop_parsing(),
+            _ => {
+                self.unexpected_token(&token);
+                self.reprocess_in(InsertionMode::InBody, token);
+            }
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inframeset
+    fn in_frameset(&mut self, token: Token) {
+        match token {
+            Token::Character(c) if is_whitespace(c) => self.insert_character(c),
+            Token::Comment(data) => self.insert_comment(data),
+            Token::Doctype(_) => {
+                self.unexpected_token(&token);
+            }
+            Token::StartTag(ref tag) if tag.name == "html" => self.in_body(token),
+            Token::StartTag(ref tag) if tag.name == "frameset" => {
+                self.insert_html_element(tag);
+            }
+            // If the current node is the root html element, then this is a parse error; ignore the token.
+            Token::EndTag(ref tag)
+                if tag.name == "frameset" && self.parse_state.open_elements.len() > 1 =>
+            {
+                self.parse_state.open_elements.pop();
+                if !self.current_node_is("frameset") {
+                    self.switch_to(InsertionMode::AfterFrameset);
+                }
+            }
+            Token::StartTag(ref tag) if tag.name == "frame" => self.insert_void_element(tag),
+            Token::StartTag(ref tag) if tag.name == "noframes" => self.in_head(token),
+            Token::Eof => self.stop_parsing(),
+            _ => {
+                self.unexpected_token(&token);
+            }
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-afterframeset
+    fn after_frameset(&mut self, token: Token) {
+        match token {
+            Token::Character(c) if is_whitespace(c) => self.insert_character(c),
+            Token::Comment(data) => self.insert_comment(data),
+            Token::Doctype(_) => {
+                self.unexpected_token(&token);
+            }
+            Token::StartTag(ref tag) if tag.name == "html" => self.in_body(token),
+            Token::EndTag(ref tag) if tag.name == "html" => {
+                self.switch_to(InsertionMode::AfterAfterFrameset)
+            }
+            Token::StartTag(ref tag) if tag.name == "noframes" => self.in_head(token),
+            Token::Eof => self.stop_parsing(),
+            _ => {
+                self.unexpected_token(&token);
+            }
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#the-after-after-body-insertion-mode
+    fn after_after_body(&mut self, token: Token) {
+        match token {
+            Token::Comment(data) => self.insert_comment_in(self.document.root(), data),
+            Token::Doctype(_) => self.in_body(token),
+            Token::Character(c) if is_whitespace(c) => self.in_body(token),
+            Token::StartTag(ref tag) if tag.name == "html" => self.in_body(token),
+            Token::Eof => self.stop_parsing(),
+            _ => {
+                self.unexpected_token(&token);
+                self.reprocess_in(InsertionMode::InBody, token);
+            }
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#the-after-after-frameset-insertion-mode
+    fn after_after_frameset(&mut self, token: Token) {
+        match token {
+            Token::Comment(data) => self.insert_comment_in(self.document.root(), data),
+            Token::Doctype(_) => self.in_body(token),
+            Token::Character(c) if is_whitespace(c) => self.in_body(token),
+            Token::StartTag(ref tag) if tag.name == "html" => self.in_body(token),
+            Token::Eof => self.stop_parsing(),
+            Token::StartTag(ref tag) if tag.name == "noframes" => self.in_head(token),
+            _ => {
+                self.unexpected_token(&token);
+            }
+        }
+    }
+}
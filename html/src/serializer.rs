@@ -0,0 +1,280 @@
+// This file is based on section 13.3 of the HTML5 specification
+// The goal is to implement the algorithm for serializing HTML fragments (13.3)
+// https://html.spec.whatwg.org/multipage/parsing.html#serialising-html-fragments
+
+use crate::dom::{Document, ElementData, Namespace, NodeId, NodeType};
+
+/// Elements that have no end tag and can't have children.
+///
+/// https://html.spec.whatwg.org/multipage/syntax.html#void-elements
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "basefont", "bgsound", "br", "col", "embed", "frame", "hr", "img", "input",
+    "keygen", "link", "meta", "param", "source", "track", "wbr",
+];
+
+/// Elements whose text children are written out without escaping. `noscript` is one of them as
+/// well in documents parsed with scripting enabled.
+const RAW_TEXT_PARENTS: &[&str] = &[
+    "style",
+    "script",
+    "xmp",
+    "iframe",
+    "noembed",
+    "noframes",
+    "plaintext",
+];
+
+/// Elements whose contents are written out as they are, even when pretty-printing, because their
+/// whitespace is significant.
+const PREFORMATTED_ELEMENTS: &[&str] = &["pre", "listing", "textarea"];
+
+/// Serialize the children of `node` as HTML.
+///
+/// Attributes are written in alphabetical order, since the DOM doesn't keep the order in which
+/// they were parsed.
+pub fn serialize(document: &Document, node: NodeId) -> String {
+    let mut serializer = Serializer {
+        document,
+        pretty: false,
+        output: String::new(),
+    };
+    serializer.serialize_children(node, 0);
+    serializer.output
+}
+
+/// Serialize the children of `node` as HTML, with every node that isn't inside a `<pre>` or a
+/// similar element on its own line, indented by its depth. Whitespace-only text is dropped and
+/// other text is trimmed, so the output doesn't always parse back to the same DOM.
+pub fn serialize_pretty(document: &Document, node: NodeId) -> String {
+    let mut serializer = Serializer {
+        document,
+        pretty: true,
+        output: String::new(),
+    };
+    serializer.serialize_children(node, 0);
+    serializer.output
+}
+
+struct Serializer<'a> {
+    document: &'a Document,
+    pretty: bool,
+    output: String,
+}
+
+impl Serializer<'_> {
+    /// The node whose children are serialized for `node`: if the node is a template element, then
+    /// let the node instead be the template element's template contents.
+    fn contents(&self, node: NodeId) -> NodeId {
+        self.document.template_contents(node).unwrap_or(node)
+    }
+
+    /// Are the text children of `elem` written out without escaping?
+    fn is_raw_text_parent(&self, elem: &ElementData) -> bool {
+        elem.namespace == Namespace::Html
+            && (RAW_TEXT_PARENTS.contains(&&*elem.tag_name)
+                || (elem.tag_name == "noscript" && self.document.scripting_enabled()))
+    }
+
+    fn serialize_children(&mut self, node: NodeId, depth: usize) {
+        let raw_text = self
+            .document
+            .element(node)
+            .is_some_and(|elem| self.is_raw_text_parent(elem));
+        for child in self.document.children(self.contents(node)) {
+            if self.pretty {
+                if let NodeType::Text(data) = self.document.node_type(child) {
+                    if data.trim().is_empty() {
+                        continue;
+                    }
+                }
+                self.output.push_str(&"  ".repeat(depth));
+            }
+            self.serialize_node(child, raw_text, depth);
+            if self.pretty {
+                self.output.push('\n');
+            }
+        }
+    }
+
+    fn serialize_node(&mut self, node: NodeId, raw_text: bool, depth: usize) {
+        match self.document.node_type(node) {
+            NodeType::Element(elem) => {
+                self.output.push('<');
+                self.output.push_str(&elem.tag_name);
+                let mut attributes: Vec<_> = elem.attributes.iter().collect();
+                attributes.sort();
+                for (name, value) in attributes {
+                    self.output.push(' ');
+                    self.output.push_str(name);
+                    self.output.push_str("=\"");
+                    escape(&mut self.output, value, true);
+                    self.output.push('"');
+                }
+                self.output.push('>');
+                // If current node serializes as void, then continue on to the next child node at
+                // this point.
+                if elem.namespace == Namespace::Html && VOID_ELEMENTS.contains(&&*elem.tag_name) {
+                    return;
+                }
+                let multiline = self.pretty
+                    && !PREFORMATTED_ELEMENTS.contains(&&*elem.tag_name)
+                    && !self.is_raw_text_parent(elem)
+                    && self
+                        .document
+                        .children(self.contents(node))
+                        .any(|child| !matches!(self.document.node_type(child), NodeType::Text(_)));
+                if multiline {
+                    self.output.push('\n');
+                    self.serialize_children(node, depth + 1);
+                    self.output.push_str(&"  ".repeat(depth));
+                } else {
+                    let pretty = std::mem::replace(&mut self.pretty, false);
+                    if pretty && !PREFORMATTED_ELEMENTS.contains(&&*elem.tag_name) {
+                        // The element only has text children, so keep them on one line.
+                        let text = self.document.text_content(self.contents(node));
+                        if self.is_raw_text_parent(elem) {
+                            self.output.push_str(text.trim());
+                        } else {
+                            escape(&mut self.output, text.trim(), false);
+                        }
+                    } else {
+                        self.serialize_children(node, depth + 1);
+                    }
+                    self.pretty = pretty;
+                }
+                self.output.push_str("</");
+                self.output.push_str(&elem.tag_name);
+                self.output.push('>');
+            }
+            NodeType::Text(data) => {
+                let data = if self.pretty { data.trim() } else { data };
+                if raw_text {
+                    self.output.push_str(data);
+                } else {
+                    escape(&mut self.output, data, false);
+                }
+            }
+            NodeType::Comment(data) => {
+                self.output.push_str("<!--");
+                self.output.push_str(data);
+                self.output.push_str("-->");
+            }
+            NodeType::ProcessingInstruction(pi) => {
+                self.output.push_str("<?");
+                self.output.push_str(&pi.target);
+                self.output.push(' ');
+                self.output.push_str(&pi.data);
+                self.output.push('>');
+            }
+            NodeType::Doctype(doctype) => {
+                self.output.push_str("<!DOCTYPE ");
+                self.output.push_str(&doctype.name);
+                self.output.push('>');
+            }
+            NodeType::Document(_) | NodeType::DocumentFragment => {
+                self.serialize_children(node, depth)
+            }
+        }
+    }
+}
+
+/// Escaping a string (for the purposes of the algorithm above) consists of running the following
+/// steps:
+///
+/// https://html.spec.whatwg.org/multipage/parsing.html#escapingString
+fn escape(output: &mut String, data: &str, attribute_mode: bool) {
+    for c in data.chars() {
+        match c {
+            // Replace any occurrence of the "&" character by the string "&amp;".
+            '&' => output.push_str("&amp;"),
+            // Replace any occurrences of the U+00A0 NO-BREAK SPACE character by the string
+            // "&nbsp;".
+            '\u{A0}' => output.push_str("&nbsp;"),
+            // Replace any occurrences of the "<" character by the string "&lt;".
+            '<' => output.push_str("&lt;"),
+            // Replace any occurrences of the ">" character by the string "&gt;".
+            '>' => output.push_str("&gt;"),
+            // If the algorithm was invoked in the attribute mode, then replace any occurrences of
+            // the """ character by the string "&quot;".
+            '"' if attribute_mode => output.push_str("&quot;"),
+            c => output.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    /// Parse `input` and serialize the whole document.
+    fn round_trip(input: &str) -> String {
+        let document = Parser::from_string(input).run();
+        serialize(&document, document.root())
+    }
+
+    #[test]
+    fn serializing() {
+        assert_eq!(
+            round_trip("<!doctype html><p class=a id='b'>x<br>y<!--c--></p>"),
+            r#"<!DOCTYPE html><html><head></head><body><p class="a" id="b">x<br>y<!--c--></p></body></html>"#
+        );
+        assert_eq!(
+            round_trip("<img alt='&quot;a&quot; & <b>'><input>"),
+            r#"<html><head></head><body><img alt="&quot;a&quot; &amp; &lt;b&gt;"><input></body></html>"#
+        );
+    }
+
+    #[test]
+    fn template_contents() {
+        assert_eq!(
+            round_trip("<template><p>a</p><template>b</template></template>"),
+            "<html><head><template><p>a</p><template>b</template></template></head>\
+             <body></body></html>"
+        );
+    }
+
+    #[test]
+    fn escaping_text() {
+        let document = Parser::from_string(
+            "<p>a &lt; b &amp;&amp; c &gt; d&nbsp;</p><style>p > a { content: '&amp;' }</style>\
+             <script>if (a < b) {}</script><textarea><b>&amp;</textarea>",
+        )
+        .run();
+        let body = document
+            .children(document.document_element().unwrap())
+            .nth(1)
+            .unwrap();
+        assert_eq!(
+            serialize(&document, body),
+            "<p>a &lt; b &amp;&amp; c &gt; d&nbsp;</p>\
+             <style>p > a { content: '&amp;' }</style>\
+             <script>if (a < b) {}</script>\
+             <textarea>&lt;b&gt;&amp;</textarea>"
+        );
+    }
+
+    #[test]
+    fn pretty_printing() {
+        let document =
+            Parser::from_string("<div>\n <p>a <b>b</b></p><pre> x\n y</pre>\n<p>c</p></div>").run();
+        assert_eq!(
+            serialize_pretty(&document, document.root()),
+            "<html>
+  <head></head>
+  <body>
+    <div>
+      <p>
+        a
+        <b>b</b>
+      </p>
+      <pre> x
+ y</pre>
+      <p>c</p>
+    </div>
+  </body>
+</html>
+"
+        );
+    }
+}
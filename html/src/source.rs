@@ -0,0 +1,150 @@
+// Positions in source files, so that debugging tools can point from a DOM node, or a CSS rule
+// built on top of it, back to the text it was parsed from.
+
+/// A position in a source file. Lines and columns start at 1, and columns count characters
+/// rather than bytes. Lines end at a line feed, a carriage return, or a carriage return followed
+/// by a line feed, the same newlines that the tokenizer normalizes.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
+pub struct SourcePosition {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Default for SourcePosition {
+    fn default() -> Self {
+        SourcePosition { line: 1, column: 1 }
+    }
+}
+
+/// The part of a source file that something was parsed from, from `start` up to but not
+/// including `end`.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct SourceSpan {
+    pub start: SourcePosition,
+    pub end: SourcePosition,
+}
+
+/// Turns byte offsets into a source string into positions. It remembers the last position it
+/// found, so tokenizers that mostly move forwards through their input only scan it once.
+#[derive(Debug, Clone, Default)]
+pub struct PositionTracker {
+    origin: SourcePosition,
+    offset: usize,
+    position: SourcePosition,
+}
+
+impl PositionTracker {
+    /// A tracker for a source string that starts at `origin` in its file, like the contents of a
+    /// `<style>` element in an HTML file.
+    pub fn new(origin: SourcePosition) -> Self {
+        PositionTracker {
+            origin,
+            offset: 0,
+            position: origin,
+        }
+    }
+
+    /// The position of the byte `offset` in `source`, which must be at a character boundary.
+    pub fn position(&mut self, source: &str, offset: usize) -> SourcePosition {
+        if offset < self.offset {
+            let skipped = &source[offset..self.offset];
+            let lines = line_breaks(skipped, source[..offset].ends_with('\r'));
+            self.position = if lines == 0 {
+                SourcePosition {
+                    line: self.position.line,
+                    column: self.position.column - skipped.chars().count(),
+                }
+            } else {
+                let line = self.position.line - lines;
+                let column = match source[..offset].rfind(['\n', '\r']) {
+                    Some(newline) => source[newline + 1..offset].chars().count() + 1,
+                    None => self.origin.column + source[..offset].chars().count(),
+                };
+                SourcePosition { line, column }
+            };
+        } else {
+            let mut after_cr = source[..self.offset].ends_with('\r');
+            for c in source[self.offset..offset].chars() {
+                match c {
+                    // A line feed after a carriage return is part of the same newline.
+                    '\n' if after_cr => {}
+                    '\n' | '\r' => {
+                        self.position.line += 1;
+                        self.position.column = 1;
+                    }
+                    _ => self.position.column += 1,
+                }
+                after_cr = c == '\r';
+            }
+        }
+        self.offset = offset;
+        self.position
+    }
+
+    /// The span of the bytes from `start` to `end` in `source`.
+    pub fn span(&mut self, source: &str, start: usize, end: usize) -> SourceSpan {
+        SourceSpan {
+            start: self.position(source, start),
+            end: self.position(source, end),
+        }
+    }
+}
+
+/// The number of newlines in `text`, which comes right after a carriage return if `after_cr` is
+/// set.
+fn line_breaks(text: &str, mut after_cr: bool) -> usize {
+    let mut lines = 0;
+    for c in text.chars() {
+        if c == '\r' || (c == '\n' && !after_cr) {
+            lines += 1;
+        }
+        after_cr = c == '\r';
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(line: usize, column: usize) -> SourcePosition {
+        SourcePosition { line, column }
+    }
+
+    #[test]
+    fn positions() {
+        let source = "ab\nçd\n\nef";
+        let mut tracker = PositionTracker::default();
+        assert_eq!(tracker.position(source, 0), at(1, 1));
+        assert_eq!(tracker.position(source, 2), at(1, 3));
+        assert_eq!(tracker.position(source, 3), at(2, 1));
+        // Columns count characters, not bytes.
+        assert_eq!(tracker.position(source, 5), at(2, 2));
+        assert_eq!(tracker.position(source, 8), at(4, 1));
+        // Going backwards works too.
+        assert_eq!(tracker.position(source, 5), at(2, 2));
+        assert_eq!(tracker.position(source, 1), at(1, 2));
+        assert_eq!(tracker.position(source, 10), at(4, 3));
+
+        let mut tracker = PositionTracker::new(at(3, 8));
+        assert_eq!(tracker.position(source, 2), at(3, 10));
+        assert_eq!(tracker.position(source, 8), at(6, 1));
+        assert_eq!(tracker.position(source, 1), at(3, 9));
+    }
+
+    #[test]
+    fn carriage_returns() {
+        let source = "a\rb\r\nc\r\rd";
+        let mut tracker = PositionTracker::default();
+        assert_eq!(tracker.position(source, 2), at(2, 1));
+        // Between the two halves of a CRLF pair, the line has already ended.
+        assert_eq!(tracker.position(source, 4), at(3, 1));
+        assert_eq!(tracker.position(source, 5), at(3, 1));
+        assert_eq!(tracker.position(source, 6), at(3, 2));
+        assert_eq!(tracker.position(source, 9), at(5, 2));
+        // Going backwards works too.
+        assert_eq!(tracker.position(source, 5), at(3, 1));
+        assert_eq!(tracker.position(source, 3), at(2, 2));
+        assert_eq!(tracker.position(source, 0), at(1, 1));
+    }
+}
@@ -1,3 +0,0 @@
-// This file is based on section 13.2 of the HTML5 specification
-// The goal is to implement the tokenizer (13.2.5)
-// https://html.spec.whatwg.org/multipage/parsing.html#parse-state
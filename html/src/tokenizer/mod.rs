@@ -0,0 +1,2178 @@
+// This file is based on section 13.2 of the HTML5 specification
+// The goal is to implement the tokenizer (13.2.5)
+// https://html.spec.whatwg.org/multipage/parsing.html#tokenization
+
+mod char_ref;
+mod state;
+mod token;
+
+use std::collections::VecDeque;
+use std::mem;
+
+use crate::{
+    parse_error::{ParseError, ParseErrorCode},
+    source::{PositionTracker, SourceSpan},
+};
+pub use state::State;
+pub use token::{Attribute, Doctype, Tag, Token};
+
+const REPLACEMENT_CHARACTER: char = '\u{FFFD}';
+
+/// The most input that a single step of the state machine looks at, in bytes. The longest is a
+/// named character reference: 32 characters, a semicolon, and the character after it. While the
+/// input is still arriving, the tokenizer only runs while at least this much is available.
+const LOOKAHEAD: usize = 64;
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+enum TagKind {
+    Start,
+    End,
+}
+
+pub struct Tokenizer {
+    /// The state of the tokenizer state machine.
+    state: State,
+    /// The return state is used by the character reference states to get back to the state they were invoked from.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#return-state
+    return_state: State,
+    /// The temporary buffer is used by the character reference states and the end tag name states of the RCDATA, RAWTEXT and script data states.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#temporary-buffer
+    temporary_buffer: String,
+    /// https://html.spec.whatwg.org/multipage/parsing.html#character-reference-code
+    character_reference_code: u32,
+    /// The current input character is the last character to have been consumed.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#current-input-character
+    current_input_character: Option<char>,
+    /// Set when the current attribute has the same name as an earlier attribute on the tag.
+    duplicate_attribute: bool,
+    /// Set when a state asks for the current input character to be reconsumed.
+    reconsume: bool,
+    /// Whether the tree builder allows `<![CDATA[` sections, which are only recognised when the
+    /// adjusted current node is not an element in the HTML namespace.
+    allow_cdata: bool,
+
+    // Tokens under construction
+    current_tag: Tag,
+    current_tag_kind: TagKind,
+    current_comment: String,
+    current_doctype: Doctype,
+    /// The tag name of the last start tag to have been emitted from this tokenizer, used to find the appropriate end tag.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#appropriate-end-tag-token
+    last_start_tag_name: Option<String>,
+
+    // Properties for internal use
+    input: String,
+    /// Set once all of the input has been added, so that running out of it is the end of the file
+    /// rather than a reason to wait for more.
+    input_complete: bool,
+    pos: usize,
+    /// The byte offset of the current input character.
+    char_start: usize,
+    /// The byte offset of the `<` that started the tag, comment or DOCTYPE being built.
+    token_start: usize,
+    /// The byte offset of the `&` that started the character reference being consumed.
+    character_reference_start: usize,
+    /// The byte offset at which the token last returned by `next_token` starts.
+    token_offset: usize,
+    /// The lines and columns of the token last returned by `next_token`.
+    token_span: SourceSpan,
+    positions: PositionTracker,
+    /// Emitted tokens that have not been returned yet, with the byte offsets they start at and
+    /// their spans.
+    pending: VecDeque<(Token, usize, SourceSpan)>,
+    eof_emitted: bool,
+    errors: Vec<ParseError>,
+}
+
+impl Tokenizer {
+    pub fn new(input: &str) -> Self {
+        Tokenizer {
+            state: State::Data,
+            return_state: State::Data,
+            temporary_buffer: String::new(),
+            character_reference_code: 0,
+            current_input_character: None,
+            duplicate_attribute: false,
+            reconsume: false,
+            allow_cdata: false,
+            current_tag: Tag::default(),
+            current_tag_kind: TagKind::Start,
+            current_comment: String::new(),
+            current_doctype: Doctype::default(),
+            last_start_tag_name: None,
+            input: input.to_owned(),
+            input_complete: true,
+            pos: 0,
+            char_start: 0,
+            token_start: 0,
+            character_reference_start: 0,
+            token_offset: 0,
+            token_span: SourceSpan::default(),
+            positions: PositionTracker::default(),
+            pending: VecDeque::new(),
+            eof_emitted: false,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Switch the tokenizer to another state. The tree construction stage uses this to put the
+    /// tokenizer in the RCDATA, RAWTEXT, script data or PLAINTEXT state.
+    pub fn set_state(&mut self, state: State) {
+        self.state = state;
+    }
+
+    /// Set the tag name of the last start tag, for a tokenizer that starts in one of the states
+    /// that ends at an appropriate end tag.
+    pub fn set_last_start_tag(&mut self, name: &str) {
+        self.last_start_tag_name = Some(name.to_string());
+    }
+
+    /// Tell the tokenizer whether `<![CDATA[` starts a CDATA section, which the tree construction
+    /// stage decides from the adjusted current node.
+    pub(crate) fn set_allow_cdata(&mut self, allow_cdata: bool) {
+        self.allow_cdata = allow_cdata;
+    }
+
+    /// A tokenizer for input that arrives in parts. Add them with `push_str`, and call
+    /// `end_input` after the last one.
+    pub fn streaming() -> Self {
+        Tokenizer {
+            input_complete: false,
+            ..Tokenizer::new("")
+        }
+    }
+
+    /// Add more input to the end of a streaming tokenizer's input.
+    pub fn push_str(&mut self, input: &str) {
+        debug_assert!(!self.input_complete, "the input has already ended");
+        self.input.push_str(input);
+    }
+
+    /// Mark the end of a streaming tokenizer's input.
+    pub fn end_input(&mut self) {
+        self.input_complete = true;
+    }
+
+    /// Whether all of the input has been added.
+    pub fn is_input_complete(&self) -> bool {
+        self.input_complete
+    }
+
+    /// Run the state machine until it emits a token, and return it.
+    ///
+    /// Panics if the tokenizer has to wait for more input; use `try_next_token` for streaming
+    /// input.
+    pub fn next_token(&mut self) -> Token {
+        self.try_next_token()
+            .expect("the tokenizer needs more input to emit a token")
+    }
+
+    /// Run the state machine until it emits a token, and return it, or return `None` if more
+    /// input is needed first. There are no more tokens after the end-of-file token.
+    pub fn try_next_token(&mut self) -> Option<Token> {
+        loop {
+            if let Some((token, offset, span)) = self.pending.pop_front() {
+                self.token_offset = offset;
+                self.token_span = span;
+                return Some(token);
+            }
+            if self.eof_emitted || (!self.input_complete && self.input.len() - self.pos < LOOKAHEAD)
+            {
+                return None;
+            }
+            self.step();
+        }
+    }
+
+    /// The byte offset at which the token last returned by `next_token` starts.
+    pub(crate) fn token_offset(&self) -> usize {
+        self.token_offset
+    }
+
+    /// Where the token last returned by `next_token` appears in the input. Tags, comments and
+    /// DOCTYPEs span from their `<` to their `>`, and characters from the input character that
+    /// produced them, up to the end of a character reference.
+    pub fn token_span(&self) -> SourceSpan {
+        self.token_span
+    }
+
+    /// Take the parse errors found so far.
+    pub fn take_errors(&mut self) -> Vec<ParseError> {
+        mem::take(&mut self.errors)
+    }
+
+    fn parse_error(&mut self, code: ParseErrorCode) {
+        self.parse_error_at(code, self.char_start);
+    }
+
+    /// Report a parse error at the byte `offset`. States that look ahead without consuming the
+    /// current input character report their errors at the next input character.
+    fn parse_error_at(&mut self, code: ParseErrorCode, offset: usize) {
+        self.errors.push(ParseError { code, offset });
+    }
+
+    /// Consume the next input character, or return `None` at the end of the input.
+    fn consume(&mut self) -> Option<char> {
+        if self.reconsume {
+            self.reconsume = false;
+            return self.current_input_character;
+        }
+        self.char_start = self.pos;
+        let mut c = self.input[self.pos..].chars().next();
+        if let Some(next) = c {
+            self.pos += next.len_utf8();
+            // https://html.spec.whatwg.org/multipage/parsing.html#preprocessing-the-input-stream
+            // Before the tokenization stage, the input stream must be preprocessed by normalizing newlines. Thus, newlines in HTML DOMs are represented by U+000A LF characters, and there are never any U+000D CR characters in the input to the tokenization stage.
+            // To normalize newlines in a string, replace every U+000D CR U+000A LF code point pair with a single U+000A LF code point, and then replace every remaining U+000D CR code point with a U+000A LF code point.
+            if next == '\r' {
+                if self.input[self.pos..].starts_with('\n') {
+                    self.pos += 1;
+                }
+                c = Some('\n');
+            } else if is_noncharacter(next) {
+                self.parse_error(ParseErrorCode::NoncharacterInInputStream);
+            } else if next.is_control() && !matches!(next, '\0' | '\t' | '\n' | '\x0C' | ' ') {
+                self.parse_error(ParseErrorCode::ControlCharacterInInputStream);
+            }
+        }
+        self.current_input_character = c;
+        c
+    }
+
+    /// Switch to `state`, and reconsume the current input character there.
+    fn reconsume_in(&mut self, state: State) {
+        self.reconsume = true;
+        self.state = state;
+    }
+
+    /// Put a reconsumed character back into the input, so that lookahead sees it.
+    fn unconsume(&mut self) {
+        if self.reconsume {
+            self.reconsume = false;
+            if let Some(c) = self.current_input_character {
+                self.pos -= c.len_utf8();
+            }
+        }
+    }
+
+    /// Do the next characters match `s`, ignoring ASCII case?
+    fn next_characters_are(&self, s: &str, ascii_case_insensitive: bool) -> bool {
+        match self.input.get(self.pos..self.pos + s.len()) {
+            Some(next) if ascii_case_insensitive => next.eq_ignore_ascii_case(s),
+            Some(next) => next == s,
+            None => false,
+        }
+    }
+
+    fn emit(&mut self, token: Token) {
+        let offset = match token {
+            Token::Character(_) => self.char_start,
+            Token::Eof => self.pos,
+            _ => self.token_start,
+        };
+        self.emit_at(token, offset);
+    }
+
+    /// Emit `token`, which starts at the byte `offset`.
+    fn emit_at(&mut self, token: Token, offset: usize) {
+        if token == Token::Eof {
+            self.eof_emitted = true;
+        }
+        let span = self
+            .positions
+            .span(&self.input, offset, self.pos.max(offset));
+        self.pending.push_back((token, offset, span));
+    }
+
+    fn emit_char(&mut self, c: char) {
+        self.emit(Token::Character(c));
+    }
+
+    fn emit_str(&mut self, s: &str) {
+        for c in s.chars() {
+            self.emit_char(c);
+        }
+    }
+
+    fn create_tag(&mut self, kind: TagKind) {
+        self.current_tag = Tag::default();
+        self.current_tag_kind = kind;
+    }
+
+    fn emit_current_tag(&mut self) {
+        self.remove_duplicate_attribute();
+        let tag = mem::take(&mut self.current_tag);
+        match self.current_tag_kind {
+            TagKind::Start => {
+                self.last_start_tag_name = Some(tag.name.clone());
+                self.emit(Token::StartTag(tag));
+            }
+            TagKind::End => {
+                if !tag.attributes.is_empty() {
+                    self.parse_error(ParseErrorCode::EndTagWithAttributes);
+                }
+                if tag.self_closing {
+                    self.parse_error(ParseErrorCode::EndTagWithTrailingSolidus);
+                }
+                self.emit(Token::EndTag(tag));
+            }
+        }
+    }
+
+    fn emit_current_comment(&mut self) {
+        let comment = mem::take(&mut self.current_comment);
+        self.emit(Token::Comment(comment));
+    }
+
+    fn emit_current_doctype(&mut self) {
+        let doctype = mem::take(&mut self.current_doctype);
+        self.emit(Token::Doctype(doctype));
+    }
+
+    /// An appropriate end tag token is an end tag token whose tag name matches the tag name of the last start tag to have been emitted from this tokenizer, if any.
+    fn is_appropriate_end_tag(&self) -> bool {
+        self.last_start_tag_name.as_deref() == Some(self.current_tag.name.as_str())
+    }
+
+    fn start_attribute(&mut self) {
+        self.remove_duplicate_attribute();
+        self.current_tag.attributes.push(Attribute::default());
+    }
+
+    /// When the user agent leaves the attribute name state (and before emitting the tag token, if appropriate), the complete attribute's name must be compared to the other attributes on the same token; if there is already an attribute on the token with the exact same name, then this is a duplicate-attribute parse error and the new attribute must be removed from the token.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#attribute-name-state
+    fn leave_attribute_name(&mut self) {
+        if let Some((attribute, others)) = self.current_tag.attributes.split_last() {
+            if others.iter().any(|other| other.name == attribute.name) {
+                self.parse_error(ParseErrorCode::DuplicateAttribute);
+                self.duplicate_attribute = true;
+            }
+        }
+    }
+
+    /// Remove the current attribute if it duplicates an earlier one. It is kept until its value
+    /// has been consumed.
+    fn remove_duplicate_attribute(&mut self) {
+        if mem::take(&mut self.duplicate_attribute) {
+            self.current_tag.attributes.pop();
+        }
+    }
+
+    fn current_attribute(&mut self) -> &mut Attribute {
+        self.current_tag
+            .attributes
+            .last_mut()
+            .expect("attribute states always start an attribute first")
+    }
+
+    /// Is the return state one of the attribute value states?
+    fn consumed_as_part_of_an_attribute(&self) -> bool {
+        matches!(
+            self.return_state,
+            State::AttributeValueDoubleQuoted
+                | State::AttributeValueSingleQuoted
+                | State::AttributeValueUnquoted
+        )
+    }
+
+    /// When a state says to flush code points consumed as a character reference, it means that for each code point in the temporary buffer (in the order they were added to the buffer) user agent must append the code point from the buffer to the current attribute's value if the character reference was consumed as part of an attribute, or emit the code point as a character token otherwise.
+    ///
+    /// https://html.spec.whatwg.org/multipage/parsing.html#flush-code-points-consumed-as-a-character-reference
+    fn flush_code_points_consumed_as_a_character_reference(&mut self) {
+        let buffer = mem::take(&mut self.temporary_buffer);
+        if self.consumed_as_part_of_an_attribute() {
+            self.current_attribute().value.push_str(&buffer);
+        } else {
+            for c in buffer.chars() {
+                self.emit_at(Token::Character(c), self.character_reference_start);
+            }
+        }
+    }
+
+    /// Shared handling for the "end tag name" states of RCDATA, RAWTEXT and script data.
+    fn end_tag_name_state(&mut self, c: Option<char>, text_state: State) {
+        match c {
+            Some('\t' | '\n' | '\x0C' | ' ') if self.is_appropriate_end_tag() => {
+                self.state = State::BeforeAttributeName;
+            }
+            Some('/') if self.is_appropriate_end_tag() => {
+                self.state = State::SelfClosingStartTag;
+            }
+            Some('>') if self.is_appropriate_end_tag() => {
+                self.state = State::Data;
+                self.emit_current_tag();
+            }
+            Some(c) if c.is_ascii_alphabetic() => {
+                self.current_tag.name.push(c.to_ascii_lowercase());
+                self.temporary_buffer.push(c);
+            }
+            _ => {
+                self.emit_str("</");
+                let buffer = mem::take(&mut self.temporary_buffer);
+                self.emit_str(&buffer);
+                self.reconsume_in(text_state);
+            }
+        }
+    }
+
+    /// Shared handling for the double-escape start and end states of script data.
+    fn script_data_double_escape_state(
+        &mut self,
+        c: Option<char>,
+        if_script: State,
+        otherwise: State,
+    ) {
+        match c {
+            Some(c @ ('\t' | '\n' | '\x0C' | ' ' | '/' | '>')) => {
+                self.state = if self.temporary_buffer == "script" {
+                    if_script
+                } else {
+                    otherwise
+                };
+                self.emit_char(c);
+            }
+            Some(c) if c.is_ascii_alphabetic() => {
+                self.temporary_buffer.push(c.to_ascii_lowercase());
+                self.emit_char(c);
+            }
+            _ => self.reconsume_in(otherwise),
+        }
+    }
+
+    /// Run a single step of the state machine.
+    fn step(&mut self) {
+        match self.state {
+            // https://html.spec.whatwg.org/multipage/parsing.html#data-state
+            State::Data => match self.consume() {
+                Some('&') => {
+                    self.return_state = State::Data;
+                    self.state = State::CharacterReference;
+                }
+                Some('<') => {
+                    self.token_start = self.char_start;
+                    self.state = State::TagOpen;
+                }
+                Some('\0') => {
+                    self.parse_error(ParseErrorCode::UnexpectedNullCharacter);
+                    self.emit_char('\0');
+                }
+                None => self.emit(Token::Eof),
+                Some(c) => self.emit_char(c),
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#rcdata-state
+            State::Rcdata => match self.consume() {
+                Some('&') => {
+                    self.return_state = State::Rcdata;
+                    self.state = State::CharacterReference;
+                }
+                Some('<') => {
+                    self.token_start = self.char_start;
+                    self.state = State::RcdataLessThanSign;
+                }
+                Some('\0') => {
+                    self.parse_error(ParseErrorCode::UnexpectedNullCharacter);
+                    self.emit_char(REPLACEMENT_CHARACTER);
+                }
+                None => self.emit(Token::Eof),
+                Some(c) => self.emit_char(c),
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#rawtext-state
+            State::Rawtext => match self.consume() {
+                Some('<') => {
+                    self.token_start = self.char_start;
+                    self.state = State::RawtextLessThanSign;
+                }
+                Some('\0') => {
+                    self.parse_error(ParseErrorCode::UnexpectedNullCharacter);
+                    self.emit_char(REPLACEMENT_CHARACTER);
+                }
+                None => self.emit(Token::Eof),
+                Some(c) => self.emit_char(c),
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#script-data-state
+            State::ScriptData => match self.consume() {
+                Some('<') => {
+                    self.token_start = self.char_start;
+                    self.state = State::ScriptDataLessThanSign;
+                }
+                Some('\0') => {
+                    self.parse_error(ParseErrorCode::UnexpectedNullCharacter);
+                    self.emit_char(REPLACEMENT_CHARACTER);
+                }
+                None => self.emit(Token::Eof),
+                Some(c) => self.emit_char(c),
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#plaintext-state
+            State::Plaintext => match self.consume() {
+                Some('\0') => {
+                    self.parse_error(ParseErrorCode::UnexpectedNullCharacter);
+                    self.emit_char(REPLACEMENT_CHARACTER);
+                }
+                None => self.emit(Token::Eof),
+                Some(c) => self.emit_char(c),
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#tag-open-state
+            State::TagOpen => match self.consume() {
+                Some('!') => self.state = State::MarkupDeclarationOpen,
+                Some('/') => self.state = State::EndTagOpen,
+                Some(c) if c.is_ascii_alphabetic() => {
+                    self.create_tag(TagKind::Start);
+                    self.reconsume_in(State::TagName);
+                }
+                Some('?') => {
+                    self.parse_error(ParseErrorCode::UnexpectedQuestionMarkInsteadOfTagName);
+                    self.current_comment.clear();
+                    self.reconsume_in(State::BogusComment);
+                }
+                None => {
+                    self.parse_error(ParseErrorCode::EofBeforeTagName);
+                    self.emit_char('<');
+                    self.emit(Token::Eof);
+                }
+                Some(_) => {
+                    self.parse_error(ParseErrorCode::InvalidFirstCharacterOfTagName);
+                    self.emit_char('<');
+                    self.reconsume_in(State::Data);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#end-tag-open-state
+            State::EndTagOpen => match self.consume() {
+                Some(c) if c.is_ascii_alphabetic() => {
+                    self.create_tag(TagKind::End);
+                    self.reconsume_in(State::TagName);
+                }
+                Some('>') => {
+                    self.parse_error(ParseErrorCode::MissingEndTagName);
+                    self.state = State::Data;
+                }
+                None => {
+                    self.parse_error(ParseErrorCode::EofBeforeTagName);
+                    self.emit_str("</");
+                    self.emit(Token::Eof);
+                }
+                Some(_) => {
+                    self.parse_error(ParseErrorCode::InvalidFirstCharacterOfTagName);
+                    self.current_comment.clear();
+                    self.reconsume_in(State::BogusComment);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#tag-name-state
+            State::TagName => match self.consume() {
+                Some('\t' | '\n' | '\x0C' | ' ') => self.state = State::BeforeAttributeName,
+                Some('/') => self.state = State::SelfClosingStartTag,
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_current_tag();
+                }
+                Some('\0') => {
+                    self.parse_error(ParseErrorCode::UnexpectedNullCharacter);
+                    self.current_tag.name.push(REPLACEMENT_CHARACTER);
+                }
+                None => {
+                    self.parse_error(ParseErrorCode::EofInTag);
+                    self.emit(Token::Eof);
+                }
+                Some(c) => self.current_tag.name.push(c.to_ascii_lowercase()),
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#rcdata-less-than-sign-state
+            State::RcdataLessThanSign => match self.consume() {
+                Some('/') => {
+                    self.temporary_buffer.clear();
+                    self.state = State::RcdataEndTagOpen;
+                }
+                _ => {
+                    self.emit_char('<');
+                    self.reconsume_in(State::Rcdata);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#rcdata-end-tag-open-state
+            State::RcdataEndTagOpen => match self.consume() {
+                Some(c) if c.is_ascii_alphabetic() => {
+                    self.create_tag(TagKind::End);
+                    self.reconsume_in(State::RcdataEndTagName);
+                }
+                _ => {
+                    self.emit_str("</");
+                    self.reconsume_in(State::Rcdata);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#rcdata-end-tag-name-state
+            State::RcdataEndTagName => {
+                let c = self.consume();
+                self.end_tag_name_state(c, State::Rcdata);
+            }
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#rawtext-less-than-sign-state
+            State::RawtextLessThanSign => match self.consume() {
+                Some('/') => {
+                    self.temporary_buffer.clear();
+                    self.state = State::RawtextEndTagOpen;
+                }
+                _ => {
+                    self.emit_char('<');
+                    self.reconsume_in(State::Rawtext);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#rawtext-end-tag-open-state
+            State::RawtextEndTagOpen => match self.consume() {
+                Some(c) if c.is_ascii_alphabetic() => {
+                    self.create_tag(TagKind::End);
+                    self.reconsume_in(State::RawtextEndTagName);
+                }
+                _ => {
+                    self.emit_str("</");
+                    self.reconsume_in(State::Rawtext);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#rawtext-end-tag-name-state
+            State::RawtextEndTagName => {
+                let c = self.consume();
+                self.end_tag_name_state(c, State::Rawtext);
+            }
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#script-data-less-than-sign-state
+            State::ScriptDataLessThanSign => match self.consume() {
+                Some('/') => {
+                    self.temporary_buffer.clear();
+                    self.state = State::ScriptDataEndTagOpen;
+                }
+                Some('!') => {
+                    self.state = State::ScriptDataEscapeStart;
+                    self.emit_str("<!");
+                }
+                _ => {
+                    self.emit_char('<');
+                    self.reconsume_in(State::ScriptData);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#script-data-end-tag-open-state
+            State::ScriptDataEndTagOpen => match self.consume() {
+                Some(c) if c.is_ascii_alphabetic() => {
+                    self.create_tag(TagKind::End);
+                    self.reconsume_in(State::ScriptDataEndTagName);
+                }
+                _ => {
+                    self.emit_str("</");
+                    self.reconsume_in(State::ScriptData);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#script-data-end-tag-name-state
+            State::ScriptDataEndTagName => {
+                let c = self.consume();
+                self.end_tag_name_state(c, State::ScriptData);
+            }
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#script-data-escape-start-state
+            State::ScriptDataEscapeStart => match self.consume() {
+                Some('-') => {
+                    self.state = State::ScriptDataEscapeStartDash;
+                    self.emit_char('-');
+                }
+                _ => self.reconsume_in(State::ScriptData),
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#script-data-escape-start-dash-state
+            State::ScriptDataEscapeStartDash => match self.consume() {
+                Some('-') => {
+                    self.state = State::ScriptDataEscapedDashDash;
+                    self.emit_char('-');
+                }
+                _ => self.reconsume_in(State::ScriptData),
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#script-data-escaped-state
+            State::ScriptDataEscaped => match self.consume() {
+                Some('-') => {
+                    self.state = State::ScriptDataEscapedDash;
+                    self.emit_char('-');
+                }
+                Some('<') => {
+                    self.token_start = self.char_start;
+                    self.state = State::ScriptDataEscapedLessThanSign;
+                }
+                Some('\0') => {
+                    self.parse_error(ParseErrorCode::UnexpectedNullCharacter);
+                    self.emit_char(REPLACEMENT_CHARACTER);
+                }
+                None => {
+                    self.parse_error(ParseErrorCode::EofInScriptHtmlCommentLikeText);
+                    self.emit(Token::Eof);
+                }
+                Some(c) => self.emit_char(c),
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#script-data-escaped-dash-state
+            State::ScriptDataEscapedDash => match self.consume() {
+                Some('-') => {
+                    self.state = State::ScriptDataEscapedDashDash;
+                    self.emit_char('-');
+                }
+                Some('<') => {
+                    self.token_start = self.char_start;
+                    self.state = State::ScriptDataEscapedLessThanSign;
+                }
+                Some('\0') => {
+                    self.parse_error(ParseErrorCode::UnexpectedNullCharacter);
+                    self.state = State::ScriptDataEscaped;
+                    self.emit_char(REPLACEMENT_CHARACTER);
+                }
+                None => {
+                    self.parse_error(ParseErrorCode::EofInScriptHtmlCommentLikeText);
+                    self.emit(Token::Eof);
+                }
+                Some(c) => {
+                    self.state = State::ScriptDataEscaped;
+                    self.emit_char(c);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#script-data-escaped-dash-dash-state
+            State::ScriptDataEscapedDashDash => match self.consume() {
+                Some('-') => self.emit_char('-'),
+                Some('<') => {
+                    self.token_start = self.char_start;
+                    self.state = State::ScriptDataEscapedLessThanSign;
+                }
+                Some('>') => {
+                    self.state = State::ScriptData;
+                    self.emit_char('>');
+                }
+                Some('\0') => {
+                    self.parse_error(ParseErrorCode::UnexpectedNullCharacter);
+                    self.state = State::ScriptDataEscaped;
+                    self.emit_char(REPLACEMENT_CHARACTER);
+                }
+                None => {
+                    self.parse_error(ParseErrorCode::EofInScriptHtmlCommentLikeText);
+                    self.emit(Token::Eof);
+                }
+                Some(c) => {
+                    self.state = State::ScriptDataEscaped;
+                    self.emit_char(c);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#script-data-escaped-less-than-sign-state
+            State::ScriptDataEscapedLessThanSign => match self.consume() {
+                Some('/') => {
+                    self.temporary_buffer.clear();
+                    self.state = State::ScriptDataEscapedEndTagOpen;
+                }
+                Some(c) if c.is_ascii_alphabetic() => {
+                    self.temporary_buffer.clear();
+                    self.emit_char('<');
+                    self.reconsume_in(State::ScriptDataDoubleEscapeStart);
+                }
+                _ => {
+                    self.emit_char('<');
+                    self.reconsume_in(State::ScriptDataEscaped);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#script-data-escaped-end-tag-open-state
+            State::ScriptDataEscapedEndTagOpen => match self.consume() {
+                Some(c) if c.is_ascii_alphabetic() => {
+                    self.create_tag(TagKind::End);
+                    self.reconsume_in(State::ScriptDataEscapedEndTagName);
+                }
+                _ => {
+                    self.emit_str("</");
+                    self.reconsume_in(State::ScriptDataEscaped);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#script-data-escaped-end-tag-name-state
+            State::ScriptDataEscapedEndTagName => {
+                let c = self.consume();
+                self.end_tag_name_state(c, State::ScriptDataEscaped);
+            }
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#script-data-double-escape-start-state
+            State::ScriptDataDoubleEscapeStart => {
+                let c = self.consume();
+                self.script_data_double_escape_state(
+                    c,
+                    State::ScriptDataDoubleEscaped,
+                    State::ScriptDataEscaped,
+                );
+            }
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#script-data-double-escaped-state
+            State::ScriptDataDoubleEscaped => match self.consume() {
+                Some('-') => {
+                    self.state = State::ScriptDataDoubleEscapedDash;
+                    self.emit_char('-');
+                }
+                Some('<') => {
+                    self.state = State::ScriptDataDoubleEscapedLessThanSign;
+                    self.emit_char('<');
+                }
+                Some('\0') => {
+                    self.parse_error(ParseErrorCode::UnexpectedNullCharacter);
+                    self.emit_char(REPLACEMENT_CHARACTER);
+                }
+                None => {
+                    self.parse_error(ParseErrorCode::EofInScriptHtmlCommentLikeText);
+                    self.emit(Token::Eof);
+                }
+                Some(c) => self.emit_char(c),
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#script-data-double-escaped-dash-state
+            State::ScriptDataDoubleEscapedDash => match self.consume() {
+                Some('-') => {
+                    self.state = State::ScriptDataDoubleEscapedDashDash;
+                    self.emit_char('-');
+                }
+                Some('<') => {
+                    self.state = State::ScriptDataDoubleEscapedLessThanSign;
+                    self.emit_char('<');
+                }
+                Some('\0') => {
+                    self.parse_error(ParseErrorCode::UnexpectedNullCharacter);
+                    self.state = State::ScriptDataDoubleEscaped;
+                    self.emit_char(REPLACEMENT_CHARACTER);
+                }
+                None => {
+                    self.parse_error(ParseErrorCode::EofInScriptHtmlCommentLikeText);
+                    self.emit(Token::Eof);
+                }
+                Some(c) => {
+                    self.state = State::ScriptDataDoubleEscaped;
+                    self.emit_char(c);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#script-data-double-escaped-dash-dash-state
+            State::ScriptDataDoubleEscapedDashDash => match self.consume() {
+                Some('-') => self.emit_char('-'),
+                Some('<') => {
+                    self.state = State::ScriptDataDoubleEscapedLessThanSign;
+                    self.emit_char('<');
+                }
+                Some('>') => {
+                    self.state = State::ScriptData;
+                    self.emit_char('>');
+                }
+                Some('\0') => {
+                    self.parse_error(ParseErrorCode::UnexpectedNullCharacter);
+                    self.state = State::ScriptDataDoubleEscaped;
+                    self.emit_char(REPLACEMENT_CHARACTER);
+                }
+                None => {
+                    self.parse_error(ParseErrorCode::EofInScriptHtmlCommentLikeText);
+                    self.emit(Token::Eof);
+                }
+                Some(c) => {
+                    self.state = State::ScriptDataDoubleEscaped;
+                    self.emit_char(c);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#script-data-double-escaped-less-than-sign-state
+            State::ScriptDataDoubleEscapedLessThanSign => match self.consume() {
+                Some('/') => {
+                    self.temporary_buffer.clear();
+                    self.state = State::ScriptDataDoubleEscapeEnd;
+                    self.emit_char('/');
+                }
+                _ => self.reconsume_in(State::ScriptDataDoubleEscaped),
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#script-data-double-escape-end-state
+            State::ScriptDataDoubleEscapeEnd => {
+                let c = self.consume();
+                self.script_data_double_escape_state(
+                    c,
+                    State::ScriptDataEscaped,
+                    State::ScriptDataDoubleEscaped,
+                );
+            }
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#before-attribute-name-state
+            State::BeforeAttributeName => match self.consume() {
+                Some('\t' | '\n' | '\x0C' | ' ') => {}
+                Some('/' | '>') | None => self.reconsume_in(State::AfterAttributeName),
+                Some('=') => {
+                    self.parse_error(ParseErrorCode::UnexpectedEqualsSignBeforeAttributeName);
+                    self.start_attribute();
+                    self.current_attribute().name.push('=');
+                    self.state = State::AttributeName;
+                }
+                Some(_) => {
+                    self.start_attribute();
+                    self.reconsume_in(State::AttributeName);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#attribute-name-state
+            State::AttributeName => match self.consume() {
+                Some('\t' | '\n' | '\x0C' | ' ' | '/' | '>') | None => {
+                    self.leave_attribute_name();
+                    self.reconsume_in(State::AfterAttributeName);
+                }
+                Some('=') => {
+                    self.leave_attribute_name();
+                    self.state = State::BeforeAttributeValue;
+                }
+                Some('\0') => {
+                    self.parse_error(ParseErrorCode::UnexpectedNullCharacter);
+                    self.current_attribute().name.push(REPLACEMENT_CHARACTER);
+                }
+                Some(c) => {
+                    if matches!(c, '"' | '\'' | '<') {
+                        self.parse_error(ParseErrorCode::UnexpectedCharacterInAttributeName);
+                    }
+                    self.current_attribute().name.push(c.to_ascii_lowercase());
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#after-attribute-name-state
+            State::AfterAttributeName => match self.consume() {
+                Some('\t' | '\n' | '\x0C' | ' ') => {}
+                Some('/') => self.state = State::SelfClosingStartTag,
+                Some('=') => self.state = State::BeforeAttributeValue,
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_current_tag();
+                }
+                None => {
+                    self.parse_error(ParseErrorCode::EofInTag);
+                    self.emit(Token::Eof);
+                }
+                Some(_) => {
+                    self.start_attribute();
+                    self.reconsume_in(State::AttributeName);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#before-attribute-value-state
+            State::BeforeAttributeValue => match self.consume() {
+                Some('\t' | '\n' | '\x0C' | ' ') => {}
+                Some('"') => self.state = State::AttributeValueDoubleQuoted,
+                Some('\'') => self.state = State::AttributeValueSingleQuoted,
+                Some('>') => {
+                    self.parse_error(ParseErrorCode::MissingAttributeValue);
+                    self.state = State::Data;
+                    self.emit_current_tag();
+                }
+                _ => self.reconsume_in(State::AttributeValueUnquoted),
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#attribute-value-(double-quoted)-state
+            State::AttributeValueDoubleQuoted => match self.consume() {
+                Some('"') => self.state = State::AfterAttributeValueQuoted,
+                Some('&') => {
+                    self.return_state = State::AttributeValueDoubleQuoted;
+                    self.state = State::CharacterReference;
+                }
+                Some('\0') => {
+                    self.parse_error(ParseErrorCode::UnexpectedNullCharacter);
+                    self.current_attribute().value.push(REPLACEMENT_CHARACTER);
+                }
+                None => {
+                    self.parse_error(ParseErrorCode::EofInTag);
+                    self.emit(Token::Eof);
+                }
+                Some(c) => self.current_attribute().value.push(c),
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#attribute-value-(single-quoted)-state
+            State::AttributeValueSingleQuoted => match self.consume() {
+                Some('\'') => self.state = State::AfterAttributeValueQuoted,
+                Some('&') => {
+                    self.return_state = State::AttributeValueSingleQuoted;
+                    self.state = State::CharacterReference;
+                }
+                Some('\0') => {
+                    self.parse_error(ParseErrorCode::UnexpectedNullCharacter);
+                    self.current_attribute().value.push(REPLACEMENT_CHARACTER);
+                }
+                None => {
+                    self.parse_error(ParseErrorCode::EofInTag);
+                    self.emit(Token::Eof);
+                }
+                Some(c) => self.current_attribute().value.push(c),
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#attribute-value-(unquoted)-state
+            State::AttributeValueUnquoted => match self.consume() {
+                Some('\t' | '\n' | '\x0C' | ' ') => self.state = State::BeforeAttributeName,
+                Some('&') => {
+                    self.return_state = State::AttributeValueUnquoted;
+                    self.state = State::CharacterReference;
+                }
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_current_tag();
+                }
+                Some('\0') => {
+                    self.parse_error(ParseErrorCode::UnexpectedNullCharacter);
+                    self.current_attribute().value.push(REPLACEMENT_CHARACTER);
+                }
+                None => {
+                    self.parse_error(ParseErrorCode::EofInTag);
+                    self.emit(Token::Eof);
+                }
+                Some(c) => {
+                    if matches!(c, '"' | '\'' | '<' | '=' | '`') {
+                        self.parse_error(
+                            ParseErrorCode::UnexpectedCharacterInUnquotedAttributeValue,
+                        );
+                    }
+                    self.current_attribute().value.push(c);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#after-attribute-value-(quoted)-state
+            State::AfterAttributeValueQuoted => match self.consume() {
+                Some('\t' | '\n' | '\x0C' | ' ') => self.state = State::BeforeAttributeName,
+                Some('/') => self.state = State::SelfClosingStartTag,
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_current_tag();
+                }
+                None => {
+                    self.parse_error(ParseErrorCode::EofInTag);
+                    self.emit(Token::Eof);
+                }
+                Some(_) => {
+                    self.parse_error(ParseErrorCode::MissingWhitespaceBetweenAttributes);
+                    self.reconsume_in(State::BeforeAttributeName);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#self-closing-start-tag-state
+            State::SelfClosingStartTag => match self.consume() {
+                Some('>') => {
+                    self.current_tag.self_closing = true;
+                    self.state = State::Data;
+                    self.emit_current_tag();
+                }
+                None => {
+                    self.parse_error(ParseErrorCode::EofInTag);
+                    self.emit(Token::Eof);
+                }
+                Some(_) => {
+                    self.parse_error(ParseErrorCode::UnexpectedSolidusInTag);
+                    self.reconsume_in(State::BeforeAttributeName);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#bogus-comment-state
+            State::BogusComment => match self.consume() {
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_current_comment();
+                }
+                None => {
+                    self.emit_current_comment();
+                    self.emit(Token::Eof);
+                }
+                Some('\0') => {
+                    self.parse_error(ParseErrorCode::UnexpectedNullCharacter);
+                    self.current_comment.push(REPLACEMENT_CHARACTER);
+                }
+                Some(c) => self.current_comment.push(c),
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#markup-declaration-open-state
+            State::MarkupDeclarationOpen => {
+                if self.next_characters_are("--", false) {
+                    self.pos += 2;
+                    self.current_comment.clear();
+                    self.state = State::CommentStart;
+                } else if self.next_characters_are("DOCTYPE", true) {
+                    self.pos += 7;
+                    self.state = State::Doctype;
+                } else if self.next_characters_are("[CDATA[", false) {
+                    self.pos += 7;
+                    if self.allow_cdata {
+                        self.state = State::CdataSection;
+                    } else {
+                        self.parse_error(ParseErrorCode::CdataInHtmlContent);
+                        self.current_comment = "[CDATA[".to_string();
+                        self.state = State::BogusComment;
+                    }
+                } else {
+                    self.parse_error_at(ParseErrorCode::IncorrectlyOpenedComment, self.pos);
+                    self.current_comment.clear();
+                    self.state = State::BogusComment;
+                }
+            }
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#comment-start-state
+            State::CommentStart => match self.consume() {
+                Some('-') => self.state = State::CommentStartDash,
+                Some('>') => {
+                    self.parse_error(ParseErrorCode::AbruptClosingOfEmptyComment);
+                    self.state = State::Data;
+                    self.emit_current_comment();
+                }
+                _ => self.reconsume_in(State::Comment),
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#comment-start-dash-state
+            State::CommentStartDash => match self.consume() {
+                Some('-') => self.state = State::CommentEnd,
+                Some('>') => {
+                    self.parse_error(ParseErrorCode::AbruptClosingOfEmptyComment);
+                    self.state = State::Data;
+                    self.emit_current_comment();
+                }
+                None => {
+                    self.parse_error(ParseErrorCode::EofInComment);
+                    self.emit_current_comment();
+                    self.emit(Token::Eof);
+                }
+                Some(_) => {
+                    self.current_comment.push('-');
+                    self.reconsume_in(State::Comment);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#comment-state
+            State::Comment => match self.consume() {
+                Some('<') => {
+                    self.current_comment.push('<');
+                    self.state = State::CommentLessThanSign;
+                }
+                Some('-') => self.state = State::CommentEndDash,
+                Some('\0') => {
+                    self.parse_error(ParseErrorCode::UnexpectedNullCharacter);
+                    self.current_comment.push(REPLACEMENT_CHARACTER);
+                }
+                None => {
+                    self.parse_error(ParseErrorCode::EofInComment);
+                    self.emit_current_comment();
+                    self.emit(Token::Eof);
+                }
+                Some(c) => self.current_comment.push(c),
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#comment-less-than-sign-state
+            State::CommentLessThanSign => match self.consume() {
+                Some('!') => {
+                    self.current_comment.push('!');
+                    self.state = State::CommentLessThanSignBang;
+                }
+                Some('<') => self.current_comment.push('<'),
+                _ => self.reconsume_in(State::Comment),
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#comment-less-than-sign-bang-state
+            State::CommentLessThanSignBang => match self.consume() {
+                Some('-') => self.state = State::CommentLessThanSignBangDash,
+                _ => self.reconsume_in(State::Comment),
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#comment-less-than-sign-bang-dash-state
+            State::CommentLessThanSignBangDash => match self.consume() {
+                Some('-') => self.state = State::CommentLessThanSignBangDashDash,
+                _ => self.reconsume_in(State::CommentEndDash),
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#comment-less-than-sign-bang-dash-dash-state
+            State::CommentLessThanSignBangDashDash => match self.consume() {
+                Some('>') | None => self.reconsume_in(State::CommentEnd),
+                Some(_) => {
+                    self.parse_error(ParseErrorCode::NestedComment);
+                    self.reconsume_in(State::CommentEnd);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#comment-end-dash-state
+            State::CommentEndDash => match self.consume() {
+                Some('-') => self.state = State::CommentEnd,
+                None => {
+                    self.parse_error(ParseErrorCode::EofInComment);
+                    self.emit_current_comment();
+                    self.emit(Token::Eof);
+                }
+                Some(_) => {
+                    self.current_comment.push('-');
+                    self.reconsume_in(State::Comment);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#comment-end-state
+            State::CommentEnd => match self.consume() {
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_current_comment();
+                }
+                Some('!') => self.state = State::CommentEndBang,
+                Some('-') => self.current_comment.push('-'),
+                None => {
+                    self.parse_error(ParseErrorCode::EofInComment);
+                    self.emit_current_comment();
+                    self.emit(Token::Eof);
+                }
+                Some(_) => {
+                    self.current_comment.push_str("--");
+                    self.reconsume_in(State::Comment);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#comment-end-bang-state
+            State::CommentEndBang => match self.consume() {
+                Some('-') => {
+                    self.current_comment.push_str("--!");
+                    self.state = State::CommentEndDash;
+                }
+                Some('>') => {
+                    self.parse_error(ParseErrorCode::IncorrectlyClosedComment);
+                    self.state = State::Data;
+                    self.emit_current_comment();
+                }
+                None => {
+                    self.parse_error(ParseErrorCode::EofInComment);
+                    self.emit_current_comment();
+                    self.emit(Token::Eof);
+                }
+                Some(_) => {
+                    self.current_comment.push_str("--!");
+                    self.reconsume_in(State::Comment);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#doctype-state
+            State::Doctype => match self.consume() {
+                Some('\t' | '\n' | '\x0C' | ' ') => self.state = State::BeforeDoctypeName,
+                Some('>') => self.reconsume_in(State::BeforeDoctypeName),
+                None => {
+                    self.parse_error(ParseErrorCode::EofInDoctype);
+                    self.current_doctype = Doctype {
+                        force_quirks: true,
+                        ..Doctype::default()
+                    };
+                    self.emit_current_doctype();
+                    self.emit(Token::Eof);
+                }
+                Some(_) => {
+                    self.parse_error(ParseErrorCode::MissingWhitespaceBeforeDoctypeName);
+                    self.reconsume_in(State::BeforeDoctypeName);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#before-doctype-name-state
+            State::BeforeDoctypeName => match self.consume() {
+                Some('\t' | '\n' | '\x0C' | ' ') => {}
+                Some('\0') => {
+                    self.parse_error(ParseErrorCode::UnexpectedNullCharacter);
+                    self.current_doctype = Doctype {
+                        name: Some(REPLACEMENT_CHARACTER.to_string()),
+                        ..Doctype::default()
+                    };
+                    self.state = State::DoctypeName;
+                }
+                Some('>') => {
+                    self.parse_error(ParseErrorCode::MissingDoctypeName);
+                    self.current_doctype = Doctype {
+                        force_quirks: true,
+                        ..Doctype::default()
+                    };
+                    self.state = State::Data;
+                    self.emit_current_doctype();
+                }
+                None => {
+                    self.parse_error(ParseErrorCode::EofInDoctype);
+                    self.current_doctype = Doctype {
+                        force_quirks: true,
+                        ..Doctype::default()
+                    };
+                    self.emit_current_doctype();
+                    self.emit(Token::Eof);
+                }
+                Some(c) => {
+                    self.current_doctype = Doctype {
+                        name: Some(c.to_ascii_lowercase().to_string()),
+                        ..Doctype::default()
+                    };
+                    self.state = State::DoctypeName;
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#doctype-name-state
+            State::DoctypeName => match self.consume() {
+                Some('\t' | '\n' | '\x0C' | ' ') => self.state = State::AfterDoctypeName,
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_current_doctype();
+                }
+                Some('\0') => {
+                    self.parse_error(ParseErrorCode::UnexpectedNullCharacter);
+                    self.doctype_name().push(REPLACEMENT_CHARACTER);
+                }
+                None => {
+                    self.parse_error(ParseErrorCode::EofInDoctype);
+                    self.current_doctype.force_quirks = true;
+                    self.emit_current_doctype();
+                    self.emit(Token::Eof);
+                }
+                Some(c) => self.doctype_name().push(c.to_ascii_lowercase()),
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#after-doctype-name-state
+            State::AfterDoctypeName => match self.consume() {
+                Some('\t' | '\n' | '\x0C' | ' ') => {}
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_current_doctype();
+                }
+                None => {
+                    self.parse_error(ParseErrorCode::EofInDoctype);
+                    self.current_doctype.force_quirks = true;
+                    self.emit_current_doctype();
+                    self.emit(Token::Eof);
+                }
+                Some(c) => {
+                    // Look at the current input character together with the five that follow it.
+                    self.pos -= c.len_utf8();
+                    if self.next_characters_are("PUBLIC", true) {
+                        self.pos += 6;
+                        self.state = State::AfterDoctypePublicKeyword;
+                    } else if self.next_characters_are("SYSTEM", true) {
+                        self.pos += 6;
+                        self.state = State::AfterDoctypeSystemKeyword;
+                    } else {
+                        self.parse_error(ParseErrorCode::InvalidCharacterSequenceAfterDoctypeName);
+                        self.pos += c.len_utf8();
+                        self.current_doctype.force_quirks = true;
+                        self.reconsume_in(State::BogusDoctype);
+                    }
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#after-doctype-public-keyword-state
+            State::AfterDoctypePublicKeyword => match self.consume() {
+                Some('\t' | '\n' | '\x0C' | ' ') => {
+                    self.state = State::BeforeDoctypePublicIdentifier;
+                }
+                Some('"') => {
+                    self.parse_error(ParseErrorCode::MissingWhitespaceAfterDoctypePublicKeyword);
+                    self.current_doctype.public_identifier = Some(String::new());
+                    self.state = State::DoctypePublicIdentifierDoubleQuoted;
+                }
+                Some('\'') => {
+                    self.parse_error(ParseErrorCode::MissingWhitespaceAfterDoctypePublicKeyword);
+                    self.current_doctype.public_identifier = Some(String::new());
+                    self.state = State::DoctypePublicIdentifierSingleQuoted;
+                }
+                Some('>') => {
+                    self.parse_error(ParseErrorCode::MissingDoctypePublicIdentifier);
+                    self.current_doctype.force_quirks = true;
+                    self.state = State::Data;
+                    self.emit_current_doctype();
+                }
+                None => {
+                    self.parse_error(ParseErrorCode::EofInDoctype);
+                    self.current_doctype.force_quirks = true;
+                    self.emit_current_doctype();
+                    self.emit(Token::Eof);
+                }
+                Some(_) => {
+                    self.parse_error(ParseErrorCode::MissingQuoteBeforeDoctypePublicIdentifier);
+                    self.current_doctype.force_quirks = true;
+                    self.reconsume_in(State::BogusDoctype);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#before-doctype-public-identifier-state
+            State::BeforeDoctypePublicIdentifier => match self.consume() {
+                Some('\t' | '\n' | '\x0C' | ' ') => {}
+                Some('"') => {
+                    self.current_doctype.public_identifier = Some(String::new());
+                    self.state = State::DoctypePublicIdentifierDoubleQuoted;
+                }
+                Some('\'') => {
+                    self.current_doctype.public_identifier = Some(String::new());
+                    self.state = State::DoctypePublicIdentifierSingleQuoted;
+                }
+                Some('>') => {
+                    self.parse_error(ParseErrorCode::MissingDoctypePublicIdentifier);
+                    self.current_doctype.force_quirks = true;
+                    self.state = State::Data;
+                    self.emit_current_doctype();
+                }
+                None => {
+                    self.parse_error(ParseErrorCode::EofInDoctype);
+                    self.current_doctype.force_quirks = true;
+                    self.emit_current_doctype();
+                    self.emit(Token::Eof);
+                }
+                Some(_) => {
+                    self.parse_error(ParseErrorCode::MissingQuoteBeforeDoctypePublicIdentifier);
+                    self.current_doctype.force_quirks = true;
+                    self.reconsume_in(State::BogusDoctype);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#doctype-public-identifier-(double-quoted)-state
+            State::DoctypePublicIdentifierDoubleQuoted => {
+                let c = self.consume();
+                self.doctype_identifier_state(c, '"', State::AfterDoctypePublicIdentifier);
+            }
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#doctype-public-identifier-(single-quoted)-state
+            State::DoctypePublicIdentifierSingleQuoted => {
+                let c = self.consume();
+                self.doctype_identifier_state(c, '\'', State::AfterDoctypePublicIdentifier);
+            }
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#after-doctype-public-identifier-state
+            State::AfterDoctypePublicIdentifier => {
+                match self.consume() {
+                    Some('\t' | '\n' | '\x0C' | ' ') => {
+                        self.state = State::BetweenDoctypePublicAndSystemIdentifiers;
+                    }
+                    Some('>') => {
+                        self.state = State::Data;
+                        self.emit_current_doctype();
+                    }
+                    Some('"') => {
+                        self.parse_error(ParseErrorCode::MissingWhitespaceBetweenDoctypePublicAndSystemIdentifiers);
+                        self.current_doctype.system_identifier = Some(String::new());
+                        self.state = State::DoctypeSystemIdentifierDoubleQuoted;
+                    }
+                    Some('\'') => {
+                        self.parse_error(ParseErrorCode::MissingWhitespaceBetweenDoctypePublicAndSystemIdentifiers);
+                        self.current_doctype.system_identifier = Some(String::new());
+                        self.state = State::DoctypeSystemIdentifierSingleQuoted;
+                    }
+                    None => {
+                        self.parse_error(ParseErrorCode::EofInDoctype);
+                        self.current_doctype.force_quirks = true;
+                        self.emit_current_doctype();
+                        self.emit(Token::Eof);
+                    }
+                    Some(_) => {
+                        self.parse_error(ParseErrorCode::MissingQuoteBeforeDoctypeSystemIdentifier);
+                        self.current_doctype.force_quirks = true;
+                        self.reconsume_in(State::BogusDoctype);
+                    }
+                }
+            }
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#between-doctype-public-and-system-identifiers-state
+            State::BetweenDoctypePublicAndSystemIdentifiers => match self.consume() {
+                Some('\t' | '\n' | '\x0C' | ' ') => {}
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_current_doctype();
+                }
+                Some('"') => {
+                    self.current_doctype.system_identifier = Some(String::new());
+                    self.state = State::DoctypeSystemIdentifierDoubleQuoted;
+                }
+                Some('\'') => {
+                    self.current_doctype.system_identifier = Some(String::new());
+                    self.state = State::DoctypeSystemIdentifierSingleQuoted;
+                }
+                None => {
+                    self.parse_error(ParseErrorCode::EofInDoctype);
+                    self.current_doctype.force_quirks = true;
+                    self.emit_current_doctype();
+                    self.emit(Token::Eof);
+                }
+                Some(_) => {
+                    self.parse_error(ParseErrorCode::MissingQuoteBeforeDoctypeSystemIdentifier);
+                    self.current_doctype.force_quirks = true;
+                    self.reconsume_in(State::BogusDoctype);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#after-doctype-system-keyword-state
+            State::AfterDoctypeSystemKeyword => match self.consume() {
+                Some('\t' | '\n' | '\x0C' | ' ') => {
+                    self.state = State::BeforeDoctypeSystemIdentifier;
+                }
+                Some('"') => {
+                    self.parse_error(ParseErrorCode::MissingWhitespaceAfterDoctypeSystemKeyword);
+                    self.current_doctype.system_identifier = Some(String::new());
+                    self.state = State::DoctypeSystemIdentifierDoubleQuoted;
+                }
+                Some('\'') => {
+                    self.parse_error(ParseErrorCode::MissingWhitespaceAfterDoctypeSystemKeyword);
+                    self.current_doctype.system_identifier = Some(String::new());
+                    self.state = State::DoctypeSystemIdentifierSingleQuoted;
+                }
+                Some('>') => {
+                    self.parse_error(ParseErrorCode::MissingDoctypeSystemIdentifier);
+                    self.current_doctype.force_quirks = true;
+                    self.state = State::Data;
+                    self.emit_current_doctype();
+                }
+                None => {
+                    self.parse_error(ParseErrorCode::EofInDoctype);
+                    self.current_doctype.force_quirks = true;
+                    self.emit_current_doctype();
+                    self.emit(Token::Eof);
+                }
+                Some(_) => {
+                    self.parse_error(ParseErrorCode::MissingQuoteBeforeDoctypeSystemIdentifier);
+                    self.current_doctype.force_quirks = true;
+                    self.reconsume_in(State::BogusDoctype);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#before-doctype-system-identifier-state
+            State::BeforeDoctypeSystemIdentifier => match self.consume() {
+                Some('\t' | '\n' | '\x0C' | ' ') => {}
+                Some('"') => {
+                    self.current_doctype.system_identifier = Some(String::new());
+                    self.state = State::DoctypeSystemIdentifierDoubleQuoted;
+                }
+                Some('\'') => {
+                    self.current_doctype.system_identifier = Some(String::new());
+                    self.state = State::DoctypeSystemIdentifierSingleQuoted;
+                }
+                Some('>') => {
+                    self.parse_error(ParseErrorCode::MissingDoctypeSystemIdentifier);
+                    self.current_doctype.force_quirks = true;
+                    self.state = State::Data;
+                    self.emit_current_doctype();
+                }
+                None => {
+                    self.parse_error(ParseErrorCode::EofInDoctype);
+                    self.current_doctype.force_quirks = true;
+                    self.emit_current_doctype();
+                    self.emit(Token::Eof);
+                }
+                Some(_) => {
+                    self.parse_error(ParseErrorCode::MissingQuoteBeforeDoctypeSystemIdentifier);
+                    self.current_doctype.force_quirks = true;
+                    self.reconsume_in(State::BogusDoctype);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#doctype-system-identifier-(double-quoted)-state
+            State::DoctypeSystemIdentifierDoubleQuoted => {
+                let c = self.consume();
+                self.doctype_identifier_state(c, '"', State::AfterDoctypeSystemIdentifier);
+            }
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#doctype-system-identifier-(single-quoted)-state
+            State::DoctypeSystemIdentifierSingleQuoted => {
+                let c = self.consume();
+                self.doctype_identifier_state(c, '\'', State::AfterDoctypeSystemIdentifier);
+            }
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#after-doctype-system-identifier-state
+            State::AfterDoctypeSystemIdentifier => match self.consume() {
+                Some('\t' | '\n' | '\x0C' | ' ') => {}
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_current_doctype();
+                }
+                None => {
+                    self.parse_error(ParseErrorCode::EofInDoctype);
+                    self.current_doctype.force_quirks = true;
+                    self.emit_current_doctype();
+                    self.emit(Token::Eof);
+                }
+                Some(_) => {
+                    self.parse_error(
+                        ParseErrorCode::UnexpectedCharacterAfterDoctypeSystemIdentifier,
+                    );
+                    self.reconsume_in(State::BogusDoctype);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#bogus-doctype-state
+            State::BogusDoctype => match self.consume() {
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_current_doctype();
+                }
+                Some('\0') => {
+                    self.parse_error(ParseErrorCode::UnexpectedNullCharacter);
+                }
+                None => {
+                    self.emit_current_doctype();
+                    self.emit(Token::Eof);
+                }
+                Some(_) => {}
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#cdata-section-state
+            State::CdataSection => match self.consume() {
+                Some(']') => self.state = State::CdataSectionBracket,
+                None => {
+                    self.parse_error(ParseErrorCode::EofInCdata);
+                    self.emit(Token::Eof);
+                }
+                Some(c) => self.emit_char(c),
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#cdata-section-bracket-state
+            State::CdataSectionBracket => match self.consume() {
+                Some(']') => self.state = State::CdataSectionEnd,
+                _ => {
+                    self.emit_char(']');
+                    self.reconsume_in(State::CdataSection);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#cdata-section-end-state
+            State::CdataSectionEnd => match self.consume() {
+                Some(']') => self.emit_char(']'),
+                Some('>') => self.state = State::Data,
+                _ => {
+                    self.emit_str("]]");
+                    self.reconsume_in(State::CdataSection);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#character-reference-state
+            State::CharacterReference => {
+                self.character_reference_start = self.char_start;
+                self.temporary_buffer.clear();
+                self.temporary_buffer.push('&');
+                match self.consume() {
+                    Some(c) if c.is_ascii_alphanumeric() => {
+                        self.reconsume_in(State::NamedCharacterReference);
+                    }
+                    Some('#') => {
+                        self.temporary_buffer.push('#');
+                        self.state = State::NumericCharacterReference;
+                    }
+                    _ => {
+                        self.flush_code_points_consumed_as_a_character_reference();
+                        let return_state = self.return_state;
+                        self.reconsume_in(return_state);
+                    }
+                }
+            }
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#named-character-reference-state
+            State::NamedCharacterReference => {
+                self.unconsume();
+                match char_ref::longest_match(&self.input[self.pos..]) {
+                    Some((name, characters)) => {
+                        self.pos += name.len();
+                        self.temporary_buffer.push_str(name);
+                        // If the character reference was consumed as part of an attribute, and the last character matched is not a U+003B SEMICOLON character (;), and the next input character is either a U+003D EQUALS SIGN character (=) or an ASCII alphanumeric, then, for historical reasons, flush code points consumed as a character reference and switch to the return state.
+                        let historical = self.consumed_as_part_of_an_attribute()
+                            && !name.ends_with(';')
+                            && self.input[self.pos..]
+                                .starts_with(|c: char| c == '=' || c.is_ascii_alphanumeric());
+                        if !historical {
+                            if !name.ends_with(';') {
+                                self.parse_error_at(
+                                    ParseErrorCode::MissingSemicolonAfterCharacterReference,
+                                    self.pos,
+                                );
+                            }
+                            self.temporary_buffer = characters.to_string();
+                        }
+                        self.flush_code_points_consumed_as_a_character_reference();
+                        self.state = self.return_state;
+                    }
+                    None => {
+                        self.flush_code_points_consumed_as_a_character_reference();
+                        self.state = State::AmbiguousAmpersand;
+                    }
+                }
+            }
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#ambiguous-ampersand-state
+            State::AmbiguousAmpersand => match self.consume() {
+                Some(c) if c.is_ascii_alphanumeric() => {
+                    if self.consumed_as_part_of_an_attribute() {
+                        self.current_attribute().value.push(c);
+                    } else {
+                        self.emit_char(c);
+                    }
+                }
+                Some(';') => {
+                    self.parse_error(ParseErrorCode::UnknownNamedCharacterReference);
+                    let return_state = self.return_state;
+                    self.reconsume_in(return_state);
+                }
+                _ => {
+                    let return_state = self.return_state;
+                    self.reconsume_in(return_state);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#numeric-character-reference-state
+            State::NumericCharacterReference => {
+                self.character_reference_code = 0;
+                match self.consume() {
+                    Some(c @ ('x' | 'X')) => {
+                        self.temporary_buffer.push(c);
+                        self.state = State::HexadecimalCharacterReferenceStart;
+                    }
+                    _ => self.reconsume_in(State::DecimalCharacterReferenceStart),
+                }
+            }
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#hexadecimal-character-reference-start-state
+            State::HexadecimalCharacterReferenceStart => match self.consume() {
+                Some(c) if c.is_ascii_hexdigit() => {
+                    self.reconsume_in(State::HexadecimalCharacterReference);
+                }
+                _ => {
+                    self.parse_error(ParseErrorCode::AbsenceOfDigitsInNumericCharacterReference);
+                    self.flush_code_points_consumed_as_a_character_reference();
+                    let return_state = self.return_state;
+                    self.reconsume_in(return_state);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#decimal-character-reference-start-state
+            State::DecimalCharacterReferenceStart => match self.consume() {
+                Some(c) if c.is_ascii_digit() => {
+                    self.reconsume_in(State::DecimalCharacterReference);
+                }
+                _ => {
+                    self.parse_error(ParseErrorCode::AbsenceOfDigitsInNumericCharacterReference);
+                    self.flush_code_points_consumed_as_a_character_reference();
+                    let return_state = self.return_state;
+                    self.reconsume_in(return_state);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#hexadecimal-character-reference-state
+            State::HexadecimalCharacterReference => match self.consume() {
+                Some(c) if c.is_ascii_hexdigit() => {
+                    let digit = c.to_digit(16).unwrap();
+                    self.character_reference_code = self
+                        .character_reference_code
+                        .saturating_mul(16)
+                        .saturating_add(digit);
+                }
+                Some(';') => self.state = State::NumericCharacterReferenceEnd,
+                _ => {
+                    self.parse_error(ParseErrorCode::MissingSemicolonAfterCharacterReference);
+                    self.reconsume_in(State::NumericCharacterReferenceEnd);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#decimal-character-reference-state
+            State::DecimalCharacterReference => match self.consume() {
+                Some(c) if c.is_ascii_digit() => {
+                    let digit = c.to_digit(10).unwrap();
+                    self.character_reference_code = self
+                        .character_reference_code
+                        .saturating_mul(10)
+                        .saturating_add(digit);
+                }
+                Some(';') => self.state = State::NumericCharacterReferenceEnd,
+                _ => {
+                    self.parse_error(ParseErrorCode::MissingSemicolonAfterCharacterReference);
+                    self.reconsume_in(State::NumericCharacterReferenceEnd);
+                }
+            },
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#numeric-character-reference-end-state
+            State::NumericCharacterReferenceEnd => {
+                // A reconsumed character has not been handled by this state, so put it back.
+                let reconsume = mem::take(&mut self.reconsume);
+                let offset = if reconsume { self.char_start } else { self.pos };
+                let c = match self.character_reference_code {
+                    0 => {
+                        self.parse_error_at(ParseErrorCode::NullCharacterReference, offset);
+                        REPLACEMENT_CHARACTER
+                    }
+                    0x110000.. => {
+                        self.parse_error_at(
+                            ParseErrorCode::CharacterReferenceOutsideUnicodeRange,
+                            offset,
+                        );
+                        REPLACEMENT_CHARACTER
+                    }
+                    0xD800..=0xDFFF => {
+                        self.parse_error_at(ParseErrorCode::SurrogateCharacterReference, offset);
+                        REPLACEMENT_CHARACTER
+                    }
+                    code => {
+                        let c = char::from_u32(code).unwrap_or(REPLACEMENT_CHARACTER);
+                        if is_noncharacter(c) {
+                            self.parse_error_at(
+                                ParseErrorCode::NoncharacterCharacterReference,
+                                offset,
+                            );
+                        }
+                        // If the number is 0x0D, or a control that's not ASCII whitespace, then this is a control-character-reference parse error.
+                        if code == 0x0D
+                            || (c.is_control() && !matches!(c, '\t' | '\n' | '\x0C' | ' '))
+                        {
+                            self.parse_error_at(ParseErrorCode::ControlCharacterReference, offset);
+                        }
+                        char_ref::numeric_replacement(code).unwrap_or(c)
+                    }
+                };
+                self.temporary_buffer.clear();
+                self.temporary_buffer.push(c);
+                self.flush_code_points_consumed_as_a_character_reference();
+                self.state = self.return_state;
+                self.reconsume = reconsume;
+            }
+        }
+    }
+
+    fn doctype_name(&mut self) -> &mut String {
+        self.current_doctype.name.get_or_insert_with(String::new)
+    }
+
+    /// Shared handling for the quoted DOCTYPE public and system identifier states.
+    fn doctype_identifier_state(&mut self, c: Option<char>, quote: char, after: State) {
+        let public = after == State::AfterDoctypePublicIdentifier;
+        match c {
+            Some(c) if c == quote => self.state = after,
+            Some('\0') => {
+                self.parse_error(ParseErrorCode::UnexpectedNullCharacter);
+                self.doctype_identifier(public).push(REPLACEMENT_CHARACTER);
+            }
+            Some('>') => {
+                self.parse_error(if public {
+                    ParseErrorCode::AbruptDoctypePublicIdentifier
+                } else {
+                    ParseErrorCode::AbruptDoctypeSystemIdentifier
+                });
+                self.current_doctype.force_quirks = true;
+                self.state = State::Data;
+                self.emit_current_doctype();
+            }
+            None => {
+                self.parse_error(ParseErrorCode::EofInDoctype);
+                self.current_doctype.force_quirks = true;
+                self.emit_current_doctype();
+                self.emit(Token::Eof);
+            }
+            Some(c) => self.doctype_identifier(public).push(c),
+        }
+    }
+
+    fn doctype_identifier(&mut self, public: bool) -> &mut String {
+        let identifier = if public {
+            &mut self.current_doctype.public_identifier
+        } else {
+            &mut self.current_doctype.system_identifier
+        };
+        identifier.get_or_insert_with(String::new)
+    }
+}
+
+/// Noncharacters are code points in the range U+FDD0 to U+FDEF, and the last two code points of each plane.
+///
+/// https://infra.spec.whatwg.org/#noncharacter
+fn is_noncharacter(c: char) -> bool {
+    matches!(c, '\u{FDD0}'..='\u{FDEF}') || (c as u32 & 0xFFFE) == 0xFFFE
+}
+
+impl Iterator for Tokenizer {
+    type Item = Token;
+
+    /// Return the next token, ending after the end-of-file token has been returned.
+    fn next(&mut self) -> Option<Token> {
+        if self.eof_emitted && self.pending.is_empty() {
+            return None;
+        }
+        Some(self.next_token())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokenize(input: &str) -> Vec<Token> {
+        Tokenizer::new(input).collect()
+    }
+
+    fn characters(s: &str) -> Vec<Token> {
+        s.chars().map(Token::Character).collect()
+    }
+
+    fn start_tag(name: &str, attributes: &[(&str, &str)]) -> Token {
+        Token::StartTag(Tag {
+            name: name.to_string(),
+            self_closing: false,
+            attributes: attributes
+                .iter()
+                .map(|(name, value)| Attribute {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                })
+                .collect(),
+        })
+    }
+
+    fn end_tag(name: &str) -> Token {
+        Token::EndTag(Tag {
+            name: name.to_string(),
+            ..Tag::default()
+        })
+    }
+
+    #[test]
+    fn tags_and_text() {
+        let mut expected = vec![start_tag("p", &[])];
+        expected.extend(characters("Hi"));
+        expected.extend([end_tag("p"), Token::Eof]);
+        assert_eq!(tokenize("<P>Hi</p>"), expected);
+    }
+
+    #[test]
+    fn attributes() {
+        assert_eq!(
+            tokenize(r#"<a href="x" class='y z' id=w disabled>"#),
+            vec![
+                start_tag(
+                    "a",
+                    &[
+                        ("href", "x"),
+                        ("class", "y z"),
+                        ("id", "w"),
+                        ("disabled", "")
+                    ]
+                ),
+                Token::Eof
+            ]
+        );
+
+        assert_eq!(
+            tokenize(r#"<TD ColSpan=2 data-foo-bar="x" xml:lang=en _a=1 checked/>"#),
+            vec![
+                Token::StartTag(Tag {
+                    name: "td".to_string(),
+                    self_closing: true,
+                    attributes: [
+                        ("colspan", "2"),
+                        ("data-foo-bar", "x"),
+                        ("xml:lang", "en"),
+                        ("_a", "1"),
+                        ("checked", "")
+                    ]
+                    .iter()
+                    .map(|(name, value)| Attribute {
+                        name: name.to_string(),
+                        value: value.to_string(),
+                    })
+                    .collect(),
+                }),
+                Token::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicate_attributes() {
+        // The first attribute with a name wins.
+        assert_eq!(
+            tokenize(r#"<p id=a ID="b" class=c id>"#),
+            vec![start_tag("p", &[("id", "a"), ("class", "c")]), Token::Eof]
+        );
+        assert_eq!(
+            errors("<p a b a=1>"),
+            vec![(ParseErrorCode::DuplicateAttribute, 8)]
+        );
+    }
+
+    #[test]
+    fn self_closing() {
+        assert_eq!(
+            tokenize("<br/>"),
+            vec![
+                Token::StartTag(Tag {
+                    name: "br".to_string(),
+                    self_closing: true,
+                    attributes: Vec::new(),
+                }),
+                Token::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn doctype() {
+        assert_eq!(
+            tokenize("<!DOCTYPE html>"),
+            vec![
+                Token::Doctype(Doctype {
+                    name: Some("html".to_string()),
+                    ..Doctype::default()
+                }),
+                Token::Eof
+            ]
+        );
+        assert_eq!(
+            tokenize(r#"<!doctype html PUBLIC "-//W3C//DTD HTML 4.01//EN">"#),
+            vec![
+                Token::Doctype(Doctype {
+                    name: Some("html".to_string()),
+                    public_identifier: Some("-//W3C//DTD HTML 4.01//EN".to_string()),
+                    ..Doctype::default()
+                }),
+                Token::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(
+            tokenize("<!-- a -- b --><?php x ?>"),
+            vec![
+                Token::Comment(" a -- b ".to_string()),
+                Token::Comment("?php x ?".to_string()),
+                Token::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn character_references() {
+        let mut expected = characters("a&b<c \u{a0}'");
+        expected.push(Token::Eof);
+        assert_eq!(tokenize("a&amp;b&#60;c &nbsp;&#x27;"), expected);
+
+        assert_eq!(
+            tokenize(r#"<a title="&lt;&unknown;">"#),
+            vec![start_tag("a", &[("title", "<&unknown;")]), Token::Eof]
+        );
+
+        // Names from the full table, including ones that stand for two code points.
+        let mut expected = characters("\u{2192} \u{1d504} \u{2242}\u{338}");
+        expected.push(Token::Eof);
+        assert_eq!(tokenize("&rarr; &Afr; &NotEqualTilde;"), expected);
+
+        // Legacy references without a semicolon match the longest name in the table, except in
+        // attribute values when followed by an alphanumeric or "=".
+        let mut expected = characters("\u{a9} \u{ac}it; \u{2209} \u{a9}x");
+        expected.push(Token::Eof);
+        assert_eq!(tokenize("&copy &notit; &notin; &copyx"), expected);
+        assert_eq!(
+            tokenize("<a href='?a=1&copy=2&amp;b=3&copy' title=&copy;x>"),
+            vec![
+                start_tag(
+                    "a",
+                    &[("href", "?a=1&copy=2&b=3\u{a9}"), ("title", "\u{a9}x")]
+                ),
+                Token::Eof
+            ]
+        );
+
+        // Numeric references in the C1 control range are taken as windows-1252.
+        let mut expected = characters("\u{20ac}\u{2122}\u{81}\u{fffd}\u{fffd}");
+        expected.push(Token::Eof);
+        assert_eq!(tokenize("&#128;&#x99;&#x81;&#xD800;&#x110000;"), expected);
+    }
+
+    #[test]
+    fn rawtext_states() {
+        let mut tokenizer = Tokenizer::new("<style>a > b { }</style>");
+        assert_eq!(tokenizer.next_token(), start_tag("style", &[]));
+        tokenizer.set_state(State::Rawtext);
+        let mut expected = characters("a > b { }");
+        expected.extend([end_tag("style"), Token::Eof]);
+        assert_eq!(tokenizer.collect::<Vec<_>>(), expected);
+
+        let mut tokenizer = Tokenizer::new("<script>if (a<b) {}</scripts></script>");
+        assert_eq!(tokenizer.next_token(), start_tag("script", &[]));
+        tokenizer.set_state(State::ScriptData);
+        let mut expected = characters("if (a<b) {}</scripts>");
+        expected.extend([end_tag("script"), Token::Eof]);
+        assert_eq!(tokenizer.collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn malformed_markup() {
+        let mut expected = characters("a < b");
+        expected.push(Token::Eof);
+        assert_eq!(tokenize("a < b"), expected);
+
+        assert_eq!(tokenize("<div"), vec![Token::Eof]);
+        assert_eq!(tokenize("</>"), vec![Token::Eof]);
+    }
+
+    fn errors(input: &str) -> Vec<(ParseErrorCode, usize)> {
+        let mut tokenizer = Tokenizer::new(input);
+        while tokenizer.next_token() != Token::Eof {}
+        tokenizer
+            .take_errors()
+            .into_iter()
+            .map(|error| (error.code, error.offset))
+            .collect()
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(errors("<p>ok</p>"), vec![]);
+        assert_eq!(errors("<div"), vec![(ParseErrorCode::EofInTag, 4)]);
+        assert_eq!(
+            errors("a</div x>"),
+            vec![(ParseErrorCode::EndTagWithAttributes, 8)]
+        );
+        assert_eq!(
+            errors("x<!->"),
+            vec![(ParseErrorCode::IncorrectlyOpenedComment, 3)]
+        );
+        assert_eq!(
+            errors("&#0;\u{1}"),
+            vec![
+                (ParseErrorCode::NullCharacterReference, 4),
+                (ParseErrorCode::ControlCharacterInInputStream, 4)
+            ]
+        );
+    }
+
+    #[test]
+    fn newlines() {
+        let mut expected = characters("a\nb\n\nc");
+        expected.push(start_tag("p", &[("title", "\n")]));
+        expected.push(Token::Eof);
+        assert_eq!(tokenize("a\r\nb\r\rc<p\r\ntitle='\r\n'>"), expected);
+
+        // A line feed after a carriage return at the end of one part of the input still belongs to
+        // it.
+        let mut tokenizer = Tokenizer::streaming();
+        tokenizer.push_str("a\r");
+        assert_eq!(tokenizer.try_next_token(), None);
+        tokenizer.push_str("\nb");
+        tokenizer.end_input();
+        assert_eq!(tokenizer.collect::<Vec<_>>(), tokenize("a\nb"));
+    }
+
+    #[test]
+    fn streaming_input() {
+        let input = "<!DOCTYPE html PUBLIC \"x\"><p title='&notin;&notit'>\
+                     a &CounterClockwiseContourIntegral; b</p><!-- c -->";
+        // Add the input a character at a time, taking whatever tokens are ready after each one.
+        let mut tokenizer = Tokenizer::streaming();
+        let mut tokens = Vec::new();
+        for c in input.chars() {
+            tokenizer.push_str(c.encode_utf8(&mut [0; 4]));
+            while let Some(token) = tokenizer.try_next_token() {
+                tokens.push(token);
+            }
+        }
+        // Nothing is emitted while the end of the input might still change what it means.
+        assert!(!tokens.contains(&Token::Comment(" c ".to_string())));
+        tokenizer.end_input();
+        tokens.extend(tokenizer);
+        assert_eq!(tokens, tokenize(input));
+    }
+}
@@ -0,0 +1,35 @@
+/// The output of the tokenization step is a series of zero or more of the following tokens: DOCTYPE, start tag, end tag, comment, character, end-of-file.
+///
+/// https://html.spec.whatwg.org/multipage/parsing.html#tokenization
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Token {
+    Doctype(Doctype),
+    StartTag(Tag),
+    EndTag(Tag),
+    Comment(String),
+    Character(char),
+    Eof,
+}
+
+/// DOCTYPE tokens have a name, a public identifier, a system identifier, and a force-quirks flag. When a DOCTYPE token is created, its name, public identifier, and system identifier must be marked as missing (which is a distinct state from the empty string), and the force-quirks flag must be set to off (its other state is on).
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct Doctype {
+    pub name: Option<String>,
+    pub public_identifier: Option<String>,
+    pub system_identifier: Option<String>,
+    pub force_quirks: bool,
+}
+
+/// Start and end tag tokens have a tag name, a self-closing flag, and a list of attributes, each of which has a name and a value. When a start or end tag token is created, its self-closing flag must be unset (its other state is that it be set), and its attributes list must be empty.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct Tag {
+    pub name: String,
+    pub self_closing: bool,
+    pub attributes: Vec<Attribute>,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct Attribute {
+    pub name: String,
+    pub value: String,
+}
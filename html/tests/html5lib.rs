@@ -0,0 +1,467 @@
+// Runs conformance tests against the tokenizer and the tree construction stage: the files of
+// html5lib-tests that are vendored in `tests/html5lib-tests`, and the cases written for this crate
+// in the same formats in `tests/html5lib`.
+// https://github.com/html5lib/html5lib-tests
+
+use std::{collections::BTreeSet, env, fs, path::PathBuf, sync::Mutex};
+
+use html::{
+    dom::{Document, Namespace, NodeId, NodeType},
+    parser::Parser,
+    source::PositionTracker,
+    tokenizer::{State, Token, Tokenizer},
+};
+use serde_json::{json, Map, Value};
+
+/// The tests written for this crate, and the list of those that are known to fail.
+const CASES: Tests = Tests {
+    dir: concat!(env!("CARGO_MANIFEST_DIR"), "/tests/html5lib"),
+    expected_failures: concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/html5lib/expected-failures.txt"
+    ),
+};
+
+/// The vendored html5lib-tests, and the list of those that are known to fail. `HTML5LIB_TESTS`
+/// can be set to the path of another checkout to run it instead.
+const HTML5LIB_TESTS: Tests = Tests {
+    dir: concat!(env!("CARGO_MANIFEST_DIR"), "/tests/html5lib-tests"),
+    expected_failures: concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/html5lib/expected-failures-upstream.txt"
+    ),
+};
+
+/// Held while an expected failures file is rewritten, since each is updated by two suites.
+static EXPECTED_FAILURES_LOCK: Mutex<()> = Mutex::new(());
+
+struct Tests {
+    dir: &'static str,
+    expected_failures: &'static str,
+}
+
+/// The outcome of a single test: `Err` holds a description of what went wrong.
+type Outcome = Result<(), String>;
+
+#[test]
+fn tokenizer() {
+    run_suite(&HTML5LIB_TESTS, "tokenizer", "test", tokenizer_tests);
+}
+
+#[test]
+fn tree_construction() {
+    run_suite(
+        &HTML5LIB_TESTS,
+        "tree-construction",
+        "dat",
+        tree_construction_tests,
+    );
+}
+
+#[test]
+fn tokenizer_cases() {
+    run_suite(&CASES, "tokenizer", "test", tokenizer_tests);
+}
+
+#[test]
+fn tree_construction_cases() {
+    run_suite(&CASES, "tree-construction", "dat", tree_construction_tests);
+}
+
+/// Run every file with the extension `extension` in the directory `suite` of `tests`, printing how many of
+/// each file's tests pass. Tests in the expected failures file are allowed to fail, and the suite
+/// fails if any other test does, or if an expected failure starts passing so that the list can be
+/// kept up to date. When `UPDATE_EXPECTED_FAILURES` is set, the list is rewritten from the run
+/// instead.
+fn run_suite(tests: &Tests, suite: &str, extension: &str, run_file: fn(&str) -> Vec<Outcome>) {
+    let expected_failures = expected_failures(tests);
+    let dir = match env::var_os("HTML5LIB_TESTS") {
+        Some(dir) if tests.dir == HTML5LIB_TESTS.dir => PathBuf::from(dir),
+        _ => PathBuf::from(tests.dir),
+    };
+    let mut files: Vec<_> = fs::read_dir(dir.join(suite))
+        .expect("the tests directory exists")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == extension))
+        .collect();
+    files.sort();
+
+    let mut problems = Vec::new();
+    let mut failures = BTreeSet::new();
+    for path in files {
+        let file = format!("{suite}/{}", path.file_name().unwrap().to_string_lossy());
+        let outcomes = run_file(&fs::read_to_string(&path).unwrap());
+        let mut passed = 0;
+        for (index, outcome) in outcomes.iter().enumerate() {
+            // Tests are numbered from 1, in the order they appear in the file.
+            let test = format!("{file}:{}", index + 1);
+            if outcome.is_err() {
+                failures.insert(test.clone());
+            }
+            match (outcome, expected_failures.contains(&test)) {
+                (Ok(()), false) => passed += 1,
+                (Ok(()), true) => problems.push(format!("{test} passes unexpectedly")),
+                (Err(_), true) => {}
+                (Err(message), false) => problems.push(format!("{test} fails: {message}")),
+            }
+        }
+        println!("{file}: {passed} of {} tests pass", outcomes.len());
+    }
+    if env::var_os("UPDATE_EXPECTED_FAILURES").is_some() {
+        update_expected_failures(tests, suite, &failures);
+        return;
+    }
+    assert!(problems.is_empty(), "\n{}", problems.join("\n\n"));
+}
+
+/// The tests that are known to fail, as `<file>:<test number>`.
+fn expected_failures(tests: &Tests) -> BTreeSet<String> {
+    fs::read_to_string(tests.expected_failures)
+        .unwrap_or_default()
+        .lines()
+        .map(|line| line.split('#').next().unwrap().trim())
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Make `failures` the expected failures of `suite`. Comments and the other suite's tests are
+/// kept, as are the tests of `suite` that still fail; new failures are added at the end.
+fn update_expected_failures(tests: &Tests, suite: &str, failures: &BTreeSet<String>) {
+    let _lock = EXPECTED_FAILURES_LOCK.lock().unwrap();
+    let path = tests.expected_failures;
+    let mut listed = BTreeSet::new();
+    let mut contents = String::new();
+    for line in fs::read_to_string(path).unwrap_or_default().lines() {
+        let test = line.split('#').next().unwrap().trim();
+        if test.starts_with(&format!("{suite}/")) {
+            if !failures.contains(test) {
+                continue;
+            }
+            listed.insert(test.to_string());
+        }
+        contents.push_str(line);
+        contents.push('\n');
+    }
+    for test in failures.difference(&listed) {
+        contents.push_str(test);
+        contents.push('\n');
+    }
+    fs::write(path, contents).unwrap();
+}
+
+/// Compare an actual result with the expected one.
+fn check<T: PartialEq + std::fmt::Debug>(what: &str, actual: T, expected: T) -> Outcome {
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(format!(
+            "wrong {what}\n  expected: {expected:?}\n  actual:   {actual:?}"
+        ))
+    }
+}
+
+/// Run the tests in a tokenizer `.test` file. A test with several initial states passes if it
+/// passes in all of them.
+fn tokenizer_tests(source: &str) -> Vec<Outcome> {
+    let file: Value = serde_json::from_str(source).expect("tokenizer tests are JSON");
+    // Files without a `tests` list, like `xmlViolation.test`, are for tokenizers that produce
+    // XML, which this one doesn't.
+    let Some(tests) = file["tests"].as_array() else {
+        return Vec::new();
+    };
+    tests
+        .iter()
+        .map(|test| {
+            if test["doubleEscaped"] == true {
+                return Err("double-escaped tests aren't supported".to_string());
+            }
+            let states = match test["initialStates"].as_array() {
+                Some(states) => states.iter().map(|s| s.as_str().unwrap()).collect(),
+                None => vec!["Data state"],
+            };
+            states
+                .into_iter()
+                .try_for_each(|state| tokenizer_test(test, state))
+        })
+        .collect()
+}
+
+fn tokenizer_test(test: &Value, state: &str) -> Outcome {
+    let input = test["input"].as_str().unwrap();
+    let mut tokenizer = Tokenizer::new(input);
+    tokenizer.set_state(match state {
+        "Data state" => State::Data,
+        "PLAINTEXT state" => State::Plaintext,
+        "RCDATA state" => State::Rcdata,
+        "RAWTEXT state" => State::Rawtext,
+        "Script data state" => State::ScriptData,
+        "CDATA section state" => State::CdataSection,
+        _ => return Err(format!("unknown initial state {state}")),
+    });
+    if let Some(name) = test["lastStartTag"].as_str() {
+        tokenizer.set_last_start_tag(name);
+    }
+
+    let mut output: Vec<Value> = Vec::new();
+    for token in &mut tokenizer {
+        let token = match token {
+            Token::Doctype(doctype) => json!([
+                "DOCTYPE",
+                doctype.name,
+                doctype.public_identifier,
+                doctype.system_identifier,
+                !doctype.force_quirks
+            ]),
+            Token::StartTag(tag) => {
+                let attributes: Map<_, _> = tag
+                    .attributes
+                    .into_iter()
+                    .map(|attribute| (attribute.name, Value::from(attribute.value)))
+                    .collect();
+                if tag.self_closing {
+                    json!(["StartTag", tag.name, attributes, true])
+                } else {
+                    json!(["StartTag", tag.name, attributes])
+                }
+            }
+            Token::EndTag(tag) => json!(["EndTag", tag.name]),
+            Token::Comment(data) => json!(["Comment", data]),
+            Token::Character(c) => {
+                // Adjacent character tokens are merged.
+                if let Some(Value::Array(last)) = output.last_mut() {
+                    if last[0] == "Character" {
+                        let data =
+                            last[1].as_str().unwrap().to_string() + c.encode_utf8(&mut [0; 4]);
+                        last[1] = Value::from(data);
+                        continue;
+                    }
+                }
+                json!(["Character", c.to_string()])
+            }
+            Token::Eof => continue,
+        };
+        output.push(token);
+    }
+    let (expected_output, error_count) = expected_tokens(&test["output"]);
+    check(
+        &format!("tokens in the {state}"),
+        Value::from(output),
+        expected_output,
+    )?;
+
+    let errors = tokenizer.take_errors();
+    if test["errors"].is_null() {
+        // Older tests have a `ParseError` in the output for each parse error instead of a list of
+        // errors, so only the number of errors can be compared.
+        return check(
+            &format!("number of errors in the {state}"),
+            errors.len(),
+            error_count,
+        );
+    }
+    let mut positions = PositionTracker::default();
+    let errors: Vec<_> = errors
+        .into_iter()
+        .map(|error| {
+            let position = positions.position(input, error.offset);
+            (
+                error.code.as_str().to_string(),
+                position.line,
+                position.column,
+            )
+        })
+        .collect();
+    let expected_errors: Vec<_> = test["errors"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .map(|error| {
+            (
+                error["code"].as_str().unwrap().to_string(),
+                error["line"].as_u64().unwrap() as usize,
+                error["col"].as_u64().unwrap() as usize,
+            )
+        })
+        .collect();
+    check(&format!("errors in the {state}"), errors, expected_errors)
+}
+
+/// Split the expected output of a tokenizer test into its tokens and the number of `ParseError`s
+/// among them. Character tokens that were separated by a parse error are merged, the way the
+/// tokens that the tokenizer emits are.
+fn expected_tokens(output: &Value) -> (Value, usize) {
+    let mut tokens: Vec<Value> = Vec::new();
+    let mut errors = 0;
+    for token in output.as_array().expect("the output is a list of tokens") {
+        if token == "ParseError" {
+            errors += 1;
+            continue;
+        }
+        if let (Some(Value::Array(last)), Value::Array(token)) = (tokens.last_mut(), token) {
+            if last[0] == "Character" && token[0] == "Character" {
+                let data = last[1].as_str().unwrap().to_string() + token[1].as_str().unwrap();
+                last[1] = Value::from(data);
+                continue;
+            }
+        }
+        tokens.push(token.clone());
+    }
+    (Value::from(tokens), errors)
+}
+
+/// A test from a tree construction `.dat` file.
+#[derive(Default)]
+struct TreeTest {
+    data: String,
+    fragment_context: Option<String>,
+    scripting: bool,
+    document: String,
+}
+
+/// Split a `.dat` file into its tests. Each test is a list of sections, each starting with a
+/// line like `#data`. Only the sections that are needed are kept: the parse errors that the tree
+/// construction stage reports aren't compared.
+fn parse_tree_tests(source: &str) -> Vec<TreeTest> {
+    let mut tests = Vec::new();
+    let mut section = "";
+    let mut test = TreeTest::default();
+    // Lines are split at line feeds only, since the data can contain carriage returns.
+    for line in source.split('\n') {
+        match line {
+            "#data" => {
+                if !section.is_empty() {
+                    tests.push(test);
+                }
+                test = TreeTest::default();
+                section = line;
+            }
+            "#errors" | "#new-errors" | "#document-fragment" | "#document" => section = line,
+            "#script-on" => test.scripting = true,
+            "#script-off" => test.scripting = false,
+            _ => match section {
+                "#data" => {
+                    test.data.push_str(line);
+                    test.data.push('\n');
+                }
+                "#document-fragment" => test.fragment_context = Some(line.to_string()),
+                "#document" => {
+                    test.document.push_str(line);
+                    test.document.push('\n');
+                }
+                _ => {}
+            },
+        }
+    }
+    if !section.is_empty() {
+        tests.push(test);
+    }
+    for test in &mut tests {
+        // The data doesn't include the newline before the next section, and the document
+        // doesn't include the blank line between tests.
+        test.data.pop();
+        while test.document.ends_with("\n\n") {
+            test.document.pop();
+        }
+    }
+    tests
+}
+
+fn tree_construction_tests(source: &str) -> Vec<Outcome> {
+    parse_tree_tests(source)
+        .iter()
+        .map(tree_construction_test)
+        .collect()
+}
+
+fn tree_construction_test(test: &TreeTest) -> Outcome {
+    let mut parser = Parser::from_string(&test.data);
+    if test.scripting {
+        parser.enable_scripting();
+    }
+    let mut output = String::new();
+    match &test.fragment_context {
+        Some(context) => {
+            if context.contains(' ') {
+                return Err(format!("fragments in {context} aren't supported"));
+            }
+            parser.run_fragment(context);
+            // The nodes are the children of the html element that the fragment is parsed into.
+            let document = parser.document();
+            let root = document.document_element().unwrap();
+            dump(document, root, 0, &mut output);
+        }
+        None => {
+            let document = parser.run();
+            dump(&document, document.root(), 0, &mut output);
+        }
+    }
+    check("document", output.as_str(), test.document.as_str())
+}
+
+/// Write out the children of `node` in the format of the `#document` section: one line for each
+/// node and attribute, indented by depth.
+fn dump(document: &Document, node: NodeId, depth: usize, output: &mut String) {
+    let line = |output: &mut String, depth: usize, text: &str| {
+        output.push_str("| ");
+        output.push_str(&"  ".repeat(depth));
+        output.push_str(text);
+        output.push('\n');
+    };
+    for child in document.children(node) {
+        match document.node_type(child) {
+            NodeType::Element(elem) => {
+                let prefix = match elem.namespace {
+                    Namespace::Html => "",
+                    Namespace::Svg => "svg ",
+                    Namespace::MathMl => "math ",
+                };
+                line(output, depth, &format!("<{prefix}{}>", elem.tag_name));
+                // Attributes in the XLink, XML and XMLNS namespaces are written with a space
+                // between their prefix and local name.
+                let mut attributes: Vec<_> = elem
+                    .attributes
+                    .iter()
+                    .map(|(name, value)| {
+                        let name = match name.split_once(':') {
+                            Some((prefix @ ("xlink" | "xml" | "xmlns"), local))
+                                if elem.namespace != Namespace::Html =>
+                            {
+                                format!("{prefix} {local}")
+                            }
+                            _ => name.clone(),
+                        };
+                        (name, value)
+                    })
+                    .collect();
+                attributes.sort();
+                for (name, value) in attributes {
+                    line(output, depth + 1, &format!("{name}=\"{value}\""));
+                }
+                if let Some(contents) = document.template_contents(child) {
+                    line(output, depth + 1, "content");
+                    dump(document, contents, depth + 2, output);
+                }
+                dump(document, child, depth + 1, output);
+            }
+            NodeType::Text(data) => line(output, depth, &format!("\"{data}\"")),
+            NodeType::Comment(data) => line(output, depth, &format!("<!-- {data} -->")),
+            NodeType::Doctype(doctype) => {
+                let text = if doctype.public_id.is_empty() && doctype.system_id.is_empty() {
+                    format!("<!DOCTYPE {}>", doctype.name)
+                } else {
+                    format!(
+                        "<!DOCTYPE {} \"{}\" \"{}\">",
+                        doctype.name, doctype.public_id, doctype.system_id
+                    )
+                };
+                line(output, depth, &text);
+            }
+            NodeType::ProcessingInstruction(pi) => {
+                line(output, depth, &format!("<?{} {}>", pi.target, pi.data))
+            }
+            NodeType::Document(_) | NodeType::DocumentFragment => {}
+        }
+    }
+}
@@ -1,12 +1,15 @@
 use crate::args::Args;
 use crate::wgpu_util::get_gpu_instance;
-use benser::css::Parser as css_parser;
+use benser::css::{Parser as css_parser, Stylesheet, Warning};
 use benser::layout::{layout_tree, Dimensions};
 use benser::style::style_tree;
 use html::parser::Parser as html_parser;
 use image::ImageFormat;
+use log::warn;
 use std::fs;
 use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
 use wgpu::util::DeviceExt;
 
 #[repr(C)]
@@ -37,10 +40,25 @@ impl Vertex {
     }
 }
 
+/// Log the problems found while parsing a stylesheet from `path`. The CSS parser recovers from
+/// them by dropping the invalid parts, so rendering carries on without them.
+pub fn log_warnings(path: &Path, warnings: &[Warning]) {
+    for warning in warnings {
+        let start = warning.span.start;
+        warn!(
+            "{}:{}:{}: {}",
+            path.display(),
+            start.line,
+            start.column,
+            warning.message
+        );
+    }
+}
+
 pub async fn run(args: Args) {
     // Read input files
-    let css_source = fs::read_to_string(args.css_file).unwrap();
-    let html_source = fs::read_to_string(args.html_file).unwrap();
+    let css_source = fs::read_to_string(&args.css_file).unwrap();
+    let html_source = fs::read_to_string(&args.html_file).unwrap();
 
     // Create a virtual viewport
     let mut viewport = Dimensions::default();
@@ -56,9 +74,14 @@ pub async fn run(args: Args) {
     }
 
     // Parsing and rendering:
-    let root_node = html_parser::from_string(&html_source).run();
-    let stylesheet = css_parser::parse(&css_source);
-    let style_root = style_tree(&root_node, &stylesheet);
+    let document = Arc::new(html_parser::from_string(&html_source).run());
+    let mut stylesheet = css_parser::parse(&css_source);
+    // Rules from `<style>` elements come after the stylesheet file, so they win ties.
+    let style_elements = Stylesheet::from_style_elements(&document);
+    stylesheet.rules.extend(style_elements.rules);
+    log_warnings(&args.css_file, &stylesheet.warnings);
+    log_warnings(&args.html_file, &style_elements.warnings);
+    let style_root = style_tree(&document, &stylesheet);
     let layout_root = layout_tree(&style_root, viewport);
 
     // Create the output file:
@@ -1,4 +1,4 @@
-use benser::css::Parser as css_parser;
+use benser::css::{Parser as css_parser, Stylesheet};
 use benser::style::style_tree;
 use clap::Parser;
 use html::parser::Parser as html_parser;
@@ -16,9 +16,14 @@ fn main() {
     } else {
         let html_source = fs::read_to_string(&args.html_file).unwrap();
         let css_source = fs::read_to_string(&args.css_file).unwrap();
-        let root_node = html_parser::from_string(&html_source).run();
-        let stylesheet = css_parser::parse(&css_source);
-        let style_root = style_tree(&root_node, &stylesheet);
+        let document = Arc::new(html_parser::from_string(&html_source).run());
+        let mut stylesheet = css_parser::parse(&css_source);
+        // Rules from `<style>` elements come after the stylesheet file, so they win ties.
+        let style_elements = Stylesheet::from_style_elements(&document);
+        stylesheet.rules.extend(style_elements.rules);
+        file_output::log_warnings(&args.css_file, &stylesheet.warnings);
+        file_output::log_warnings(&args.html_file, &style_elements.warnings);
+        let style_root = style_tree(&document, &stylesheet);
 
         pollster::block_on(browser::run(Arc::new(style_root)));
     }